@@ -0,0 +1,1959 @@
+//! A `no_std`-compatible crate providing fixed-precision decimal features.
+//!
+//! Core arithmetic, parsing and formatting only rely on `core`, while anything needing `std`, ie. allocating parsers and serde
+//! support, is gated behind the default `std` feature.
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+use core::{fmt, num::ParseIntError, ops, str::FromStr};
+
+#[cfg(feature = "std")]
+use serde::{de, Deserialize, Serialize};
+use thiserror::Error;
+
+/// The largest precision that could be represented by this decimal type.
+pub const MAX_N: u8 = u64::MAX.ilog10() as u8; // 19
+
+/// A decimal error.
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum Error {
+    #[error("not a finite number")]
+    NotFinite,
+    #[error("negative number")]
+    Negative,
+    #[error("number too large to be represented")]
+    Overflow,
+    #[error("unsupported precision '{0}'")]
+    UnsupportedPrecision(u8),
+}
+
+/// A decimal parsing error.
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum ParseDecimalError {
+    #[error("invalid digit '{1}' found at position {0}")]
+    InvalidDigit(usize, char),
+    #[error("zero denominator")]
+    ZeroDenominator,
+    #[error(transparent)]
+    Int(#[from] ParseIntError),
+    #[error(transparent)]
+    Decimal(#[from] Error),
+}
+
+/// A rounding mode, applied to digits past the `N`th place of a decimal.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RoundingMode {
+    /// Round half away from zero, eg. `1.00025` to `1.0003` at precision `4`.
+    #[default]
+    HalfUp,
+    /// Round half to even, aka. banker's rounding, eg. `1.00025` to `1.0002` but `1.00035` to `1.0004` at precision `4`.
+    HalfEven,
+    /// Truncate extra digits, eg. `1.00029` to `1.0002` at precision `4`.
+    Truncate,
+}
+
+impl RoundingMode {
+    /// Whether a truncated value should be rounded up, given the parity of its last digit and how its dropped digits compare to one half.
+    fn rounds_up(self, odd: bool, half: core::cmp::Ordering) -> bool {
+        match self {
+            Self::HalfUp => half.is_ge(),
+            Self::HalfEven => half.is_gt() || (half.is_eq() && odd),
+            Self::Truncate => false,
+        }
+    }
+}
+
+/// A decimal handling fixed-precision with up to `N` places past the decimal.
+///
+/// Safety: `N` is statically checked at compile type and could never exceed `MAX_N`.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Decimal<const N: u8>(u64);
+
+impl<const N: u8> Decimal<N> {
+    /// The largest value that can be represented by this decimal type.
+    pub const MAX: Decimal<N> = Decimal(u64::MAX);
+    /// The smallest value that can be represented by this decimal type.
+    pub const MIN: Decimal<N> = Decimal(u64::MIN);
+
+    /// The largest integer value that can be represented by this decimal type.
+    ///
+    /// It is safe to consider that `Self::MAX_UINT < Self::MAX`.
+    ///
+    /// Caution: `MAX_UINT`.`MAX_FRAC` is typically greater than `MAX` and could not be represented by this decimal type.
+    pub const MAX_UINT: u64 = u64::MAX / Self::FRAC;
+    /// The largest fractional value that can be represented by this decimal type.
+    pub const MAX_FRAC: u64 = Self::FRAC - 1;
+
+    /// The constant denominator internally used to compute fractional values.
+    const FRAC: u64 = u64::pow(10, N as u32);
+
+    /// Create a new decimal from its integer / fractional parts.
+    ///
+    /// The fractional part is normalized as follows:
+    /// - if `frac < 10^N`, it is considered as a number of `10^-N` units, eg. `3.0014` for `(3, 14)` at precision `4`;
+    /// - otherwise, it is considered as all the digits past the decimal, rounded half up at the `N`th place if needed,
+    ///   eg. `3.1` for `(3, 10000)` and `3.1416` for `(3, 14159)` at precision `4`;
+    /// - if `N == 0`, it is just ignored.
+    ///
+    /// # Examples
+    /// ```
+    /// # use decimal::Decimal;
+    /// assert_eq!(Decimal::<0>::new(1, 2345).to_string(), "1");
+    /// assert_eq!(Decimal::<3>::new(5, 0).to_string(), "5");
+    /// assert_eq!(Decimal::<4>::new(3, 14).to_string(), "3.0014");
+    /// assert_eq!(Decimal::<4>::new(3, 1416).to_string(), "3.1416");
+    /// assert_eq!(Decimal::<4>::new(3, 14159).to_string(), "3.1416");
+    /// assert_eq!(Decimal::<3>::new(2, 10).to_string(), "2.01");
+    /// assert_eq!(Decimal::<3>::new(2, 1000).to_string(), "2.1");
+    /// assert_eq!(Decimal::<3>::new(2, 10000).to_string(), "2.1");
+    /// ```
+    ///
+    /// # Panics
+    /// This method panics if the decimal cannot be represented, ie. if `uint > Self::MAX_UINT`.
+    /// See `Self::try_new()` for a fallible version.
+    pub fn new(uint: u64, frac: u64) -> Self {
+        assert!(uint <= Self::MAX_UINT);
+
+        Self::try_new(uint, frac).expect("decimal cannot be represented")
+    }
+
+    /// Try to create a new decimal from its integer / fractional parts, normalized as with `Self::new()`.
+    ///
+    /// Should the decimal not be represented, eg. if `uint > Self::MAX_UINT`, an `Error::Overflow` is returned instead of panicking.
+    pub fn try_new(uint: u64, frac: u64) -> Result<Self, Error> {
+        Self::try_with_rounding(uint, frac, RoundingMode::default())
+    }
+
+    /// Create a new decimal from its integer / fractional parts, normalized as with `Self::new()` but rounded with `mode`.
+    ///
+    /// # Examples
+    /// ```
+    /// # use decimal::{Decimal, RoundingMode};
+    /// assert_eq!(Decimal::<4>::with_rounding(3, 14165, RoundingMode::HalfUp).to_string(), "3.1417");
+    /// assert_eq!(Decimal::<4>::with_rounding(3, 14165, RoundingMode::HalfEven).to_string(), "3.1416");
+    /// assert_eq!(Decimal::<4>::with_rounding(3, 14169, RoundingMode::Truncate).to_string(), "3.1416");
+    /// ```
+    ///
+    /// # Panics
+    /// This method panics if the decimal cannot be represented, ie. if `uint > Self::MAX_UINT`.
+    /// See `Self::try_with_rounding()` for a fallible version.
+    pub fn with_rounding(uint: u64, frac: u64, mode: RoundingMode) -> Self {
+        assert!(uint <= Self::MAX_UINT);
+
+        Self::try_with_rounding(uint, frac, mode).expect("decimal cannot be represented")
+    }
+
+    /// Try to create a new decimal from its integer / fractional parts, normalized as with `Self::new()` but rounded with `mode`.
+    ///
+    /// Should the decimal not be represented, eg. if `uint > Self::MAX_UINT`, an `Error::Overflow` is returned instead of panicking.
+    pub fn try_with_rounding(uint: u64, mut frac: u64, mode: RoundingMode) -> Result<Self, Error> {
+        if N == 0 {
+            frac = 0;
+        } else if frac == Self::FRAC {
+            frac /= 10;
+        } else if frac > Self::FRAC {
+            let n = u64::pow(10, 1 + frac.ilog10() - N as u32);
+            let (trunc, rem) = (frac / n, frac % n);
+
+            frac = trunc + u64::from(mode.rounds_up(trunc % 2 == 1, rem.cmp(&(n / 2))));
+            // could be rounded up to `Self::FRAC`
+        };
+
+        if uint > Self::MAX_UINT {
+            return Err(Error::Overflow);
+        }
+
+        (uint * Self::FRAC).checked_add(frac).map(Self).ok_or(Error::Overflow)
+    }
+
+    /// Create a new decimal from a raw value.
+    pub const fn raw(value: u64) -> Self {
+        Self(value)
+    }
+
+    /// Turn a decimal into a raw value.
+    pub const fn into_raw(self) -> u64 {
+        self.0
+    }
+
+    /// Create a new decimal from a number of minor units, eg. cents, without formatting and parsing it.
+    ///
+    /// Caution: the minor-unit scale of the caller must equal `N`, eg. `1234` cents are `12.34` at precision `2` but `0.1234`
+    /// at precision `4`. No rescaling is ever attempted.
+    ///
+    /// # Examples
+    /// ```
+    /// # use decimal::Decimal;
+    /// assert_eq!(Decimal::<2>::from_minor_units(1234).to_string(), "12.34");
+    /// ```
+    #[inline]
+    pub const fn from_minor_units(units: u64) -> Self {
+        Self(units)
+    }
+
+    /// Get the number of minor units of this decimal, ie. of `10^-N` units, eg. `1234` cents for `12.34` at precision `2`.
+    #[inline]
+    pub const fn to_minor_units(&self) -> u64 {
+        self.0
+    }
+
+    /// Compare this decimal to zero, ie. its sign.
+    ///
+    /// An unsigned decimal is never less than zero, unlike a `SignedDecimal`.
+    #[inline]
+    pub const fn cmp_zero(&self) -> core::cmp::Ordering {
+        if self.0 == 0 {
+            core::cmp::Ordering::Equal
+        } else {
+            core::cmp::Ordering::Greater
+        }
+    }
+
+    /// Whether this decimal is strictly positive.
+    #[inline]
+    pub const fn is_positive(&self) -> bool {
+        self.0 > 0
+    }
+
+    /// Split this decimal into its integer / fractional parts.
+    #[inline]
+    pub fn split(&self) -> (u64, u64) {
+        (self.0 / Self::FRAC, self.0 % Self::FRAC)
+    }
+
+    /// Get the fractional part of this decimal, eg. `0.1416` for `3.1416`.
+    #[inline]
+    pub fn fract(&self) -> Self {
+        Self(self.split().1)
+    }
+
+    /// Get the integer part of this decimal, eg. `3` for `3.1416`.
+    #[inline]
+    pub fn trunc(&self) -> Self {
+        Self(self.split().0 * Self::FRAC)
+    }
+
+    /// Checked decimal addition, returning `None` if overflow occurred.
+    #[inline]
+    pub fn checked_add(self, other: Self) -> Option<Self> {
+        self.0.checked_add(other.0).map(Self)
+    }
+
+    /// Checked decimal subtraction, returning `None` if overflow occurred.
+    #[inline]
+    pub fn checked_sub(self, other: Self) -> Option<Self> {
+        self.0.checked_sub(other.0).map(Self)
+    }
+
+    /// Checked scalar multiplication, returning `None` if overflow occurred.
+    #[inline]
+    pub fn checked_mul(self, scalar: u64) -> Option<Self> {
+        self.0.checked_mul(scalar).map(Self)
+    }
+
+    /// Checked decimal multiplication, eg. `quantity × price`, returning `None` if overflow occurred.
+    ///
+    /// The result is rounded half up at the `N`th place past the decimal.
+    pub fn checked_mul_decimal(self, other: Self) -> Option<Self> {
+        let (n, d) = (self.0 as u128 * other.0 as u128, Self::FRAC as u128);
+        let raw = n / d + u128::from(n % d * 2 >= d);
+
+        u64::try_from(raw).ok().map(Self)
+    }
+
+    /// Saturating decimal addition, returning `Self::MAX` if overflow occurred.
+    #[inline]
+    pub fn saturating_add(self, other: Self) -> Self {
+        Self(self.0.saturating_add(other.0))
+    }
+
+    /// Saturating decimal subtraction, returning `Self::MIN` if overflow occurred.
+    #[inline]
+    pub fn saturating_sub(self, other: Self) -> Self {
+        Self(self.0.saturating_sub(other.0))
+    }
+
+    /// Multiply this decimal by a `numerator / denominator` ratio, eg. to apply a fee percentage.
+    ///
+    /// The result is rounded half up at the `N`th place past the decimal.
+    ///
+    /// # Panics
+    /// This method panics if `denominator` is `0` or if the result cannot be represented.
+    pub fn mul_ratio(self, numerator: u64, denominator: u64) -> Self {
+        assert!(denominator > 0);
+
+        let (n, d) = (self.0 as u128 * numerator as u128, denominator as u128);
+        let raw = n / d + u128::from(n % d * 2 >= d);
+
+        assert!(raw <= u64::MAX as u128);
+
+        Self(raw as u64)
+    }
+
+    /// Compute the midpoint of this decimal and `other`, ie. `(self + other) / 2`, without overflowing.
+    ///
+    /// Should the midpoint not be representable, the last fractional digit is rounded half up.
+    pub fn midpoint(self, other: Self) -> Self {
+        let (lo, hi) = if self <= other { (self.0, other.0) } else { (other.0, self.0) };
+        let diff = hi - lo;
+
+        Self(lo + diff / 2 + diff % 2)
+    }
+
+    /// Find the tier of this decimal among sorted tier `boundaries`, ie. the number of boundaries lower than or equal to it.
+    ///
+    /// Each boundary is the inclusive lower bound of its tier: a decimal below the first boundary falls in tier `0`, while a decimal
+    /// equal to the `i`th boundary falls in tier `i + 1`. The result is meaningless should `boundaries` not be sorted.
+    ///
+    /// # Examples
+    /// ```
+    /// # use decimal::Decimal;
+    /// let boundaries = [Decimal::<4>::from(100), Decimal::from(1000)];
+    /// assert_eq!(Decimal::from(50).tier(&boundaries), 0);
+    /// assert_eq!(Decimal::from(100).tier(&boundaries), 1);
+    /// assert_eq!(Decimal::from(5000).tier(&boundaries), 2);
+    /// ```
+    #[inline]
+    pub fn tier(&self, boundaries: &[Self]) -> usize {
+        boundaries.partition_point(|boundary| boundary <= self)
+    }
+
+    /// Parse a decimal from a string as with the strict `FromStr` implementation, extra digits past the `N`th place being rounded with `mode`.
+    ///
+    /// # Examples
+    /// ```
+    /// # use decimal::{Decimal, RoundingMode};
+    /// assert_eq!(Decimal::<4>::from_str_rounded("1.00025", RoundingMode::HalfUp).unwrap().to_string(), "1.0003");
+    /// assert_eq!(Decimal::<4>::from_str_rounded("1.00025", RoundingMode::HalfEven).unwrap().to_string(), "1.0002");
+    /// ```
+    pub fn from_str_rounded(s: &str, mode: RoundingMode) -> Result<Self, ParseDecimalError> {
+        // an optional leading `+` sign, as with Rust integers
+        let (offset, s) = s.strip_prefix('+').map_or((0, s), |s| (1, s));
+
+        // `_` digit separators must stand between two digits of the integer part
+        let bytes = s.as_bytes();
+        let int_len = s.find('.').unwrap_or(s.len());
+        let is_separator = |i: usize| i > 0 && i + 1 < int_len && bytes[i - 1].is_ascii_digit() && bytes[i + 1].is_ascii_digit();
+
+        // only accept ASCII digits, digit separators and a single decimal point
+        let mut chars = s
+            .chars()
+            .enumerate()
+            .filter(|&(i, c)| !(c.is_ascii_digit() || c == '_' && is_separator(i)));
+        if let Some((i, c)) = chars.next().filter(|(_, c)| *c != '.').or_else(|| chars.next()) {
+            return Err(ParseDecimalError::InvalidDigit(offset + i, c));
+        }
+
+        let (u, f) = s.split_once('.').map_or((s, None), |(u, f)| (u, Some(f)));
+
+        // an empty integer part is zero, eg. `.5`, unless the fractional part is empty as well
+        let u = if u.is_empty() && f.is_some_and(|f| !f.is_empty()) { "0" } else { u };
+        let mut digits = [0; 21];
+        let uint: u64 = skip_separators(u, &mut digits).parse()?;
+
+        let (uint, frac) = match f.map(|f| f.trim_end_matches('0')) {
+            None | Some("") => (uint, 0),
+            Some(f) if f.len() < N as usize => (uint, f.parse::<u64>()? * u64::pow(10, N as u32 - f.len() as u32)),
+            Some(f) if f.len() > N as usize => {
+                let (f, extra) = f.split_at(N as usize);
+                let trunc = Self::try_new(uint, if f.is_empty() { 0 } else { f.parse()? })?;
+
+                // extra digits have no trailing zeroes, so that only a single `5` is exactly one half
+                let half = match extra.as_bytes()[0].cmp(&b'5') {
+                    core::cmp::Ordering::Equal if extra.len() > 1 => core::cmp::Ordering::Greater,
+                    half => half,
+                };
+
+                // as with `Self::new()`, the fractional part is just ignored if `N == 0`
+                return match N > 0 && mode.rounds_up(trunc.0 % 2 == 1, half) {
+                    true => Ok(trunc.checked_add(Self(1)).ok_or(Error::Overflow)?),
+                    false => Ok(trunc),
+                };
+            }
+            Some(f) => (uint, f.parse()?),
+        };
+
+        Ok(Self::try_new(uint, frac)?)
+    }
+
+    /// Parse a decimal from a `numerator/denominator` fraction string, eg. `1/3`, rounded half up at the `N`th place past the decimal.
+    ///
+    /// Both numerator and denominator are integers, a plain integer being parsed as a fraction over `1`.
+    ///
+    /// # Examples
+    /// ```
+    /// # use decimal::Decimal;
+    /// assert_eq!(Decimal::<4>::from_fraction_str("1/3").unwrap().to_string(), "0.3333");
+    /// assert_eq!(Decimal::<4>::from_fraction_str("2/3").unwrap().to_string(), "0.6667");
+    /// ```
+    pub fn from_fraction_str(s: &str) -> Result<Self, ParseDecimalError> {
+        // only accept ASCII digits and a single fraction bar
+        let mut chars = s.chars().enumerate().filter(|(_, c)| !c.is_ascii_digit());
+        if let Some((i, c)) = chars.next().filter(|(_, c)| *c != '/').or_else(|| chars.next()) {
+            return Err(ParseDecimalError::InvalidDigit(i, c));
+        }
+
+        let (numerator, denominator) = s.split_once('/').unwrap_or((s, "1"));
+        let (numerator, denominator) = (numerator.parse::<u64>()?, denominator.parse::<u64>()?);
+
+        if denominator == 0 {
+            return Err(ParseDecimalError::ZeroDenominator);
+        }
+
+        let (n, d) = (numerator as u128 * Self::FRAC as u128, denominator as u128);
+        let raw = n / d + u128::from(n % d * 2 >= d);
+
+        Ok(u64::try_from(raw).map(Self).map_err(|_| Error::Overflow)?)
+    }
+
+    /// Parse a decimal from a human-authored string, eg. from a config file.
+    ///
+    /// Unlike the strict `FromStr` implementation, leading/trailing whitespaces are ignored and `_` can also be used as a digit
+    /// separator in the fractional part, eg. `1_000.000_1`. An underscore must stand between two digits: leading, trailing or
+    /// doubled underscores are rejected.
+    #[cfg(feature = "std")]
+    pub fn from_str_lenient(s: &str) -> Result<Self, <Self as FromStr>::Err> {
+        let s = s.trim();
+        let bytes = s.as_bytes();
+
+        let is_separator = |i: usize| i > 0 && i + 1 < bytes.len() && bytes[i - 1].is_ascii_digit() && bytes[i + 1].is_ascii_digit();
+
+        if (0..bytes.len()).all(|i| bytes[i] != b'_' || is_separator(i)) {
+            s.replace('_', "").parse()
+        } else {
+            s.parse() // misplaced underscores are rejected by strict parsing
+        }
+    }
+}
+
+/// Skip the `_` digit separators of an integer string into `buf`, without allocating.
+///
+/// Leading zeros are skipped as well, so that an integer past `u64::MAX`, ie. with more than 20 significant digits, still overflows
+/// once truncated to the 21 bytes of `buf`.
+fn skip_separators<'a>(s: &'a str, buf: &'a mut [u8; 21]) -> &'a str {
+    if !s.contains('_') {
+        return s;
+    }
+
+    let digits = s.bytes().filter(|&b| b != b'_').skip_while(|&b| b == b'0');
+    let len = buf.iter_mut().zip(digits).map(|(slot, digit)| *slot = digit).count();
+
+    match len {
+        0 => "0",
+        _ => core::str::from_utf8(&buf[..len]).expect("ASCII digits"),
+    }
+}
+
+impl<const N: u8> Default for Decimal<N> {
+    #[inline]
+    fn default() -> Self {
+        Self::new(0, 0)
+    }
+}
+
+impl<const N: u8> From<u64> for Decimal<N> {
+    #[inline]
+    fn from(uint: u64) -> Self {
+        Self::new(uint, 0)
+    }
+}
+
+impl<const N: u8> TryFrom<(u64, u64)> for Decimal<N> {
+    type Error = Error;
+
+    /// Create a new decimal from its integer / fractional parts, normalized as with `Self::new()`.
+    #[inline]
+    fn try_from((uint, frac): (u64, u64)) -> Result<Self, Self::Error> {
+        Self::try_new(uint, frac)
+    }
+}
+
+impl<const N: u8> TryFrom<f64> for Decimal<N> {
+    type Error = Error;
+
+    /// Create a new decimal from a float, rounding its fractional part half up at the `N`th place past the decimal.
+    ///
+    /// As floats cannot represent most decimals exactly, eg. `0.1 + 0.2 == 0.30000000000000004`,
+    /// such noise is typically rounded away at precision `N`.
+    fn try_from(value: f64) -> Result<Self, Self::Error> {
+        if !value.is_finite() {
+            return Err(Error::NotFinite);
+        }
+        if value < 0.0 {
+            return Err(Error::Negative);
+        }
+        if value >= u64::MAX as f64 {
+            return Err(Error::Overflow);
+        }
+
+        // not relying on `f64::trunc()`, `f64::fract()` or `f64::round()`, which are only provided by `std`
+        let uint = value as u64;
+        if uint > Self::MAX_UINT {
+            return Err(Error::Overflow);
+        }
+
+        let fract = (value - uint as f64) * Self::FRAC as f64; // exact difference, as `uint` is `value` truncated
+        let frac = fract as u64 + u64::from(fract - (fract as u64) as f64 >= 0.5); // could be rounded up to `Self::FRAC`
+
+        (uint * Self::FRAC).checked_add(frac).map(Self).ok_or(Error::Overflow)
+    }
+}
+
+impl<const N: u8> fmt::Debug for Decimal<N> {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self, f)
+    }
+}
+
+impl<const N: u8> fmt::Display for Decimal<N> {
+    /// Format this decimal with trimmed trailing zeros, or all `N` places past the decimal in alternate mode, eg. `{:#}`.
+    ///
+    /// A precision flag, eg. `{:.2}`, renders exactly that many places past the decimal, rounded half up if needed.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (uint, frac) = self.split();
+
+        if let Some(precision) = f.precision() {
+            match precision.checked_sub(N as usize) {
+                Some(_) if precision == 0 => write!(f, "{uint}"),
+                Some(zeros) if N == 0 => write!(f, "{uint}.{:0>zeros$}", ""),
+                Some(zeros) => write!(f, "{uint}.{frac:0>width$}{:0>zeros$}", "", width = N as usize),
+                None => {
+                    let (value, d) = (self.0 as u128, 10u128.pow(N as u32 - precision as u32));
+                    let rounded = value / d + u128::from(value % d * 2 >= d);
+                    let (uint, frac) = (rounded / 10u128.pow(precision as u32), rounded % 10u128.pow(precision as u32));
+
+                    match precision {
+                        0 => write!(f, "{uint}"),
+                        _ => write!(f, "{uint}.{frac:0>precision$}"),
+                    }
+                }
+            }
+        } else if f.alternate() {
+            write!(f, "{uint}.{frac:0>width$}", width = N as usize)
+        } else if frac > 0 {
+            let (frac, width) = trim_zeros(frac as u128, N);
+            write!(f, "{uint}.{frac:0>width$}")
+        } else {
+            write!(f, "{uint}")
+        }
+    }
+}
+
+/// Trim the trailing zeros of a non-zero fractional value of `n` places, returning the trimmed value and its remaining places.
+#[inline]
+fn trim_zeros(mut frac: u128, mut n: u8) -> (u128, usize) {
+    while frac % 10 == 0 {
+        (frac, n) = (frac / 10, n - 1);
+    }
+
+    (frac, n as usize)
+}
+
+impl<const N: u8> FromStr for Decimal<N> {
+    type Err = ParseDecimalError;
+
+    #[inline]
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::from_str_rounded(s, RoundingMode::default())
+    }
+}
+
+impl<const N: u8> PartialEq<str> for Decimal<N> {
+    /// Compare this decimal against a string, eg. for quick assertions or filters.
+    ///
+    /// Caution: the string is parsed, without allocating, at each comparison. Any string that is not a valid decimal is not equal.
+    #[inline]
+    fn eq(&self, other: &str) -> bool {
+        other.parse::<Self>().is_ok_and(|other| *self == other)
+    }
+}
+
+impl<const N: u8> PartialEq<&str> for Decimal<N> {
+    #[inline]
+    fn eq(&self, other: &&str) -> bool {
+        *self == **other
+    }
+}
+
+impl<const N: u8> PartialEq<u64> for Decimal<N> {
+    /// Compare this decimal against an integer, as if it were `Decimal::from(other)`.
+    ///
+    /// An integer that cannot be represented, ie. `other > Self::MAX_UINT`, is never equal.
+    #[inline]
+    fn eq(&self, other: &u64) -> bool {
+        self.partial_cmp(other) == Some(core::cmp::Ordering::Equal)
+    }
+}
+
+impl<const N: u8> PartialOrd<u64> for Decimal<N> {
+    /// Compare this decimal against an integer, as if it were `Decimal::from(other)`.
+    ///
+    /// An integer that cannot be represented, ie. `other > Self::MAX_UINT`, is always greater than this decimal.
+    #[inline]
+    fn partial_cmp(&self, other: &u64) -> Option<core::cmp::Ordering> {
+        Some(u128::from(self.0).cmp(&(u128::from(*other) * u128::from(Self::FRAC))))
+    }
+}
+
+impl<const N: u8> ops::Add for Decimal<N> {
+    type Output = Self;
+
+    #[inline]
+    fn add(self, other: Self) -> Self::Output {
+        Self(self.0 + other.0)
+    }
+}
+
+impl<const N: u8> ops::AddAssign for Decimal<N> {
+    #[inline]
+    fn add_assign(&mut self, other: Self) {
+        self.0 += other.0;
+    }
+}
+
+impl<const N: u8> ops::Sub for Decimal<N> {
+    type Output = Self;
+
+    #[inline]
+    fn sub(self, other: Self) -> Self::Output {
+        Self(self.0 - other.0)
+    }
+}
+
+impl<const N: u8> ops::SubAssign for Decimal<N> {
+    #[inline]
+    fn sub_assign(&mut self, other: Self) {
+        self.0 -= other.0;
+    }
+}
+
+impl<const N: u8> ops::Add<&Decimal<N>> for &Decimal<N> {
+    type Output = Decimal<N>;
+
+    #[inline]
+    fn add(self, other: &Decimal<N>) -> Self::Output {
+        *self + *other
+    }
+}
+
+impl<const N: u8> ops::Add<&Decimal<N>> for Decimal<N> {
+    type Output = Decimal<N>;
+
+    #[inline]
+    fn add(self, other: &Decimal<N>) -> Self::Output {
+        self + *other
+    }
+}
+
+impl<const N: u8> ops::Add<Decimal<N>> for &Decimal<N> {
+    type Output = Decimal<N>;
+
+    #[inline]
+    fn add(self, other: Decimal<N>) -> Self::Output {
+        *self + other
+    }
+}
+
+impl<const N: u8> ops::Sub<&Decimal<N>> for &Decimal<N> {
+    type Output = Decimal<N>;
+
+    #[inline]
+    fn sub(self, other: &Decimal<N>) -> Self::Output {
+        *self - *other
+    }
+}
+
+impl<const N: u8> ops::Sub<&Decimal<N>> for Decimal<N> {
+    type Output = Decimal<N>;
+
+    #[inline]
+    fn sub(self, other: &Decimal<N>) -> Self::Output {
+        self - *other
+    }
+}
+
+impl<const N: u8> ops::Sub<Decimal<N>> for &Decimal<N> {
+    type Output = Decimal<N>;
+
+    #[inline]
+    fn sub(self, other: Decimal<N>) -> Self::Output {
+        *self - other
+    }
+}
+
+impl<const N: u8> ops::AddAssign<&Decimal<N>> for Decimal<N> {
+    #[inline]
+    fn add_assign(&mut self, other: &Decimal<N>) {
+        *self += *other;
+    }
+}
+
+impl<const N: u8> ops::SubAssign<&Decimal<N>> for Decimal<N> {
+    #[inline]
+    fn sub_assign(&mut self, other: &Decimal<N>) {
+        *self -= *other;
+    }
+}
+
+impl<const N: u8> ops::Add<u64> for Decimal<N> {
+    type Output = Self;
+
+    #[inline]
+    fn add(self, uint: u64) -> Self::Output {
+        self + Self::from(uint)
+    }
+}
+
+impl<const N: u8> ops::AddAssign<u64> for Decimal<N> {
+    #[inline]
+    fn add_assign(&mut self, uint: u64) {
+        *self += Self::from(uint);
+    }
+}
+
+impl<const N: u8> ops::Sub<u64> for Decimal<N> {
+    type Output = Self;
+
+    #[inline]
+    fn sub(self, uint: u64) -> Self::Output {
+        self - Self::from(uint)
+    }
+}
+
+impl<const N: u8> ops::SubAssign<u64> for Decimal<N> {
+    #[inline]
+    fn sub_assign(&mut self, uint: u64) {
+        *self -= Self::from(uint);
+    }
+}
+
+impl<const N: u8> ops::Mul<u64> for Decimal<N> {
+    type Output = Self;
+
+    #[inline]
+    fn mul(self, scalar: u64) -> Self::Output {
+        Self(self.0 * scalar)
+    }
+}
+
+impl<const N: u8> ops::MulAssign<u64> for Decimal<N> {
+    #[inline]
+    fn mul_assign(&mut self, scalar: u64) {
+        self.0 *= scalar;
+    }
+}
+
+impl<const N: u8> ops::Neg for Decimal<N> {
+    type Output = SignedDecimal<N>;
+
+    #[inline]
+    fn neg(self) -> Self::Output {
+        -SignedDecimal::from(self)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<const N: u8> Serialize for Decimal<N> {
+    #[inline]
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.to_string().serialize(serializer)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<'de, const N: u8> Deserialize<'de> for Decimal<N> {
+    #[inline]
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        <&str>::deserialize(deserializer)?.parse().map_err(de::Error::custom)
+    }
+}
+
+/// A decimal precision selected at runtime, eg. to match the number of places reported by an exchange.
+///
+/// Unlike `Decimal`, whose precision is fixed at compile time, parsing and formatting are dispatched to the selected precision.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Precision {
+    /// 2 places past the decimal, eg. `1.23`.
+    P2,
+    /// 4 places past the decimal, eg. `1.2346`.
+    #[default]
+    P4,
+    /// 8 places past the decimal, eg. `1.23456789`.
+    P8,
+}
+
+impl Precision {
+    /// Get the number of places past the decimal of this precision.
+    #[inline]
+    pub const fn places(self) -> u8 {
+        match self {
+            Self::P2 => 2,
+            Self::P4 => 4,
+            Self::P8 => 8,
+        }
+    }
+
+    /// Parse a decimal at this precision, extra digits being rounded half up as with the `FromStr` implementation of `Decimal`.
+    pub fn parse(self, s: &str) -> Result<DynDecimal, ParseDecimalError> {
+        match self {
+            Self::P2 => s.parse().map(DynDecimal::P2),
+            Self::P4 => s.parse().map(DynDecimal::P4),
+            Self::P8 => s.parse().map(DynDecimal::P8),
+        }
+    }
+}
+
+impl TryFrom<u8> for Precision {
+    type Error = Error;
+
+    /// Select a precision from its number of places past the decimal, an unsupported one being an `Error::UnsupportedPrecision`.
+    fn try_from(places: u8) -> Result<Self, Self::Error> {
+        match places {
+            2 => Ok(Self::P2),
+            4 => Ok(Self::P4),
+            8 => Ok(Self::P8),
+            _ => Err(Error::UnsupportedPrecision(places)),
+        }
+    }
+}
+
+/// A decimal with a precision selected at runtime, see `Precision`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DynDecimal {
+    P2(Decimal<2>),
+    P4(Decimal<4>),
+    P8(Decimal<8>),
+}
+
+impl DynDecimal {
+    /// Get the precision of this decimal.
+    #[inline]
+    pub const fn precision(&self) -> Precision {
+        match self {
+            Self::P2(_) => Precision::P2,
+            Self::P4(_) => Precision::P4,
+            Self::P8(_) => Precision::P8,
+        }
+    }
+}
+
+impl fmt::Display for DynDecimal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::P2(decimal) => fmt::Display::fmt(decimal, f),
+            Self::P4(decimal) => fmt::Display::fmt(decimal, f),
+            Self::P8(decimal) => fmt::Display::fmt(decimal, f),
+        }
+    }
+}
+
+/// A signed decimal handling fixed-precision with up to `N` places past the decimal.
+///
+/// Its magnitude is bounded by `Decimal::<N>::MAX`, so that any decimal can be represented either positively or negatively.
+#[derive(Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct SignedDecimal<const N: u8>(i128);
+
+impl<const N: u8> SignedDecimal<N> {
+    /// The largest value that can be represented by this decimal type.
+    pub const MAX: SignedDecimal<N> = SignedDecimal(u64::MAX as i128);
+    /// The smallest value that can be represented by this decimal type.
+    pub const MIN: SignedDecimal<N> = SignedDecimal(-(u64::MAX as i128));
+    /// The zero value of this decimal type.
+    pub const ZERO: SignedDecimal<N> = SignedDecimal(0);
+
+    /// Compare this decimal to zero, ie. its sign.
+    #[inline]
+    pub fn cmp_zero(&self) -> core::cmp::Ordering {
+        self.0.cmp(&0)
+    }
+
+    /// Whether this decimal is strictly positive.
+    #[inline]
+    pub const fn is_positive(&self) -> bool {
+        self.0 > 0
+    }
+
+    /// Whether this decimal is strictly negative.
+    #[inline]
+    pub const fn is_negative(&self) -> bool {
+        self.0 < 0
+    }
+
+    /// The absolute value of this decimal, which cannot overflow since bounds are symmetric.
+    #[inline]
+    pub const fn abs(self) -> Self {
+        Self(self.0.abs())
+    }
+
+    /// The magnitude of this decimal, whatever its sign.
+    #[inline]
+    pub const fn unsigned_abs(self) -> Decimal<N> {
+        Decimal(self.0.unsigned_abs() as u64) // magnitude cannot exceed `u64::MAX`
+    }
+
+    /// Checked decimal addition, returning `None` if the result is out of bounds.
+    #[inline]
+    pub fn checked_add(self, other: Self) -> Option<Self> {
+        Self::bounded(self.0 + other.0)
+    }
+
+    /// Checked decimal subtraction, returning `None` if the result is out of bounds.
+    #[inline]
+    pub fn checked_sub(self, other: Self) -> Option<Self> {
+        Self::bounded(self.0 - other.0)
+    }
+
+    /// Saturating decimal addition, returning the nearest bound if the result is out of bounds.
+    #[inline]
+    pub fn saturating_add(self, other: Self) -> Self {
+        Self((self.0 + other.0).clamp(Self::MIN.0, Self::MAX.0))
+    }
+
+    /// Saturating decimal subtraction, returning the nearest bound if the result is out of bounds.
+    #[inline]
+    pub fn saturating_sub(self, other: Self) -> Self {
+        Self((self.0 - other.0).clamp(Self::MIN.0, Self::MAX.0))
+    }
+
+    #[inline]
+    fn bounded(value: i128) -> Option<Self> {
+        (Self::MIN.0..=Self::MAX.0).contains(&value).then_some(Self(value))
+    }
+}
+
+impl<const N: u8> From<Decimal<N>> for SignedDecimal<N> {
+    #[inline]
+    fn from(decimal: Decimal<N>) -> Self {
+        Self(decimal.0 as i128)
+    }
+}
+
+impl<const N: u8> TryFrom<SignedDecimal<N>> for Decimal<N> {
+    type Error = Error;
+
+    fn try_from(value: SignedDecimal<N>) -> Result<Self, Self::Error> {
+        if value.is_negative() {
+            return Err(Error::Negative);
+        }
+
+        Ok(value.unsigned_abs())
+    }
+}
+
+impl<const N: u8> fmt::Debug for SignedDecimal<N> {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self, f)
+    }
+}
+
+impl<const N: u8> fmt::Display for SignedDecimal<N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_negative() {
+            f.write_str("-")?;
+        }
+
+        fmt::Display::fmt(&self.unsigned_abs(), f)
+    }
+}
+
+impl<const N: u8> FromStr for SignedDecimal<N> {
+    type Err = ParseDecimalError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.strip_prefix('-') {
+            Some(s) if s.starts_with('+') => Err(ParseDecimalError::InvalidDigit(1, '+')),
+            Some(s) => Ok(Self(-(s.parse::<Decimal<N>>()?.0 as i128))),
+            None => Ok(s.parse::<Decimal<N>>()?.into()),
+        }
+    }
+}
+
+impl<const N: u8> ops::Add for SignedDecimal<N> {
+    type Output = Self;
+
+    #[inline]
+    fn add(self, other: Self) -> Self::Output {
+        self.checked_add(other).expect("attempt to add with overflow")
+    }
+}
+
+impl<const N: u8> ops::Add<Decimal<N>> for SignedDecimal<N> {
+    type Output = Self;
+
+    #[inline]
+    fn add(self, other: Decimal<N>) -> Self::Output {
+        self + Self::from(other)
+    }
+}
+
+impl<const N: u8> ops::AddAssign for SignedDecimal<N> {
+    #[inline]
+    fn add_assign(&mut self, other: Self) {
+        *self = *self + other;
+    }
+}
+
+impl<const N: u8> ops::AddAssign<Decimal<N>> for SignedDecimal<N> {
+    #[inline]
+    fn add_assign(&mut self, other: Decimal<N>) {
+        *self = *self + other;
+    }
+}
+
+impl<const N: u8> ops::Sub for SignedDecimal<N> {
+    type Output = Self;
+
+    #[inline]
+    fn sub(self, other: Self) -> Self::Output {
+        self.checked_sub(other).expect("attempt to subtract with overflow")
+    }
+}
+
+impl<const N: u8> ops::Sub<Decimal<N>> for SignedDecimal<N> {
+    type Output = Self;
+
+    #[inline]
+    fn sub(self, other: Decimal<N>) -> Self::Output {
+        self - Self::from(other)
+    }
+}
+
+impl<const N: u8> ops::SubAssign for SignedDecimal<N> {
+    #[inline]
+    fn sub_assign(&mut self, other: Self) {
+        *self = *self - other;
+    }
+}
+
+impl<const N: u8> ops::SubAssign<Decimal<N>> for SignedDecimal<N> {
+    #[inline]
+    fn sub_assign(&mut self, other: Decimal<N>) {
+        *self = *self - other;
+    }
+}
+
+impl<const N: u8> ops::Neg for SignedDecimal<N> {
+    type Output = Self;
+
+    #[inline]
+    fn neg(self) -> Self::Output {
+        Self(-self.0) // bounds are symmetric
+    }
+}
+
+#[cfg(feature = "std")]
+impl<const N: u8> Serialize for SignedDecimal<N> {
+    #[inline]
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.to_string().serialize(serializer)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<'de, const N: u8> Deserialize<'de> for SignedDecimal<N> {
+    #[inline]
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        <&str>::deserialize(deserializer)?.parse().map_err(de::Error::custom)
+    }
+}
+
+/// A wide accumulator of decimals with up to `N` places past the decimal, eg. to sum up the balances of many accounts.
+///
+/// Unlike a single decimal bounded to the `u64` range, sums are backed by an `i128` to handle negative balances as well, so that no
+/// realistic number of summed decimals could ever overflow.
+///
+/// # Examples
+/// ```
+/// # use decimal::{Decimal, DecimalAccumulator};
+/// let mut sum = DecimalAccumulator::<4>::ZERO;
+/// sum += Decimal::<4>::MAX;
+/// sum += Decimal::<4>::MAX;
+/// assert_eq!(sum.to_string(), "3689348814741910.323");
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct DecimalAccumulator<const N: u8>(i128);
+
+impl<const N: u8> DecimalAccumulator<N> {
+    /// An empty sum.
+    pub const ZERO: DecimalAccumulator<N> = DecimalAccumulator(0);
+
+    const FRAC: u128 = u128::pow(10, N as u32);
+
+    /// Convert this sum into a signed decimal, returning `None` if it cannot be represented.
+    #[inline]
+    pub fn to_signed_decimal(self) -> Option<SignedDecimal<N>> {
+        SignedDecimal::bounded(self.0)
+    }
+}
+
+impl<const N: u8> From<Decimal<N>> for DecimalAccumulator<N> {
+    #[inline]
+    fn from(value: Decimal<N>) -> Self {
+        Self(value.0 as i128)
+    }
+}
+
+impl<const N: u8> From<SignedDecimal<N>> for DecimalAccumulator<N> {
+    #[inline]
+    fn from(value: SignedDecimal<N>) -> Self {
+        Self(value.0)
+    }
+}
+
+impl<const N: u8> ops::AddAssign<Decimal<N>> for DecimalAccumulator<N> {
+    #[inline]
+    fn add_assign(&mut self, other: Decimal<N>) {
+        self.0 += other.0 as i128;
+    }
+}
+
+impl<const N: u8> ops::AddAssign<SignedDecimal<N>> for DecimalAccumulator<N> {
+    #[inline]
+    fn add_assign(&mut self, other: SignedDecimal<N>) {
+        self.0 += other.0;
+    }
+}
+
+impl<const N: u8> ops::AddAssign for DecimalAccumulator<N> {
+    #[inline]
+    fn add_assign(&mut self, other: DecimalAccumulator<N>) {
+        self.0 += other.0;
+    }
+}
+
+impl<const N: u8> fmt::Display for DecimalAccumulator<N> {
+    /// Format this sum with trimmed trailing zeros, or all `N` places past the decimal in alternate mode, eg. `{:#}`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let value = self.0.unsigned_abs();
+        let (uint, frac) = (value / Self::FRAC, value % Self::FRAC);
+
+        if self.0 < 0 {
+            f.write_str("-")?;
+        }
+
+        if N == 0 || (frac == 0 && !f.alternate()) {
+            write!(f, "{uint}")
+        } else if f.alternate() {
+            write!(f, "{uint}.{frac:0>width$}", width = N as usize)
+        } else {
+            let (frac, width) = trim_zeros(frac, N);
+            write!(f, "{uint}.{frac:0>width$}")
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<const N: u8> Serialize for DecimalAccumulator<N> {
+    #[inline]
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.to_string().serialize(serializer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use assert_matches::assert_matches;
+    use proptest::prelude::*;
+
+    /// A shrinking-friendly strategy generating any representable decimal, from its raw value.
+    fn any_decimal<const N: u8>() -> impl Strategy<Value = Decimal<N>> {
+        any::<u64>().prop_map(Decimal::raw)
+    }
+
+    fn assert_string_round_trip<const N: u8>(decimal: Decimal<N>) -> Result<(), TestCaseError> {
+        prop_assert_eq!(decimal.to_string().parse::<Decimal<N>>(), Ok(decimal));
+        prop_assert_eq!(format!("{decimal:#}").parse::<Decimal<N>>(), Ok(decimal));
+        prop_assert_eq!(format!("{decimal:#}000").parse::<Decimal<N>>(), Ok(decimal));
+
+        Ok(())
+    }
+
+    proptest! {
+        #[test]
+        fn test_decimal_string_round_trip(d0 in any_decimal::<0>(), d4 in any_decimal::<4>(), d19 in any_decimal::<{ MAX_N }>()) {
+            assert_string_round_trip(d0)?;
+            assert_string_round_trip(d4)?;
+            assert_string_round_trip(d19)?;
+        }
+    }
+
+    #[test]
+    fn test_decimal_raw() {
+        assert_eq!(Decimal::<4>::raw(123456).into_raw(), 123456);
+        assert_eq!(Decimal::<4>::raw(123456).split(), (12, 3456));
+    }
+
+    #[test]
+    fn test_decimal_minor_units() {
+        let cents = Decimal::<2>::from_minor_units(1234);
+        assert_eq!(cents.to_string(), "12.34");
+        assert_eq!(cents, Decimal::new(12, 34));
+        assert_eq!(cents.to_minor_units(), 1234);
+
+        assert_eq!(Decimal::<2>::from_minor_units(5).to_string(), "0.05");
+        assert_eq!(Decimal::<2>::from_str("7.5").unwrap().to_minor_units(), 750);
+        assert_eq!(Decimal::<2>::from_minor_units(u64::MAX), Decimal::MAX);
+    }
+
+    #[test]
+    fn test_decimal_range() {
+        assert_eq!(Decimal::<0>::MAX_FRAC, 0);
+        assert_eq!(Decimal::<1>::MAX_FRAC, 9);
+        assert_eq!(Decimal::<10>::MAX_FRAC, 9999999999);
+
+        assert_eq!(Decimal::<0>::MAX_UINT, 18446744073709551615);
+        assert_eq!(Decimal::<1>::MAX_UINT, 1844674407370955161);
+        assert_eq!(Decimal::<10>::MAX_UINT, 1844674407);
+
+        assert_eq!(Decimal::<4>::MIN.to_string(), "0");
+        assert_eq!(Decimal::<4>::MAX.to_string(), "1844674407370955.1615");
+
+        let _ = Decimal::<{ MAX_N }>::default();
+        // let _ = Decimal::<{MAX_N + 1}>::default(); // cannot compile!
+    }
+
+    #[test]
+    fn test_decimal_valid_max() {
+        let _: Decimal<8> = From::from(Decimal::<8>::MAX_UINT);
+    }
+
+    #[test]
+    #[should_panic(expected = "assertion failed: uint <= Self::MAX_UINT")]
+    fn test_decimal_invalid_max() {
+        let _: Decimal<8> = From::from(Decimal::<8>::MAX_UINT + 1); // panics!
+    }
+
+    #[test]
+    fn test_decimal_from_values() {
+        // zero decimal
+        assert_eq!(Decimal::<0>::new(0, 0), Decimal(0));
+        assert_eq!(Decimal::<0>::new(1, 0), Decimal(1));
+        assert_eq!(Decimal::<0>::new(1, 2345), Decimal(1));
+
+        // zero value
+        assert_eq!(Decimal::<3>::new(0, 0), Decimal(0));
+
+        // integer value
+        assert_eq!(Decimal::<3>::new(5, 0), Decimal(5_000));
+
+        // exact value
+        assert_eq!(Decimal::<4>::new(3, 14), Decimal(3_0014));
+        assert_eq!(Decimal::<4>::new(3, 1416), Decimal(3_1416));
+
+        // round value
+        assert_eq!(Decimal::<4>::new(3, 14159), Decimal(3_1416));
+        assert_eq!(Decimal::<3>::new(3, 14159), Decimal(3_142));
+
+        // trailing zeroes
+        assert_eq!(Decimal::<3>::new(2, 10), Decimal(2_010));
+        assert_eq!(Decimal::<3>::new(2, 1000), Decimal(2_100));
+        assert_eq!(Decimal::<3>::new(2, 10000), Decimal(2_100));
+    }
+
+    #[test]
+    #[allow(clippy::approx_constant)]
+    fn test_decimal_from_float() {
+        assert_eq!(Decimal::<4>::try_from(3.14159), Ok(Decimal::<4>::new(3, 1416)));
+        assert_eq!(Decimal::<4>::try_from(3.0), Ok(Decimal(3_0000)));
+        assert_eq!(Decimal::<4>::try_from(0.0), Ok(Decimal::MIN));
+        assert_eq!(Decimal::<4>::try_from(-0.0), Ok(Decimal::MIN));
+
+        // float noise
+        assert_eq!(Decimal::<4>::try_from(0.1 + 0.2), Ok(Decimal(3000)));
+
+        // round value
+        assert_eq!(Decimal::<4>::try_from(0.99995), Ok(Decimal(1_0000)));
+        assert_eq!(Decimal::<0>::try_from(2.5), Ok(Decimal(3)));
+
+        // invalid values
+        assert_eq!(Decimal::<4>::try_from(f64::NAN), Err(Error::NotFinite));
+        assert_eq!(Decimal::<4>::try_from(f64::INFINITY), Err(Error::NotFinite));
+        assert_eq!(Decimal::<4>::try_from(-1.0), Err(Error::Negative));
+        assert_eq!(Decimal::<4>::try_from(1e18), Err(Error::Overflow));
+        assert_eq!(Decimal::<0>::try_from(1e20), Err(Error::Overflow));
+    }
+
+    #[test]
+    fn test_decimal_try_from_values() {
+        assert_eq!(Decimal::<4>::try_new(3, 14159), Ok(Decimal(3_1416)));
+        assert_eq!(
+            Decimal::<4>::try_new(Decimal::<4>::MAX_UINT, 0),
+            Ok(Decimal(Decimal::<4>::MAX_UINT * 10000))
+        );
+
+        // normalized fractional part
+        assert_eq!(Decimal::<4>::try_new(2, 10000), Ok(Decimal(2_1000)));
+        assert_eq!(Decimal::<4>::try_new(2, 99999), Ok(Decimal(3_0000)));
+        assert_eq!(Decimal::<4>::try_new(2, 99994), Ok(Decimal(2_9999)));
+
+        // overflow
+        assert_eq!(Decimal::<4>::try_new(Decimal::<4>::MAX_UINT + 1, 0), Err(Error::Overflow));
+        assert_eq!(Decimal::<4>::try_new(u64::MAX, 0), Err(Error::Overflow));
+        assert_eq!(Decimal::<4>::try_new(Decimal::<4>::MAX_UINT, 9999), Err(Error::Overflow));
+        assert_eq!(Decimal::<4>::try_new(Decimal::<4>::MAX_UINT, 1615), Ok(Decimal::MAX));
+    }
+
+    #[test]
+    fn test_decimal_try_from_tuple() {
+        // exact value
+        assert_eq!(Decimal::<4>::try_from((3, 1416)), Ok(Decimal(3_1416)));
+        assert_eq!(Decimal::<4>::try_from((3, 14)), Ok(Decimal(3_0014)));
+        assert_eq!(Decimal::<0>::try_from((3, 14)), Ok(Decimal(3)));
+
+        // round value
+        assert_eq!(Decimal::<4>::try_from((3, 14159)), Ok(Decimal(3_1416)));
+        assert_eq!(Decimal::<4>::try_from((2, 99999)), Ok(Decimal(3_0000)));
+
+        // overflow
+        assert_eq!(Decimal::<4>::try_from((Decimal::<4>::MAX_UINT + 1, 0)), Err(Error::Overflow));
+        assert_eq!(Decimal::<4>::try_from((Decimal::<4>::MAX_UINT, 9999)), Err(Error::Overflow));
+        assert_eq!(Decimal::<4>::try_from((Decimal::<4>::MAX_UINT, 1615)), Ok(Decimal::MAX));
+    }
+
+    #[test]
+    fn test_decimal_from_string() {
+        // integer value
+        assert_eq!(Decimal::<4>::from_str("30").unwrap(), Decimal(30_0000));
+        assert_eq!(Decimal::<4>::from_str("30.").unwrap(), Decimal(30_0000));
+        assert_eq!(Decimal::<4>::from_str("30.0").unwrap(), Decimal(30_0000));
+        assert_eq!(Decimal::<4>::from_str("0.").unwrap(), Decimal(0));
+
+        // missing integer part
+        assert_eq!(Decimal::<4>::from_str(".5").unwrap(), Decimal(5000));
+        assert_eq!(Decimal::<4>::from_str(".0").unwrap(), Decimal(0));
+        assert_eq!(Decimal::<4>::from_str("+.25").unwrap(), Decimal(2500));
+
+        // exact value
+        assert_eq!(Decimal::<4>::from_str("3.1416").unwrap(), Decimal(3_1416));
+
+        // round value
+        assert_eq!(Decimal::<4>::from_str("3.14159").unwrap(), Decimal(3_1416));
+
+        // missing trailing zeroes
+        assert_eq!(Decimal::<4>::from_str("3.14").unwrap(), Decimal(3_1400));
+        assert_eq!(Decimal::<4>::from_str("3.014").unwrap(), Decimal(3_0140));
+
+        // exact leading/trailing zeroes
+        assert_eq!(Decimal::<4>::from_str("3.1400").unwrap(), Decimal(3_1400));
+        assert_eq!(Decimal::<4>::from_str("3.0140").unwrap(), Decimal(3_0140));
+        assert_eq!(Decimal::<4>::from_str("3.0014").unwrap(), Decimal(3_0014));
+
+        // extra trailing zeroes
+        assert_eq!(Decimal::<4>::from_str("3.14000").unwrap(), Decimal(3_1400));
+        assert_eq!(Decimal::<4>::from_str("3.01400").unwrap(), Decimal(3_0140));
+        assert_eq!(Decimal::<4>::from_str("3.00140").unwrap(), Decimal(3_0014));
+
+        // extra leading zeroes
+        assert_eq!(Decimal::<4>::from_str("3.014159").unwrap(), Decimal(3_0142));
+        assert_eq!(Decimal::<4>::from_str("3.0014159").unwrap(), Decimal(3_0014));
+        assert_eq!(Decimal::<4>::from_str("3.00014159").unwrap(), Decimal(3_0001));
+        assert_eq!(Decimal::<4>::from_str("3.000014159").unwrap(), Decimal(3_0000));
+        assert_eq!(Decimal::<4>::from_str("3.0000014159").unwrap(), Decimal(3_0000));
+
+        // round value limits
+        assert_eq!(Decimal::<4>::from_str("1.00024999").unwrap(), Decimal(1_0002));
+        assert_eq!(Decimal::<4>::from_str("1.00025001").unwrap(), Decimal(1_0003));
+        assert_eq!(Decimal::<4>::from_str("2.99999").unwrap(), Decimal(3_0000));
+    }
+
+    #[test]
+    fn test_decimal_rounding_modes() {
+        use RoundingMode::*;
+
+        // exactly one half, after an even/odd digit
+        for (s, half_up, half_even, truncate) in [
+            ("1.00025", 1_0003, 1_0002, 1_0002),
+            ("1.00035", 1_0004, 1_0004, 1_0003),
+            ("1.99995", 2_0000, 2_0000, 1_9999),
+            ("0.00005", 1, 0, 0),
+        ] {
+            assert_eq!(Decimal::<4>::from_str_rounded(s, HalfUp), Ok(Decimal(half_up)), "{s}");
+            assert_eq!(Decimal::<4>::from_str_rounded(s, HalfEven), Ok(Decimal(half_even)), "{s}");
+            assert_eq!(Decimal::<4>::from_str_rounded(s, Truncate), Ok(Decimal(truncate)), "{s}");
+        }
+
+        // around one half
+        for s in ["1.000249", "1.00024999999999999999999"] {
+            assert_eq!(Decimal::<4>::from_str_rounded(s, HalfUp), Ok(Decimal(1_0002)));
+            assert_eq!(Decimal::<4>::from_str_rounded(s, HalfEven), Ok(Decimal(1_0002)));
+        }
+        for s in ["1.000251", "1.00025000000000000000001"] {
+            assert_eq!(Decimal::<4>::from_str_rounded(s, HalfUp), Ok(Decimal(1_0003)));
+            assert_eq!(Decimal::<4>::from_str_rounded(s, HalfEven), Ok(Decimal(1_0003)));
+            assert_eq!(Decimal::<4>::from_str_rounded(s, Truncate), Ok(Decimal(1_0002)));
+        }
+        assert_eq!(Decimal::<4>::from_str_rounded("1.0002500", HalfEven), Ok(Decimal(1_0002)));
+
+        // half-up by default
+        assert_eq!(Decimal::<4>::from_str("1.00025"), Ok(Decimal(1_0003)));
+        assert_eq!(Decimal::<4>::new(1, 25005), Decimal::with_rounding(1, 25005, HalfUp));
+
+        // integer / fractional parts
+        assert_eq!(Decimal::<4>::with_rounding(1, 25005, HalfUp), Decimal(1_2501));
+        assert_eq!(Decimal::<4>::with_rounding(1, 25005, HalfEven), Decimal(1_2500));
+        assert_eq!(Decimal::<4>::with_rounding(1, 25015, HalfEven), Decimal(1_2502));
+        assert_eq!(Decimal::<4>::with_rounding(1, 250051, HalfEven), Decimal(1_2501));
+        assert_eq!(Decimal::<4>::with_rounding(1, 25009, Truncate), Decimal(1_2500));
+        assert_eq!(Decimal::<4>::with_rounding(1, 99995, HalfEven), Decimal(2_0000));
+
+        // boundaries
+        assert_eq!(Decimal::<4>::from_str_rounded("1844674407370955.16155", Truncate), Ok(Decimal::MAX));
+        for mode in [HalfUp, HalfEven] {
+            assert_eq!(
+                Decimal::<4>::from_str_rounded("1844674407370955.16155", mode),
+                Err(ParseDecimalError::Decimal(Error::Overflow))
+            );
+        }
+        assert_eq!(Decimal::<0>::from_str_rounded("1.5", HalfUp), Ok(Decimal(1)));
+        assert_eq!(
+            Decimal::<4>::try_with_rounding(Decimal::<4>::MAX_UINT + 1, 0, Truncate),
+            Err(Error::Overflow)
+        );
+    }
+
+    #[test]
+    fn test_decimal_from_fraction_string() {
+        assert_eq!(Decimal::<4>::from_fraction_str("1/3"), Ok(Decimal(3333)));
+        assert_eq!(Decimal::<4>::from_fraction_str("2/3"), Ok(Decimal(6667)));
+        assert_eq!(Decimal::<4>::from_fraction_str("1/20000"), Ok(Decimal(1)));
+        assert_eq!(Decimal::<4>::from_fraction_str("7/2"), Ok(Decimal(3_5000)));
+        assert_eq!(Decimal::<4>::from_fraction_str("0/5"), Ok(Decimal(0)));
+        assert_eq!(Decimal::<4>::from_fraction_str("42"), Ok(Decimal(42_0000)));
+        assert_eq!(Decimal::<0>::from_fraction_str("5/2"), Ok(Decimal(3)));
+
+        assert_eq!(Decimal::<4>::from_fraction_str("5/0"), Err(ParseDecimalError::ZeroDenominator));
+        assert_eq!(
+            Decimal::<4>::from_fraction_str("1/2/3"),
+            Err(ParseDecimalError::InvalidDigit(3, '/'))
+        );
+        assert_eq!(
+            Decimal::<4>::from_fraction_str("1.5/3"),
+            Err(ParseDecimalError::InvalidDigit(1, '.'))
+        );
+        assert_eq!(
+            Decimal::<4>::from_fraction_str("-1/3"),
+            Err(ParseDecimalError::InvalidDigit(0, '-'))
+        );
+        assert_matches!(Decimal::<4>::from_fraction_str("1/"), Err(ParseDecimalError::Int(_)));
+        assert_eq!(
+            Decimal::<4>::from_fraction_str("1844674407370956/1"),
+            Err(ParseDecimalError::Decimal(Error::Overflow))
+        );
+    }
+
+    #[test]
+    fn test_decimal_from_string_invalid() {
+        use std::num::IntErrorKind;
+
+        assert_matches!(Decimal::<4>::from_str(""), Err(ParseDecimalError::Int(e)) if *e.kind() == IntErrorKind::Empty);
+        assert_matches!(Decimal::<4>::from_str("."), Err(ParseDecimalError::Int(e)) if *e.kind() == IntErrorKind::Empty);
+        assert_matches!(Decimal::<4>::from_str("+."), Err(ParseDecimalError::Int(e)) if *e.kind() == IntErrorKind::Empty);
+        assert_matches!(Decimal::<4>::from_str("99999999999999999999"), Err(ParseDecimalError::Int(e)) if *e.kind() == IntErrorKind::PosOverflow);
+        assert_eq!(
+            Decimal::<4>::from_str("1844674407370956"),
+            Err(ParseDecimalError::Decimal(Error::Overflow))
+        );
+        assert_eq!(
+            Decimal::<4>::from_str("1844674407370955.9999"),
+            Err(ParseDecimalError::Decimal(Error::Overflow))
+        );
+        assert_eq!(Decimal::<4>::from_str("1844674407370955.1615"), Ok(Decimal::MAX));
+
+        assert_eq!(Decimal::<4>::from_str("1.2.3"), Err(ParseDecimalError::InvalidDigit(3, '.')));
+        assert_eq!(Decimal::<4>::from_str("-1.5"), Err(ParseDecimalError::InvalidDigit(0, '-')));
+        assert_eq!(Decimal::<4>::from_str("1,5"), Err(ParseDecimalError::InvalidDigit(1, ',')));
+        assert_eq!(Decimal::<4>::from_str(" 1.5"), Err(ParseDecimalError::InvalidDigit(0, ' ')));
+
+        // non-ASCII digits
+        assert_eq!(Decimal::<4>::from_str("1.٥"), Err(ParseDecimalError::InvalidDigit(2, '٥')));
+        assert_eq!(Decimal::<4>::from_str("١٢.5"), Err(ParseDecimalError::InvalidDigit(0, '١')));
+        assert_eq!(
+            Decimal::<4>::from_str("1٥").unwrap_err().to_string(),
+            "invalid digit '٥' found at position 1"
+        );
+    }
+
+    #[test]
+    fn test_decimal_from_string_sign_and_separators() {
+        assert_eq!(Decimal::<4>::from_str("+30.5"), Ok(Decimal(30_5000)));
+        assert_eq!(Decimal::<4>::from_str("1_000"), Ok(Decimal(1000_0000)));
+        assert_eq!(Decimal::<4>::from_str("+1_234.25"), Ok(Decimal(1234_2500)));
+        assert_eq!(Decimal::<4>::from_str("0_000_000_000_000_000_000_001.5"), Ok(Decimal(1_5000)));
+        assert_eq!(Decimal::<0>::from_str("18_446_744_073_709_551_615"), Ok(Decimal::MAX));
+        assert_matches!(
+            Decimal::<0>::from_str("18_446_744_073_709_551_616"),
+            Err(ParseDecimalError::Int(err)) if *err.kind() == std::num::IntErrorKind::PosOverflow
+        );
+        assert_matches!(
+            Decimal::<0>::from_str("1_000_000_000_000_000_000_000_000"),
+            Err(ParseDecimalError::Int(_))
+        );
+
+        // multiple signs
+        assert_eq!(Decimal::<4>::from_str("++5"), Err(ParseDecimalError::InvalidDigit(1, '+')));
+        assert_eq!(Decimal::<4>::from_str("+-5"), Err(ParseDecimalError::InvalidDigit(1, '-')));
+        assert_eq!(Decimal::<4>::from_str("5.+5"), Err(ParseDecimalError::InvalidDigit(2, '+')));
+
+        // misplaced separators
+        assert_eq!(Decimal::<4>::from_str("1__0"), Err(ParseDecimalError::InvalidDigit(1, '_')));
+        assert_eq!(Decimal::<4>::from_str("+_10"), Err(ParseDecimalError::InvalidDigit(1, '_')));
+        assert_eq!(Decimal::<4>::from_str("10_"), Err(ParseDecimalError::InvalidDigit(2, '_')));
+        assert_eq!(Decimal::<4>::from_str("10_.5"), Err(ParseDecimalError::InvalidDigit(2, '_')));
+        assert_eq!(Decimal::<4>::from_str("10.0_5"), Err(ParseDecimalError::InvalidDigit(4, '_')));
+    }
+
+    #[test]
+    fn test_decimal_from_string_lenient() {
+        // strict by default
+        assert!(Decimal::<4>::from_str("1.000_1").is_err());
+        assert!(Decimal::<4>::from_str(" 1000.50 ").is_err());
+
+        // whitespaces & digit separators
+        assert_eq!(Decimal::<4>::from_str_lenient("1_000.50").unwrap(), Decimal(1000_5000));
+        assert_eq!(Decimal::<4>::from_str_lenient(" 1000.50 ").unwrap(), Decimal(1000_5000));
+        assert_eq!(Decimal::<4>::from_str_lenient("1_000.000_1").unwrap(), Decimal(1000_0001));
+
+        // misplaced separators
+        assert_eq!(
+            Decimal::<4>::from_str_lenient("1__000"),
+            Err(ParseDecimalError::InvalidDigit(1, '_'))
+        );
+        assert_eq!(Decimal::<4>::from_str_lenient("_100"), Err(ParseDecimalError::InvalidDigit(0, '_')));
+        assert!(Decimal::<4>::from_str_lenient("100_").is_err());
+        assert!(Decimal::<4>::from_str_lenient("100_.5").is_err());
+        assert!(Decimal::<4>::from_str_lenient("100._5").is_err());
+    }
+
+    #[test]
+    fn test_decimal_to_string() {
+        assert_eq!(format!("{}", Decimal::<4>::from(30)), "30");
+        assert_eq!(format!("{:#}", Decimal::<4>::from(30)), "30.0000");
+
+        assert_eq!(format!("{}", Decimal::<4>::new(3, 1400)), "3.14");
+        assert_eq!(format!("{:#}", Decimal::<4>::new(3, 1400)), "3.1400");
+
+        assert_eq!(Decimal::<4>::new(3, 14).to_string(), "3.0014");
+        assert_eq!(Decimal::<4>::new(3, 1416).to_string(), "3.1416");
+        assert_eq!(Decimal::<4>::new(3, 14159).to_string(), "3.1416");
+    }
+
+    #[test]
+    fn test_decimal_to_string_with_precision() {
+        let a = Decimal::<4>::new(3, 1416);
+        assert_eq!(format!("{a}"), "3.1416");
+        assert_eq!(format!("{a:.2}"), "3.14");
+        assert_eq!(format!("{a:.3}"), "3.142");
+        assert_eq!(format!("{a:.0}"), "3");
+        assert_eq!(format!("{a:.4}"), "3.1416");
+        assert_eq!(format!("{a:.6}"), "3.141600");
+
+        // round half up
+        assert_eq!(format!("{:.2}", Decimal::<4>::new(2, 5050)), "2.51");
+        assert_eq!(format!("{:.0}", Decimal::<4>::new(2, 5000)), "3");
+        assert_eq!(format!("{:.2}", Decimal::<4>::new(9, 9950)), "10.00");
+        assert_eq!(format!("{:.1}", Decimal::<4>::MAX), "1844674407370955.2");
+
+        assert_eq!(format!("{:.0}", Decimal::<0>::new(42, 0)), "42");
+        assert_eq!(format!("{:.2}", Decimal::<0>::new(42, 0)), "42.00");
+        assert_eq!(format!("{:.2}", SignedDecimal::<4>(-3_1416)), "-3.14");
+    }
+
+    #[test]
+    fn test_decimal_eq_str() {
+        let a = Decimal::<4>::new(5, 1000);
+        assert_eq!(a, "5.1");
+        assert_eq!(a, "5.1000");
+        assert_eq!(a, *"5.10000");
+        assert_ne!(a, "5.01");
+        assert_ne!(a, "abc");
+        assert_ne!(a, "");
+    }
+
+    #[test]
+    fn test_decimal_cmp_zero() {
+        use core::cmp::Ordering;
+
+        assert_eq!(Decimal::<4>::MIN.cmp_zero(), Ordering::Equal);
+        assert!(!Decimal::<4>::MIN.is_positive());
+
+        for d in [Decimal::<4>::raw(1), Decimal::new(5, 1000), Decimal::MAX] {
+            assert_eq!(d.cmp_zero(), Ordering::Greater);
+            assert!(d.is_positive());
+        }
+
+        assert_eq!(SignedDecimal::<4>::ZERO.cmp_zero(), Ordering::Equal);
+        assert_eq!(SignedDecimal::<4>::MAX.cmp_zero(), Ordering::Greater);
+        assert_eq!(SignedDecimal::<4>::MIN.cmp_zero(), Ordering::Less);
+        assert!(SignedDecimal::<4>::MAX.is_positive() && !SignedDecimal::<4>::ZERO.is_positive() && !SignedDecimal::<4>::MIN.is_positive());
+    }
+
+    #[test]
+    #[allow(clippy::zero_prefixed_literal)]
+    fn test_decimal_valid_ops() {
+        let mut a = Decimal::<4>::new(3, 14159);
+        let mut b = Decimal::<4>::new(1, 41421);
+
+        assert_eq!(a + b, Decimal(4_5558));
+        assert_eq!(a - b, Decimal(1_7274));
+
+        let e = Decimal::<4>::new(2, 71828);
+
+        a -= e;
+        b += e;
+
+        assert_eq!(a, Decimal(0_4233));
+        assert_eq!(b, Decimal(4_1325));
+    }
+
+    #[test]
+    #[allow(clippy::op_ref)]
+    fn test_decimal_ref_ops() {
+        let mut a = Decimal::<4>::new(3, 14159);
+        let b = Decimal::<4>::new(1, 41421);
+
+        assert_eq!(&a + &b, Decimal(4_5558));
+        assert_eq!(a + &b, Decimal(4_5558));
+        assert_eq!(&a + b, Decimal(4_5558));
+        assert_eq!(&a - &b, Decimal(1_7274));
+        assert_eq!(a - &b, Decimal(1_7274));
+        assert_eq!(&a - b, Decimal(1_7274));
+
+        a += &b;
+        assert_eq!(a, Decimal(4_5558));
+        a -= &b;
+        assert_eq!(a, Decimal(3_1416));
+
+        // generic code bounded on reference ops, eg. folding an iterator of references
+        fn sum<'a, T: Default + ops::Add<&'a T, Output = T> + 'a>(values: impl IntoIterator<Item = &'a T>) -> T {
+            values.into_iter().fold(T::default(), |sum, value| sum + value)
+        }
+        assert_eq!(sum(&[a, b, b]), Decimal(5_9700));
+        assert_eq!(sum::<Decimal<4>>(&[]), Decimal(0));
+    }
+
+    #[test]
+    fn test_decimal_checked_ops() {
+        let a = Decimal::<4>::new(3, 14159);
+        let b = Decimal::<4>::new(1, 41421);
+
+        assert_eq!(a.checked_add(b), Some(Decimal(4_5558)));
+        assert_eq!(a.checked_sub(b), Some(Decimal(1_7274)));
+        assert_eq!(b.checked_sub(a), None);
+        assert_eq!(a.checked_sub(a), Some(Decimal::MIN));
+        assert_eq!(a.checked_mul(2), Some(Decimal(6_2832)));
+        assert_eq!(a.checked_mul(0), Some(Decimal::MIN));
+
+        // boundaries
+        assert_eq!(Decimal::<4>::MAX.checked_add(Decimal::MIN), Some(Decimal::MAX));
+        assert_eq!(Decimal::<4>(u64::MAX - 1).checked_add(Decimal(1)), Some(Decimal::MAX));
+        assert_eq!(Decimal::<4>::MAX.checked_add(Decimal(1)), None);
+        assert_eq!(Decimal::<4>::MAX.checked_sub(Decimal::MAX), Some(Decimal::MIN));
+        assert_eq!(Decimal::<4>::MIN.checked_sub(Decimal(1)), None);
+        assert_eq!(Decimal::<4>::MAX.checked_mul(1), Some(Decimal::MAX));
+        assert_eq!(Decimal::<4>::MAX.checked_mul(2), None);
+        assert_eq!(Decimal::<4>(u64::MAX / 5).checked_mul(5), Some(Decimal::MAX));
+    }
+
+    #[test]
+    fn test_decimal_saturating_ops() {
+        let a = Decimal::<4>::new(3, 14159);
+        let b = Decimal::<4>::new(1, 41421);
+
+        assert_eq!(a.saturating_add(b), Decimal(4_5558));
+        assert_eq!(a.saturating_sub(b), Decimal(1_7274));
+        assert_eq!(b.saturating_sub(a), Decimal::MIN);
+
+        // boundaries
+        assert_eq!(Decimal::<4>(u64::MAX - 1).saturating_add(Decimal(1)), Decimal::MAX);
+        assert_eq!(Decimal::<4>(u64::MAX - 1).saturating_add(Decimal(2)), Decimal::MAX);
+        assert_eq!(Decimal::<4>::MAX.saturating_add(Decimal::MAX), Decimal::MAX);
+        assert_eq!(Decimal::<4>(1).saturating_sub(Decimal(1)), Decimal::MIN);
+        assert_eq!(Decimal::<4>::MIN.saturating_sub(Decimal::MAX), Decimal::MIN);
+    }
+
+    #[test]
+    #[allow(clippy::erasing_op)]
+    fn test_decimal_mul() {
+        let mut a = Decimal::<4>::new(3, 14159);
+
+        assert_eq!(a * 2, Decimal(6_2832));
+        assert_eq!(a * 0, Decimal::MIN);
+
+        a *= 3;
+        assert_eq!(a, Decimal(9_4248));
+    }
+
+    #[test]
+    fn test_decimal_checked_mul_decimal() {
+        let a = Decimal::<4>::new(1, 5000);
+        assert_eq!(a.checked_mul_decimal(Decimal::new(2, 0)), Some(Decimal(3_0000)));
+        assert_eq!(a.checked_mul_decimal(Decimal::MIN), Some(Decimal::MIN));
+        assert_eq!(a.checked_mul_decimal(Decimal::from(1)), Some(a));
+
+        // round value: 1.23454321 / 0.00005 / 0.00004999
+        let a = Decimal::<4>::new(1, 1111);
+        assert_eq!(a.checked_mul_decimal(a), Some(Decimal(1_2345)));
+        assert_eq!(Decimal::<4>(1).checked_mul_decimal(Decimal(5000)), Some(Decimal(1)));
+        assert_eq!(Decimal::<4>(1).checked_mul_decimal(Decimal(4999)), Some(Decimal(0)));
+
+        // no intermediate overflow
+        assert_eq!(Decimal::<4>::MAX.checked_mul_decimal(Decimal::from(1)), Some(Decimal::MAX));
+        assert_eq!(Decimal::<4>::MAX.checked_mul_decimal(Decimal(1)), Some(Decimal(1844674407370955)));
+        assert_eq!(Decimal::<4>::MAX.checked_mul_decimal(Decimal::new(1, 1)), None);
+        assert_eq!(Decimal::<0>::MAX.checked_mul_decimal(Decimal(2)), None);
+    }
+
+    #[test]
+    fn test_decimal_mul_ratio() {
+        // 2.5% fee
+        assert_eq!(Decimal::<4>::from(100).mul_ratio(25, 1000), Decimal(2_5000));
+
+        // one-third split, rounded half up
+        assert_eq!(Decimal::<4>::from(1).mul_ratio(1, 3), Decimal(3333));
+        assert_eq!(Decimal::<4>::from(1).mul_ratio(2, 3), Decimal(6667));
+        assert_eq!(Decimal::<4>(1).mul_ratio(1, 2), Decimal(1));
+        assert_eq!(Decimal::<4>(1).mul_ratio(1, 3), Decimal(0));
+
+        // zero
+        assert_eq!(Decimal::<4>::from(100).mul_ratio(0, 3), Decimal::MIN);
+
+        // no intermediate overflow
+        assert_eq!(Decimal::<4>::MAX.mul_ratio(u64::MAX, u64::MAX), Decimal::MAX);
+    }
+
+    #[test]
+    #[should_panic(expected = "assertion failed: raw <= u64::MAX as u128")]
+    fn test_decimal_invalid_mul_ratio() {
+        let _ = Decimal::<4>::MAX.mul_ratio(3, 2); // panics!
+    }
+
+    #[test]
+    fn test_decimal_fract_trunc() {
+        let a = Decimal::<4>::new(3, 1416);
+        assert_eq!((a.trunc(), a.fract()), (Decimal(3_0000), Decimal(1416)));
+        assert_eq!(a.trunc() + a.fract(), a);
+
+        let a = Decimal::<4>::new(3, 0);
+        assert_eq!((a.trunc(), a.fract()), (a, Decimal::MIN));
+
+        let a = Decimal::<4>::new(0, 1416);
+        assert_eq!((a.trunc(), a.fract()), (Decimal::MIN, a));
+
+        let a = Decimal::<4>::MIN;
+        assert_eq!((a.trunc(), a.fract()), (a, a));
+
+        let a = Decimal::<4>::MAX;
+        assert_eq!((a.trunc(), a.fract()), (Decimal::from(Decimal::<4>::MAX_UINT), Decimal(1615)));
+
+        let a = Decimal::<0>::new(42, 0);
+        assert_eq!((a.trunc(), a.fract()), (a, Decimal::MIN));
+    }
+
+    #[test]
+    fn test_decimal_midpoint() {
+        let a = Decimal::<4>::new(1, 0);
+        let b = Decimal::<4>::new(2, 0);
+        assert_eq!(a.midpoint(b), Decimal(1_5000));
+        assert_eq!(b.midpoint(a), Decimal(1_5000));
+        assert_eq!(a.midpoint(a), a);
+
+        // round last digit
+        assert_eq!(Decimal::<4>(1_0000).midpoint(Decimal(1_0001)), Decimal(1_0001));
+        assert_eq!(Decimal::<4>::MIN.midpoint(Decimal(3)), Decimal(2));
+
+        // no overflow near boundaries
+        assert_eq!(Decimal::<4>::MAX.midpoint(Decimal::MAX), Decimal::MAX);
+        assert_eq!(Decimal::<4>::MAX.midpoint(Decimal(u64::MAX - 2)), Decimal(u64::MAX - 1));
+        assert_eq!(Decimal::<4>::MAX.midpoint(Decimal::MIN), Decimal(u64::MAX / 2 + 1));
+    }
+
+    #[test]
+    fn test_decimal_integer_ops() {
+        let a = Decimal::<4>::new(3, 5000);
+        assert!(a != 3 && a > 3 && a >= 3);
+        assert!(a != 4 && a < 4 && a <= 4);
+        assert_eq!(Decimal::<4>::new(3, 0), 3);
+        assert_eq!(Decimal::<4>::MIN, 0);
+
+        // unrepresentable integers are always greater
+        let max_uint = Decimal::<4>::MAX_UINT;
+        assert!(Decimal::<4>::MAX > max_uint);
+        assert!(Decimal::<4>::MAX != max_uint + 1 && Decimal::<4>::MAX < max_uint + 1);
+        assert!(Decimal::<4>::MAX < u64::MAX);
+        assert!(Decimal::<0>::MAX == u64::MAX);
+
+        assert_eq!(a + 2, Decimal::new(5, 5000));
+        assert_eq!(a - 2, Decimal::new(1, 5000));
+        let mut b = a;
+        b += 1;
+        assert_eq!(b, Decimal::new(4, 5000));
+        b -= 4;
+        assert_eq!(b, Decimal::new(0, 5000));
+    }
+
+    #[test]
+    #[should_panic(expected = "attempt to subtract with overflow")]
+    fn test_decimal_invalid_ops() {
+        let a = Decimal::<4>::new(3, 14159);
+        let b = Decimal::<4>::new(1, 41421);
+
+        let _ = b - a; // panics!
+    }
+
+    #[test]
+    fn test_decimal_tier() {
+        let boundaries = ["10", "100", "1000.5"].map(|boundary| Decimal::<4>::from_str(boundary).unwrap());
+
+        for (amount, tier) in [
+            ("0", 0),
+            ("9.9999", 0),
+            ("10", 1),
+            ("10.0001", 1),
+            ("99.9999", 1),
+            ("100", 2),
+            ("1000.4999", 2),
+            ("1000.5", 3),
+            ("1844674407370955.1615", 3),
+        ] {
+            assert_eq!(Decimal::<4>::from_str(amount).unwrap().tier(&boundaries), tier, "amount {amount}");
+        }
+
+        // no tiers at all
+        assert_eq!(Decimal::<4>::from(1).tier(&[]), 0);
+    }
+
+    #[test]
+    fn test_precision() {
+        for (precision, expected) in [(Precision::P2, "1.23"), (Precision::P4, "1.2346"), (Precision::P8, "1.23456789")] {
+            let decimal = precision.parse("1.23456789").unwrap();
+            assert_eq!(decimal.to_string(), expected);
+            assert_eq!(decimal.precision(), precision);
+            assert_eq!(Precision::try_from(precision.places()), Ok(precision));
+        }
+
+        assert_eq!(Precision::P8.parse("1.234567891"), Ok(DynDecimal::P8(Decimal(1_23456789))));
+        assert_eq!(format!("{:#}", Precision::P2.parse("5").unwrap()), "5.00");
+        assert_eq!(
+            Precision::P8.parse("184467440738"),
+            Err(ParseDecimalError::Decimal(Error::Overflow))
+        );
+        assert_eq!(Precision::try_from(3), Err(Error::UnsupportedPrecision(3)));
+        assert_eq!(Precision::try_from(MAX_N + 1), Err(Error::UnsupportedPrecision(20)));
+        assert_eq!(Error::UnsupportedPrecision(3).to_string(), "unsupported precision '3'");
+    }
+
+    #[test]
+    fn test_signed_decimal_conversions() {
+        let a = Decimal::<4>::new(3, 14159);
+        assert_eq!(SignedDecimal::from(a), SignedDecimal(3_1416));
+        assert_eq!(Decimal::try_from(SignedDecimal::<4>(3_1416)), Ok(a));
+        assert_eq!(Decimal::try_from(SignedDecimal::<4>(-3_1416)), Err(Error::Negative));
+
+        assert_eq!(SignedDecimal::from(Decimal::<4>::MAX), SignedDecimal::MAX);
+        assert_eq!(SignedDecimal::<4>::MIN.unsigned_abs(), Decimal::MAX);
+        assert!(SignedDecimal::<4>::MIN.is_negative());
+        assert!(!SignedDecimal::<4>::ZERO.is_negative());
+    }
+
+    #[test]
+    fn test_signed_decimal_strings() {
+        assert_eq!("3.1416".parse(), Ok(SignedDecimal::<4>(3_1416)));
+        assert_eq!("-3.1416".parse(), Ok(SignedDecimal::<4>(-3_1416)));
+        assert_eq!("-0".parse(), Ok(SignedDecimal::<4>::ZERO));
+        assert_matches!("--1".parse::<SignedDecimal<4>>(), Err(ParseDecimalError::InvalidDigit(0, '-')));
+        assert_eq!("+1".parse(), Ok(SignedDecimal::<4>(1_0000)));
+        assert_matches!("-+1".parse::<SignedDecimal<4>>(), Err(ParseDecimalError::InvalidDigit(1, '+')));
+        assert_matches!("+-1".parse::<SignedDecimal<4>>(), Err(ParseDecimalError::InvalidDigit(1, '-')));
+
+        assert_eq!(SignedDecimal::<4>(3_1416).to_string(), "3.1416");
+        assert_eq!(SignedDecimal::<4>(-3_1416).to_string(), "-3.1416");
+        assert_eq!(format!("{:#}", SignedDecimal::<4>(-3_1000)), "-3.1000");
+        assert_eq!(SignedDecimal::<4>::MIN.to_string(), "-1844674407370955.1615");
+    }
+
+    #[test]
+    fn test_signed_decimal_ops() {
+        let a = Decimal::<4>::new(3, 14159);
+        let b = Decimal::<4>::new(1, 41421);
+
+        let mut c = SignedDecimal::from(b) - a;
+        assert_eq!(c, SignedDecimal(-1_7274));
+        assert!(c < SignedDecimal::ZERO);
+
+        c += a;
+        assert_eq!(c, SignedDecimal::from(b));
+        c -= SignedDecimal::from(a) + a;
+        assert_eq!(c, SignedDecimal(-4_8690));
+
+        assert_eq!(SignedDecimal::<4>::MAX.checked_add(SignedDecimal(1)), None);
+        assert_eq!(SignedDecimal::<4>::MIN.checked_sub(SignedDecimal(1)), None);
+        assert_eq!(SignedDecimal::<4>::MIN.checked_add(SignedDecimal::MAX), Some(SignedDecimal::ZERO));
+
+        assert_eq!(SignedDecimal::<4>::MAX.saturating_add(SignedDecimal(1)), SignedDecimal::MAX);
+        assert_eq!(SignedDecimal::<4>::MIN.saturating_sub(SignedDecimal(1)), SignedDecimal::MIN);
+        assert_eq!(SignedDecimal::<4>(-1).saturating_add(SignedDecimal::MIN), SignedDecimal::MIN);
+        assert_eq!(
+            SignedDecimal::<4>(-1).saturating_sub(SignedDecimal::MIN),
+            SignedDecimal::MAX - Decimal(1)
+        );
+    }
+
+    #[test]
+    fn test_signed_decimal_neg() {
+        let a = Decimal::<4>::new(3, 14159);
+        assert_eq!(-a, SignedDecimal(-3_1416));
+        assert_eq!(-(-a), SignedDecimal::from(a));
+        assert_eq!(-Decimal::<4>::MIN, SignedDecimal::ZERO);
+        assert_eq!(-SignedDecimal::<4>::ZERO, SignedDecimal::ZERO);
+        assert_eq!(-Decimal::<4>::MAX, SignedDecimal::MIN);
+
+        assert_eq!((-a).abs(), SignedDecimal::from(a));
+        assert_eq!(SignedDecimal::from(a).abs(), SignedDecimal::from(a));
+        assert_eq!(SignedDecimal::<4>::ZERO.abs(), SignedDecimal::ZERO);
+        assert_eq!(SignedDecimal::<4>::MIN.abs(), SignedDecimal::MAX);
+    }
+
+    #[test]
+    fn test_decimal_accumulator() {
+        let mut sum = DecimalAccumulator::<4>::ZERO;
+        assert_eq!(sum.to_string(), "0");
+
+        sum += Decimal::<4>(1_2500);
+        sum += SignedDecimal::<4>(-3_0000);
+        assert_eq!(sum, DecimalAccumulator(-1_7500));
+        assert_eq!(sum.to_string(), "-1.75");
+        assert_eq!(format!("{sum:#}"), "-1.7500");
+        assert_eq!(sum.to_signed_decimal(), Some(SignedDecimal(-1_7500)));
+        assert_eq!(DecimalAccumulator::from(Decimal::<4>(1_2500)), DecimalAccumulator(1_2500));
+        assert_eq!(DecimalAccumulator::from(SignedDecimal::<4>(-3_0000)), DecimalAccumulator(-3_0000));
+
+        // sums past the bounds of a single decimal
+        for _ in 0..3 {
+            sum += Decimal::<4>::MAX;
+        }
+        assert_eq!(sum.to_string(), "5534023222112863.7345");
+        assert_eq!(sum.to_signed_decimal(), None);
+
+        let mut sum = DecimalAccumulator::<4>::ZERO;
+        for _ in 0..3 {
+            sum += SignedDecimal::<4>::MIN;
+        }
+        assert_eq!(sum.to_string(), "-5534023222112865.4845");
+
+        let mut total = DecimalAccumulator::from(Decimal::<4>::MAX);
+        total += sum;
+        assert_eq!(total.to_string(), "-3689348814741910.323");
+
+        assert_eq!(DecimalAccumulator::<0>(-42).to_string(), "-42");
+        assert_eq!(format!("{:#}", DecimalAccumulator::<2>(500)), "5.00");
+    }
+
+    #[test]
+    #[should_panic(expected = "attempt to subtract with overflow")]
+    fn test_signed_decimal_invalid_ops() {
+        let _ = SignedDecimal::<4>::MIN - Decimal(1); // panics!
+    }
+}
@@ -1,20 +1,45 @@
 use std::error::Error;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
 
 use clap::Parser;
+use futures::StreamExt;
 use tracing_subscriber::{fmt, EnvFilter};
-use transaction::io;
+use transaction::{io, Processor, TransactionHandler};
+
+/// Exit code when a strict process is aborted because of an invalid transaction, distinct from clap usage errors (`2`).
+const EXIT_CODE_STRICT_ABORT: u8 = 3;
 
 /// Struct to register all CLI args.
 #[derive(Debug, Parser)]
 #[command(about = "A simple toy payments engine!")]
 struct Cli {
-    /// The payment inputs as a path to a valid CSV file
-    input_file_path: PathBuf,
+    /// The payment inputs as paths to valid CSV files, processed in order (gzip-compressed if ending with `.gz`, with the `gzip` feature)
+    #[arg(required_unless_present = "input_dir")]
+    input_file_paths: Vec<PathBuf>,
+    /// Read the payment inputs from all `*.csv` files of a directory instead, processed in filename order
+    /// (including `*.csv.gz` files, with the `gzip` feature)
+    #[arg(long, value_name = "DIR", conflicts_with = "input_file_paths")]
+    input_dir: Option<PathBuf>,
+    /// Suppress unlocked accounts with no funds from the output
+    #[arg(long)]
+    suppress_empty: bool,
+    /// Write the output accounts to a file instead of stdout
+    #[arg(short, long, value_name = "PATH")]
+    output: Option<PathBuf>,
+    /// Abort on the first invalid transaction instead of logging and discarding it, exiting with code 3
+    #[arg(long)]
+    strict: bool,
+    /// Resume from the processor state persisted to a JSON file by a previous run, starting afresh if this file does not exist
+    #[arg(long, value_name = "PATH")]
+    state_in: Option<PathBuf>,
+    /// Persist the processor state to a JSON file once all payment inputs are processed, to be resumed by a later run
+    #[arg(long, value_name = "PATH")]
+    state_out: Option<PathBuf>,
 }
 
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
+async fn main() -> Result<ExitCode, Box<dyn Error + Send + Sync + 'static>> {
     // Install logger
     fmt::fmt()
         .with_env_filter(EnvFilter::from_default_env())
@@ -24,14 +49,100 @@ async fn main() -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
     // Parse CLI args
     let cli = Cli::parse();
 
-    tracing::info!("Processing payments from input file: `{}`", cli.input_file_path.display());
+    tracing::debug!("Amount precision: {} places past the decimal", transaction::amount_precision());
+    let input_file_paths = match &cli.input_dir {
+        Some(dir) => input_dir_file_paths(dir)?,
+        None => cli.input_file_paths,
+    };
+
+    let mut readers = Vec::with_capacity(input_file_paths.len());
+    for path in &input_file_paths {
+        tracing::info!("Processing payments from input file: `{}`", path.display());
+
+        let file: Box<dyn tokio::io::AsyncRead + Send + Unpin> = Box::new(tokio::fs::File::open(path).await?);
+        #[cfg(feature = "gzip")]
+        let file: Box<dyn tokio::io::AsyncRead + Send + Unpin> = match path.extension() {
+            Some(extension) if extension == "gz" => Box::new(io::gzip_decoder(file)),
+            _ => file,
+        };
+
+        let mut reader = io::reader(file)?;
+        io::header_layout(&mut reader).await?;
+        readers.push(reader);
+    }
+
+    let output: Box<dyn tokio::io::AsyncWrite + Unpin> = match cli.output {
+        Some(path) => Box::new(tokio::fs::File::create(path).await?),
+        None => Box::new(tokio::io::stdout()),
+    };
+
+    let transactions = io::chain_readers(readers);
+    let writer = io::writer(output)?;
+
+    let processor = match &cli.state_in {
+        Some(path) => load_state(path).await?,
+        None => Processor::default(),
+    };
+    let processor = processor.with_suppress_empty(cli.suppress_empty);
+
+    let handled = match cli.strict {
+        true => processor.handle_all_strict(transactions).await,
+        false => processor.handle_all(transactions).await,
+    };
+
+    let result = match handled {
+        Ok(processor) => {
+            let snapshot = cli.state_out.is_some().then(|| processor.snapshot());
+
+            let records = futures::stream::iter(processor.into_records()).map(Ok);
+            let result = io::write_records(records, writer, Default::default()).await;
+
+            if let (Some(path), Some(snapshot)) = (&cli.state_out, snapshot) {
+                tracing::info!("Persisting processor state to file: `{}`", path.display());
+                tokio::fs::write(path, serde_json::to_vec(&snapshot)?).await?;
+            }
+            result
+        }
+        Err(err) => Err(err),
+    };
+
+    match result {
+        Err(transaction::Error::Process(err)) => {
+            eprintln!("Error: {err}");
+            Ok(ExitCode::from(EXIT_CODE_STRICT_ABORT))
+        }
+        result => Ok(result.map(|_| ExitCode::SUCCESS)?),
+    }
+}
+
+/// Restore a processor from the state persisted to a JSON file, starting afresh if this file does not exist, eg. on a first run.
+async fn load_state(path: &Path) -> Result<Processor, Box<dyn Error + Send + Sync + 'static>> {
+    match tokio::fs::read(path).await {
+        Ok(state) => {
+            tracing::info!("Resuming processor state from file: `{}`", path.display());
+            Ok(Processor::restore(serde_json::from_slice(&state)?))
+        }
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            tracing::info!("No processor state file: `{}`, starting afresh", path.display());
+            Ok(Processor::default())
+        }
+        Err(err) => Err(err.into()),
+    }
+}
 
-    let file = tokio::fs::File::open(cli.input_file_path).await?;
+/// List the payment input files of a directory, sorted by filename.
+fn input_dir_file_paths(dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let is_input_file = |name: &str| name.ends_with(".csv") || (cfg!(feature = "gzip") && name.ends_with(".csv.gz"));
 
-    let reader = io::reader(file)?;
-    let writer = io::writer(tokio::io::stdout())?;
+    let mut paths = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
 
-    io::process(reader, writer).await?;
+        if entry.file_type()?.is_file() && entry.file_name().to_str().is_some_and(is_input_file) {
+            paths.push(entry.path());
+        }
+    }
+    paths.sort_unstable_by(|a, b| a.file_name().cmp(&b.file_name()));
 
-    Ok(())
+    Ok(paths)
 }
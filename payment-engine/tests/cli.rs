@@ -0,0 +1,133 @@
+use std::process::{Command, Output};
+
+fn payment_engine(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_payment-engine"))
+        .args(args)
+        .output()
+        .expect("failed to run payment-engine")
+}
+
+#[test]
+fn test_invalid_transaction() {
+    let input = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/data/invalid_transaction.csv");
+
+    // Test: lenient mode, the invalid transaction is discarded
+    let output = payment_engine(&[input]);
+    assert_eq!(output.status.code(), Some(0));
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let mut records: Vec<_> = stdout.lines().collect();
+    records[1..].sort_unstable();
+    assert_eq!(records, ["client,available,held,total,locked", "1,1,0,1,false", "2,2,0,2,false"]);
+
+    // Test: strict mode, the process is aborted
+    let output = payment_engine(&["--strict", input]);
+    assert_eq!(output.status.code(), Some(3));
+    assert!(output.stdout.is_empty());
+    assert!(String::from_utf8(output.stderr).unwrap().contains("transaction '2' does not exist"));
+
+    // Test: a strict abort is told apart from a usage error
+    let usage = payment_engine(&["--strict", "--no-such-flag", input]);
+    assert_ne!(usage.status.code(), output.status.code());
+}
+
+#[test]
+fn test_headerless_input() {
+    let input = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/data/headerless.csv");
+
+    // Test: the first transaction is not silently taken as the header line
+    let output = payment_engine(&[input]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(output.stdout.is_empty());
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("missing CSV header columns: type, client, tx, amount"));
+}
+
+#[test]
+fn test_multiple_input_files() {
+    let deposits = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/data/split_deposits.csv");
+    let disputes = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/data/split_disputes.csv");
+
+    // Test: a dispute in the second file refers to a deposit in the first one
+    let output = payment_engine(&[deposits, disputes]);
+    assert_eq!(output.status.code(), Some(0));
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let mut records: Vec<_> = stdout.lines().collect();
+    records[1..].sort_unstable();
+    assert_eq!(records, ["client,available,held,total,locked", "1,1,5,6,false", "2,2,0,2,false"]);
+
+    // Test: files processed in argument order, the dispute coming first is discarded
+    let output = payment_engine(&[disputes, deposits]);
+    assert_eq!(output.status.code(), Some(0));
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let mut records: Vec<_> = stdout.lines().collect();
+    records[1..].sort_unstable();
+    assert_eq!(records, ["client,available,held,total,locked", "1,6,0,6,false", "2,2,0,2,false"]);
+}
+
+#[test]
+fn test_resume_state() {
+    let deposits = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/data/split_deposits.csv");
+    let disputes = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/data/split_disputes.csv");
+    let state = std::env::temp_dir().join(format!("payment-engine-state-{}.json", std::process::id()));
+    let state = state.to_str().unwrap();
+
+    // Test: a missing state file starts afresh
+    let output = payment_engine(&["--state-in", state, "--state-out", state, deposits]);
+    assert_eq!(output.status.code(), Some(0));
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let mut records: Vec<_> = stdout.lines().collect();
+    records[1..].sort_unstable();
+    assert_eq!(records, ["client,available,held,total,locked", "1,5,0,5,false", "2,2,0,2,false"]);
+
+    // Test: the dispute in the second run refers to a deposit of the first one
+    let output = payment_engine(&["--state-in", state, "--state-out", state, disputes]);
+    assert_eq!(output.status.code(), Some(0));
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let mut records: Vec<_> = stdout.lines().collect();
+    records[1..].sort_unstable();
+    assert_eq!(records, ["client,available,held,total,locked", "1,1,5,6,false", "2,2,0,2,false"]);
+
+    // Test: an invalid state file is an error
+    std::fs::write(state, "not a processor state").unwrap();
+    let output = payment_engine(&["--state-in", state, disputes]);
+    std::fs::remove_file(state).unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert!(output.stdout.is_empty());
+}
+
+#[cfg(feature = "gzip")]
+#[test]
+fn test_input_dir() {
+    let data = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/data");
+    let dir = std::env::temp_dir().join(format!("payment-engine-input-dir-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+
+    // daily files, mixing compressed and plain ones, listed in reverse order
+    std::fs::copy(format!("{data}/split_disputes.csv"), dir.join("2024-01-02.csv")).unwrap();
+    std::fs::copy(format!("{data}/split_deposits.csv.gz"), dir.join("2024-01-01.csv.gz")).unwrap();
+    std::fs::write(dir.join("README.txt"), "not a payment input").unwrap();
+
+    // Test: files processed in filename order, the dispute in the plain file refers to a deposit in the compressed one
+    let output = payment_engine(&["--input-dir", dir.to_str().unwrap()]);
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(output.status.code(), Some(0));
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let mut records: Vec<_> = stdout.lines().collect();
+    records[1..].sort_unstable();
+    assert_eq!(records, ["client,available,held,total,locked", "1,1,5,6,false", "2,2,0,2,false"]);
+
+    // Test: input files and directory are exclusive
+    let output = payment_engine(&[
+        "--input-dir",
+        data,
+        concat!(env!("CARGO_MANIFEST_DIR"), "/tests/data/split_deposits.csv"),
+    ]);
+    assert_eq!(output.status.code(), Some(2));
+}
@@ -9,9 +9,24 @@ pub enum Error {
     Io(#[from] std::io::Error),
     #[error(transparent)]
     Csv(#[from] csv_async::Error),
-    // #[error(transparent)]
-    // Process(#[from] crate::process::Error),
+    #[cfg(feature = "json")]
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[cfg(feature = "parquet")]
+    #[error(transparent)]
+    Parquet(#[from] parquet::errors::ParquetError),
+    #[error(transparent)]
+    Process(#[from] crate::process::Error),
 }
 
 /// Convenient alias for a crate result.
 pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// An account status error, ie. funds which cannot be moved.
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum AccountError {
+    #[error("account locked")]
+    Locked,
+    #[error(transparent)]
+    Funds(#[from] crate::num::Error),
+}
@@ -1,23 +1,338 @@
 //! A module providing transaction I/O features.
 
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+
 use csv_async::{AsyncDeserializer, AsyncReaderBuilder, AsyncSerializer, AsyncWriterBuilder, Trim};
-use futures::stream::TryStreamExt;
+use futures::stream::{Stream, StreamExt, TryStreamExt};
+use serde::Serialize;
 use tokio::io;
 
+use crate::{Processor, TransactionHandler};
+
+#[cfg(feature = "parquet")]
+pub mod parquet;
+
+/// Expected CSV columns of a transaction, in their standard order.
+const COLUMNS: [&str; 4] = ["type", "client", "tx", "amount"];
+/// Optional CSV columns of a transaction, still deserialized by name.
+const OPTIONAL_COLUMNS: [&str; 1] = ["timestamp"];
+
 /// Configure a CSV reader to initiate a transaction process.
+///
+/// A leading UTF-8 byte order mark is skipped, as well as blank lines between records.
 pub fn reader(rdr: impl io::AsyncRead + Send + Unpin) -> io::Result<AsyncDeserializer<impl io::AsyncRead>> {
     // let rdr = io::BufReader::new(rdr); // CSV reader is already buffered
 
-    let reader = AsyncReaderBuilder::default()
-        .trim(Trim::All)
-        .end_on_io_error(true)
-        .has_headers(true)
-        .flexible(false)
-        .create_deserializer(rdr);
+    let reader = reader_builder().create_deserializer(Sanitize::new(rdr));
+
+    Ok(reader)
+}
+
+/// Configure a headerless CSV reader to initiate a transaction process, columns being expected in the `type,client,tx,amount` order.
+pub fn reader_headerless(rdr: impl io::AsyncRead + Send + Unpin) -> io::Result<AsyncDeserializer<impl io::AsyncRead>> {
+    let reader = reader_builder().has_headers(false).create_deserializer(Sanitize::new(rdr));
+
+    Ok(reader)
+}
+
+/// A layout of the header line of CSV transactions.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HeaderLayout {
+    /// Expected columns in the expected order, ie. `type,client,tx,amount`.
+    Standard,
+    /// Expected columns in another order or along with extra columns, which are still deserialized by name.
+    Reordered,
+}
+
+/// Classify the header line of a CSV reader, before any transaction is read.
+///
+/// Should any expected column be missing, eg. from a headerless input whose first line is a transaction, an
+/// `io::ErrorKind::InvalidData` is returned rather than this transaction being silently taken as the header line.
+pub async fn header_layout<R: io::AsyncRead + Send + Unpin>(reader: &mut AsyncDeserializer<R>) -> crate::Result<HeaderLayout> {
+    let headers = reader.headers().await?;
+
+    let missing: Vec<_> = COLUMNS
+        .into_iter()
+        .filter(|column| !headers.iter().any(|header| header == *column))
+        .collect();
+    if !missing.is_empty() {
+        let message = format!("missing CSV header columns: {}", missing.join(", "));
+        return Err(io::Error::new(io::ErrorKind::InvalidData, message).into());
+    }
+
+    match headers.iter().eq(COLUMNS) {
+        true => Ok(HeaderLayout::Standard),
+        false => Ok(HeaderLayout::Reordered),
+    }
+}
+
+/// Configure a CSV reader to initiate a transaction process, detecting its delimiter from the header line.
+///
+/// Any of comma, tab, semicolon or pipe is considered a candidate delimiter, the one splitting the header line into all the
+/// expected columns being picked, extra columns such as `timestamp` being allowed. Should no candidate or several ones be found, an `io::ErrorKind::InvalidData` is returned.
+pub async fn reader_autodetect(rdr: impl io::AsyncRead + Send + Unpin) -> io::Result<AsyncDeserializer<impl io::AsyncRead>> {
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt};
+
+    const DELIMITERS: [u8; 4] = [b',', b'\t', b';', b'|'];
+
+    let mut rdr = io::BufReader::new(Sanitize::new(rdr));
+    let mut header = Vec::new();
+    rdr.read_until(b'\n', &mut header).await?;
+
+    let delimiter = if header.trim_ascii().is_empty() {
+        b',' // no header at all, any delimiter would do
+    } else {
+        let mut candidates = DELIMITERS.into_iter().filter(|delimiter| {
+            let fields: Vec<_> = header.split(|byte| byte == delimiter).map(<[u8]>::trim_ascii).collect();
+            COLUMNS.iter().all(|column| fields.contains(&column.as_bytes()))
+        });
+
+        let delimiter = match (candidates.next(), candidates.next()) {
+            (Some(delimiter), None) => Some(delimiter),
+            _ => None, // no candidate or ambiguous ones
+        };
+
+        delimiter.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "cannot detect CSV delimiter from header"))?
+    };
+
+    let reader = reader_builder()
+        .delimiter(delimiter)
+        .create_deserializer(std::io::Cursor::new(header).chain(rdr));
 
     Ok(reader)
 }
 
+/// Configure a gzip-compressed CSV reader to initiate a transaction process.
+#[cfg(feature = "gzip")]
+pub fn gzip_reader(rdr: impl io::AsyncRead + Send + Unpin) -> io::Result<AsyncDeserializer<impl io::AsyncRead>> {
+    reader(gzip_decoder(rdr))
+}
+
+/// Decompress a gzip-compressed input, eg. to type-erase it alongside uncompressed inputs before configuring a CSV reader.
+#[cfg(feature = "gzip")]
+pub fn gzip_decoder(rdr: impl io::AsyncRead + Send + Unpin) -> impl io::AsyncRead + Send + Unpin {
+    async_compression::tokio::bufread::GzipDecoder::new(io::BufReader::new(rdr))
+}
+
+/// Chain the transactions of several CSV readers, each reader being read in turn in the given order.
+pub fn chain_readers<R>(readers: impl IntoIterator<Item = AsyncDeserializer<R>>) -> impl Stream<Item = crate::Result<crate::Transaction>>
+where
+    R: io::AsyncRead + Send + Unpin + 'static,
+{
+    futures::stream::iter(readers).flat_map(|reader| reader.into_deserialize().err_into())
+}
+
+/// A value tagged with passthrough metadata, eg. a transaction along with the extra CSV columns of its record.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Tagged<T> {
+    /// The tagged value.
+    pub value: T,
+    metadata: HashMap<String, String>,
+}
+
+impl<T> Tagged<T> {
+    /// Tag a value, with no metadata yet.
+    #[inline]
+    pub fn new(value: T) -> Self {
+        Self {
+            value,
+            metadata: HashMap::new(),
+        }
+    }
+
+    /// Build a tagged value with a metadata entry, eg. a `source` of `api`, replacing any previous value of `key`.
+    pub fn with_metadata(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.metadata.insert(key.into(), value.into());
+        self
+    }
+
+    /// Get the metadata value of `key`, if any.
+    #[inline]
+    pub fn metadata(&self, key: &str) -> Option<&str> {
+        self.metadata.get(key).map(String::as_str)
+    }
+
+    /// Untag this value, dropping its metadata.
+    #[inline]
+    pub fn into_inner(self) -> T {
+        self.value
+    }
+}
+
+/// Read the transactions of a CSV reader, tagging each one with any extra column as passthrough metadata, eg. a `source` column to
+/// be filtered with `only_sources()`.
+///
+/// Extra columns are captured by header name, hence a header line is expected. As with `AsyncDeserializer::into_deserialize()`,
+/// an invalid record is emitted as an error without ending the stream.
+pub fn into_tagged_transactions<R>(reader: AsyncDeserializer<R>) -> impl Stream<Item = crate::Result<Tagged<crate::Transaction>>>
+where
+    R: io::AsyncRead + Send + Unpin,
+{
+    async fn read_tagged<R: io::AsyncRead + Send + Unpin>(
+        reader: &mut AsyncDeserializer<R>,
+        record: &mut csv_async::StringRecord,
+    ) -> crate::Result<Option<Tagged<crate::Transaction>>> {
+        if !reader.read_record(record).await? {
+            return Ok(None);
+        }
+
+        let headers = reader.headers().await?;
+        let transaction: crate::Transaction = record.deserialize(Some(headers))?;
+
+        let metadata = headers
+            .iter()
+            .zip(record.iter())
+            .filter(|(column, _)| !COLUMNS.contains(column) && !OPTIONAL_COLUMNS.contains(column));
+        Ok(Some(metadata.fold(Tagged::new(transaction), |tagged, (column, value)| {
+            tagged.with_metadata(column, value)
+        })))
+    }
+
+    futures::stream::unfold((reader, csv_async::StringRecord::new()), |(mut reader, mut record)| async move {
+        let transaction = read_tagged(&mut reader, &mut record).await.transpose()?;
+        Some((transaction, (reader, record)))
+    })
+}
+
+/// Only keep tagged transactions whose `source` metadata is one of `sources`, eg. `api`, untagging them to be processed.
+///
+/// Other transactions, including ones without any `source` metadata, are dropped as if absent from the input, while stream errors
+/// are passed through.
+pub fn only_sources(
+    transactions: impl Stream<Item = crate::Result<Tagged<crate::Transaction>>>,
+    sources: &[&str],
+) -> impl Stream<Item = crate::Result<crate::Transaction>> {
+    let sources: HashSet<String> = sources.iter().map(|source| source.to_string()).collect();
+
+    transactions.try_filter_map(move |tagged| {
+        let kept = tagged.metadata("source").is_some_and(|source| sources.contains(source));
+        futures::future::ready(Ok(kept.then(|| tagged.into_inner())))
+    })
+}
+
+/// Deduplicate a stream of accounts, only retaining the last account emitted per client, eg. when accounts are emitted incrementally.
+///
+/// As the whole stream has to be buffered, nothing is emitted until it ends. Retained accounts are emitted in the order their clients
+/// first appeared, while the first error, if any, is emitted instead of any account.
+pub fn dedup_accounts_last_wins(
+    accounts: impl Stream<Item = crate::Result<crate::Account>>,
+) -> impl Stream<Item = crate::Result<crate::Account>> {
+    use std::collections::{hash_map::Entry, HashMap};
+
+    use futures::TryFutureExt;
+
+    accounts
+        .try_fold(
+            (Vec::new(), HashMap::new()),
+            |(mut accounts, mut positions), account: crate::Account| async move {
+                match positions.entry(account.client) {
+                    Entry::Occupied(position) => accounts[*position.get()] = account,
+                    Entry::Vacant(position) => {
+                        position.insert(accounts.len());
+                        accounts.push(account);
+                    }
+                }
+                Ok((accounts, positions))
+            },
+        )
+        .map_ok(|(accounts, _)| futures::stream::iter(accounts).map(Ok))
+        .try_flatten_stream()
+}
+
+/// Configure a CSV reader builder shared by all transaction processes.
+fn reader_builder() -> AsyncReaderBuilder {
+    let mut builder = AsyncReaderBuilder::default();
+    builder.trim(Trim::All).end_on_io_error(true).has_headers(true).flexible(false);
+
+    builder
+}
+
+/// A reader skipping a leading UTF-8 byte order mark, which would otherwise stick to the first header, as well as the leading blanks
+/// of each line, so that whitespace-only lines get skipped as empty ones.
+struct Sanitize<R> {
+    inner: R,
+    head: [u8; 3],
+    filled: usize,
+    pos: Option<usize>, // position of the next head byte to be read, once the head is known
+    line_start: bool,
+}
+
+impl<R: io::AsyncRead + Unpin> Sanitize<R> {
+    const BOM: [u8; 3] = [0xEF, 0xBB, 0xBF];
+
+    fn new(inner: R) -> Self {
+        Self {
+            inner,
+            head: [0; 3],
+            filled: 0,
+            pos: None,
+            line_start: true,
+        }
+    }
+
+    /// Read the input, past its BOM if any.
+    fn poll_read_bom(&mut self, cx: &mut std::task::Context<'_>, buf: &mut io::ReadBuf<'_>) -> std::task::Poll<io::Result<()>> {
+        // read up to the BOM length, stopping as soon as the input cannot start with a BOM
+        while self.pos.is_none() {
+            let mut head = io::ReadBuf::new(&mut self.head[self.filled..]);
+            std::task::ready!(std::pin::Pin::new(&mut self.inner).poll_read(cx, &mut head))?;
+
+            let read = head.filled().len();
+            self.filled += read;
+
+            if self.head == Self::BOM {
+                self.pos = Some(Self::BOM.len());
+            } else if read == 0 || self.filled == Self::BOM.len() || self.head[..self.filled] != Self::BOM[..self.filled] {
+                self.pos = Some(0);
+            }
+        }
+
+        match self.pos {
+            Some(pos) if pos < self.filled => {
+                let len = usize::min(self.filled - pos, buf.remaining());
+                buf.put_slice(&self.head[pos..pos + len]);
+                self.pos = Some(pos + len);
+
+                std::task::Poll::Ready(Ok(()))
+            }
+            _ => std::pin::Pin::new(&mut self.inner).poll_read(cx, buf),
+        }
+    }
+}
+
+impl<R: io::AsyncRead + Unpin> io::AsyncRead for Sanitize<R> {
+    fn poll_read(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        buf: &mut io::ReadBuf<'_>,
+    ) -> std::task::Poll<io::Result<()>> {
+        loop {
+            let start = buf.filled().len();
+            std::task::ready!(self.poll_read_bom(cx, buf))?;
+
+            // leading blanks are trimmed anyway, along with the first field
+            let read = &mut buf.filled_mut()[start..];
+            let mut kept = 0;
+            for i in 0..read.len() {
+                if self.line_start && matches!(read[i], b' ' | b'\t') {
+                    continue;
+                }
+                self.line_start = read[i] == b'\n';
+                read[kept] = read[i];
+                kept += 1;
+            }
+
+            // nothing read means the input end, unlike blanks only
+            let end = read.is_empty();
+            buf.set_filled(start + kept);
+            if end || kept > 0 {
+                return std::task::Poll::Ready(Ok(()));
+            }
+        }
+    }
+}
+
 /// Configure a CSV writer to initiate a transaction process.
 pub fn writer(wtr: impl io::AsyncWrite + Unpin) -> io::Result<AsyncSerializer<impl io::AsyncWrite>> {
     let writer = AsyncWriterBuilder::default()
@@ -28,35 +343,882 @@ pub fn writer(wtr: impl io::AsyncWrite + Unpin) -> io::Result<AsyncSerializer<im
     Ok(writer)
 }
 
+/// A writer configuration, to flush records in a timely manner, eg. when piping them to a live consumer.
+///
+/// Records are flushed as soon as any of the configured conditions is met, and always once all of them have been written.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct WriterConfig {
+    /// Flush every N records written, if any.
+    pub flush_every: Option<usize>,
+    /// Flush records written for at least this time interval, if any.
+    pub flush_interval: Option<Duration>,
+    /// Keep writing records after a record failed to be written, rather than aborting, see `write_records()`.
+    pub resilient: bool,
+    /// Zero-pad client IDs of records to this width, if any, see `PaddedClient`.
+    ///
+    /// Honored by transaction processes, eg. `process_with_config()`, while `write_records()` writes records as they are.
+    pub client_id_width: Option<usize>,
+}
+
+/// A record wrapper serializing balances with all places past the decimal, eg. `1.1000` rather than `1.1`, as expected by some
+/// downstream parsers.
+///
+/// Any stream of accounts can be written in fixed precision by wrapping its items, eg. `accounts.map_ok(FixedPrecision)`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FixedPrecision<T>(pub T);
+
+impl Serialize for FixedPrecision<crate::Balance> {
+    #[inline]
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(&format_args!("{:#}", self.0))
+    }
+}
+
+impl Serialize for FixedPrecision<crate::BalanceTotal> {
+    #[inline]
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(&format_args!("{:#}", self.0))
+    }
+}
+
+impl Serialize for FixedPrecision<crate::Account> {
+    #[inline]
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        FixedPrecisionRecord::from(self.0.clone()).serialize(serializer)
+    }
+}
+
+/// An account record with balances in fixed precision.
+type FixedPrecisionRecord = crate::AccountRecord<FixedPrecision<crate::Balance>, FixedPrecision<crate::BalanceTotal>>;
+
+impl From<crate::Account> for FixedPrecisionRecord {
+    fn from(account: crate::Account) -> Self {
+        let record = <crate::AccountRecord>::from(account);
+
+        Self {
+            client: record.client,
+            available: FixedPrecision(record.available),
+            held: FixedPrecision(record.held),
+            total: FixedPrecision(record.total),
+            locked: record.locked,
+        }
+    }
+}
+
+/// A record wrapper serializing its client ID zero-padded to a fixed width, eg. `00001` for a width of 5, as expected by some
+/// fixed-width downstream parsers. Wider client IDs are left untouched.
+///
+/// Any stream of accounts can be written with padded client IDs by wrapping its items, eg. `accounts.map_ok(|a| PaddedClient(a, 5))`,
+/// possibly along with other record wrappers, eg. `PaddedClient(FixedPrecision(account), 5)`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PaddedClient<T>(pub T, pub usize);
+
+impl Serialize for PaddedClient<crate::Account> {
+    #[inline]
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        <crate::AccountRecord>::from(self.0.clone()).padded(self.1).serialize(serializer)
+    }
+}
+
+impl Serialize for PaddedClient<FixedPrecision<crate::Account>> {
+    #[inline]
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        FixedPrecisionRecord::from(self.0 .0.clone()).padded(self.1).serialize(serializer)
+    }
+}
+
+/// A record produced by a transaction handler, whose client ID could be zero-padded as configured by `WriterConfig::client_id_width`.
+pub trait ClientRecord: Serialize + Sized {
+    /// Get this record with its client ID zero-padded to `width`, left as it is by default, eg. for a record with no client ID.
+    fn padded(self, width: usize) -> impl Serialize {
+        let _ = width;
+        self
+    }
+}
+
+impl ClientRecord for crate::Account {
+    #[inline]
+    fn padded(self, width: usize) -> impl Serialize {
+        PaddedClient(self, width)
+    }
+}
+
+impl ClientRecord for FixedPrecision<crate::Account> {
+    #[inline]
+    fn padded(self, width: usize) -> impl Serialize {
+        PaddedClient(self, width)
+    }
+}
+
+impl<B, T> crate::AccountRecord<B, T> {
+    /// Zero-pad the client ID of this record to `width`.
+    fn padded(self, width: usize) -> crate::AccountRecord<B, T, String> {
+        crate::AccountRecord {
+            client: format!("{:0width$}", self.client),
+            available: self.available,
+            held: self.held,
+            total: self.total,
+            locked: self.locked,
+        }
+    }
+}
+
+/// Configure a JSON-lines reader to initiate a transaction process, one transaction per line.
+///
+/// Blank lines are skipped, while amounts are expected as strings, eg. `{"type":"deposit","client":1,"tx":1,"amount":"1.5"}`,
+/// so that no precision is lost.
+#[cfg(feature = "json")]
+pub fn json_reader(rdr: impl io::AsyncRead + Unpin) -> impl Stream<Item = crate::Result<crate::Transaction>> {
+    use tokio::io::AsyncBufReadExt;
+
+    futures::stream::try_unfold(io::BufReader::new(rdr).lines(), |mut lines| async move {
+        while let Some(line) = lines.next_line().await? {
+            if !line.trim().is_empty() {
+                return Ok(Some((serde_json::from_str(&line)?, lines)));
+            }
+        }
+
+        Ok(None)
+    })
+}
+
+/// Write all records of a transaction process as JSON lines, one record per line.
+#[cfg(feature = "json")]
+pub async fn json_writer(
+    records: impl Stream<Item = crate::Result<impl Serialize>>,
+    wtr: impl io::AsyncWrite + Unpin,
+) -> crate::Result<()> {
+    use tokio::io::AsyncWriteExt;
+
+    tokio::pin!(records);
+    let mut wtr = io::BufWriter::new(wtr);
+
+    while let Some(record) = records.try_next().await? {
+        let mut line = serde_json::to_vec(&record)?;
+        line.push(b'\n');
+
+        wtr.write_all(&line).await?;
+    }
+
+    wtr.flush().await?;
+
+    Ok(())
+}
+
+/// A reconciliation trailer, summarizing grand totals of output accounts so that downstream consumers can checksum them.
+///
+/// It is written as a final CSV record after all accounts, with the same columns but marked by a `total` client,
+/// its `locked` column holding the number of locked accounts, eg. `total,0.9,1,1.9,1`.
+#[derive(Clone, Debug, Serialize, PartialEq, Eq)]
+pub struct Trailer {
+    client: &'static str,
+    /// Total available funds of output accounts.
+    pub available: crate::BalanceTotal,
+    /// Total held funds of output accounts.
+    pub held: crate::BalanceTotal,
+    /// Total funds of output accounts.
+    pub total: crate::BalanceTotal,
+    /// Number of locked output accounts.
+    pub locked: usize,
+}
+
+impl Trailer {
+    /// Sum up output accounts.
+    fn new(accounts: &[crate::Account]) -> Self {
+        use crate::BalanceTotal;
+
+        let mut trailer = Self {
+            client: "total",
+            available: BalanceTotal::ZERO,
+            held: BalanceTotal::ZERO,
+            total: BalanceTotal::ZERO,
+            locked: 0,
+        };
+
+        for account in accounts {
+            trailer.available += account.status.available;
+            trailer.held += account.status.held;
+            trailer.total += account.status.total();
+            trailer.locked += usize::from(account.status.locked);
+        }
+
+        trailer
+    }
+}
+
+/// Run a transaction process with a configured processor, writing a reconciliation `Trailer` after all output accounts.
+pub async fn process_with_trailer<R, W>(processor: Processor, reader: AsyncDeserializer<R>, writer: AsyncSerializer<W>) -> crate::Result<()>
+where
+    R: io::AsyncRead + Send + Unpin,
+    W: io::AsyncWrite + Unpin,
+{
+    /// An output record, either an account or the final trailer.
+    #[derive(Serialize)]
+    #[serde(untagged)]
+    enum Record {
+        Account(crate::Account),
+        Trailer(Trailer),
+    }
+
+    let accounts: Vec<_> = processor.process_stream(reader.into_deserialize().err_into()).try_collect().await?;
+    let trailer = Trailer::new(&accounts);
+
+    let records = accounts.into_iter().map(Record::Account).chain([Record::Trailer(trailer)]);
+    write_records(futures::stream::iter(records).map(Ok), writer, WriterConfig::default()).await?;
+
+    Ok(())
+}
+
+/// A summary of a transaction process run.
+#[cfg(feature = "json")]
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct RunSummary {
+    /// Counts of processed transactions and errors.
+    pub metrics: crate::process::Metrics,
+    /// Number of output accounts.
+    pub accounts: usize,
+    /// Total available funds of output accounts.
+    pub available: crate::BalanceTotal,
+    /// Total held funds of output accounts.
+    pub held: crate::BalanceTotal,
+    /// Total funds of output accounts.
+    pub total: crate::BalanceTotal,
+    /// Clients of locked output accounts, sorted by client ID.
+    pub locked_clients: Vec<crate::ClientID>,
+}
+
+#[cfg(feature = "json")]
+impl RunSummary {
+    /// Summarize a run from the metrics of its processor and its output accounts.
+    fn new(metrics: crate::process::Metrics, accounts: &[crate::Account]) -> Self {
+        let Trailer {
+            available, held, total, ..
+        } = Trailer::new(accounts);
+
+        let mut locked_clients: Vec<_> = accounts
+            .iter()
+            .filter(|account| account.status.locked)
+            .map(|account| account.client)
+            .collect();
+        locked_clients.sort_unstable();
+
+        Self {
+            metrics,
+            accounts: accounts.len(),
+            available,
+            held,
+            total,
+            locked_clients,
+        }
+    }
+}
+
+/// Run a transaction process, writing output accounts as CSV and a `RunSummary` of this run as JSON.
+#[cfg(feature = "json")]
+pub async fn process_with_summary<R, W, S>(
+    reader: AsyncDeserializer<R>,
+    accounts_writer: AsyncSerializer<W>,
+    mut summary_writer: S,
+) -> crate::Result<()>
+where
+    R: io::AsyncRead + Send + Unpin,
+    W: io::AsyncWrite + Unpin,
+    S: io::AsyncWrite + Unpin,
+{
+    use tokio::io::AsyncWriteExt;
+
+    let processor = Processor::default().handle_all(reader.into_deserialize().err_into()).await?;
+
+    let metrics = processor.metrics().clone();
+    let accounts: Vec<_> = processor.into_records().collect();
+    let summary = RunSummary::new(metrics, &accounts);
+
+    write_records(futures::stream::iter(accounts).map(Ok), accounts_writer, WriterConfig::default()).await?;
+
+    summary_writer.write_all(&serde_json::to_vec(&summary)?).await?;
+    summary_writer.flush().await?;
+
+    Ok(())
+}
+
+/// A rejected transaction record, ie. the transaction along with the code of its error.
+#[derive(Debug, Serialize)]
+struct RejectRecord {
+    #[serde(rename = "type")]
+    r#type: crate::TransactionType,
+    client: crate::ClientID,
+    tx: crate::TransactionID,
+    amount: Option<crate::Amount>,
+    error: &'static str,
+}
+
+impl From<(crate::Transaction, crate::process::Error)> for RejectRecord {
+    fn from((transaction, err): (crate::Transaction, crate::process::Error)) -> Self {
+        Self {
+            r#type: transaction.r#type,
+            client: transaction.client,
+            tx: transaction.tx,
+            amount: transaction.amount,
+            error: err.code(),
+        }
+    }
+}
+
+/// Run a transaction process, writing output accounts and rejected transactions to separate CSV writers.
+///
+/// Each rejected transaction is written as is, along with the code of its error, eg. `withdrawal,1,3,4.2,not-enough-funds`.
+pub async fn process_with_rejects<R, W, E>(
+    reader: AsyncDeserializer<R>,
+    accounts_writer: AsyncSerializer<W>,
+    rejects_writer: AsyncSerializer<E>,
+) -> crate::Result<()>
+where
+    R: io::AsyncRead + Send + Unpin,
+    W: io::AsyncWrite + Unpin,
+    E: io::AsyncWrite + Unpin,
+{
+    let mut processor = Processor::default()
+        .with_reject_log(true)
+        .handle_all(reader.into_deserialize().err_into())
+        .await?;
+
+    let rejects = processor.take_rejects().into_iter().map(|reject| Ok(RejectRecord::from(reject)));
+    write_records(
+        futures::stream::iter(processor.into_records()).map(Ok),
+        accounts_writer,
+        WriterConfig::default(),
+    )
+    .await?;
+    write_records(futures::stream::iter(rejects), rejects_writer, WriterConfig::default()).await?;
+
+    Ok(())
+}
+
 /// Run a transaction process.
-pub async fn process<R, W>(reader: AsyncDeserializer<R>, mut writer: AsyncSerializer<W>) -> crate::Result<()>
+#[inline]
+pub async fn process<R, W>(reader: AsyncDeserializer<R>, writer: AsyncSerializer<W>) -> crate::Result<()>
 where
     R: io::AsyncRead + Send + Unpin,
     W: io::AsyncWrite + Unpin,
 {
-    let stream = crate::Processor::process(reader.into_deserialize().err_into());
+    process_with(Processor::default(), reader, writer).await
+}
+
+/// Run a transaction process with a configured processor, or any other transaction handler.
+pub async fn process_with<H, R, W>(handler: H, reader: AsyncDeserializer<R>, writer: AsyncSerializer<W>) -> crate::Result<()>
+where
+    H: TransactionHandler,
+    H::Record: ClientRecord,
+    R: io::AsyncRead + Send + Unpin,
+    W: io::AsyncWrite + Unpin,
+{
+    process_with_config(handler, reader, writer, WriterConfig::default()).await?;
+
+    Ok(())
+}
+
+/// Run a transaction process with a configured processor, or any other transaction handler, writing records as configured.
+///
+/// The number of write errors is returned, see `write_records()`.
+pub async fn process_with_config<H, R, W>(
+    handler: H,
+    reader: AsyncDeserializer<R>,
+    writer: AsyncSerializer<W>,
+    config: WriterConfig,
+) -> crate::Result<usize>
+where
+    H: TransactionHandler,
+    H::Record: ClientRecord,
+    R: io::AsyncRead + Send + Unpin,
+    W: io::AsyncWrite + Unpin,
+{
+    let stream = handler.handle_stream(reader.into_deserialize().err_into());
+
+    match config.client_id_width {
+        Some(width) => write_records(stream.map_ok(move |record| record.padded(width)), writer, config).await,
+        None => write_records(stream, writer, config).await,
+    }
+}
+
+/// Run a transaction process with a configured processor, or any other transaction handler, reading and writing in stages.
+///
+/// Reading/deserializing transactions, handling them and serializing/writing records run concurrently, each stage being
+/// buffered up to `buffer` items ahead of the next one, eg. so that transactions are deserialized while previous ones are handled.
+/// Transactions are still handled, and records written, in order: the output is the same as with `process_with_config()`.
+///
+/// The number of write errors is returned, see `write_records()`.
+pub async fn process_buffered<H, R, W>(
+    handler: H,
+    reader: AsyncDeserializer<R>,
+    writer: AsyncSerializer<W>,
+    config: WriterConfig,
+    buffer: usize,
+) -> crate::Result<usize>
+where
+    H: TransactionHandler,
+    H::Record: ClientRecord,
+    R: io::AsyncRead + Send + Unpin,
+    W: io::AsyncWrite + Unpin,
+{
+    use futures::channel::mpsc;
+
+    let (transactions_tx, transactions_rx) = mpsc::channel(buffer);
+    let (records_tx, records_rx) = mpsc::channel(buffer);
+
+    // a stage only fails to send once the next one has aborted, which is then reported by the writing stage
+    let read = reader.into_deserialize().err_into().map(Ok).forward(transactions_tx);
+    let handle = handler.handle_stream(transactions_rx).map(Ok).forward(records_tx);
+    let write = async {
+        match config.client_id_width {
+            Some(width) => write_records(records_rx.map_ok(move |record| record.padded(width)), writer, config).await,
+            None => write_records(records_rx, writer, config).await,
+        }
+    };
+
+    let (_, _, written) = futures::join!(read, handle, write);
+
+    written
+}
+
+/// Run a transaction process with a configured processor, or any other transaction handler, aborting on the first transaction error.
+///
+/// Such an error is returned as a `crate::Error::Process`, while no record at all is written.
+pub async fn process_strict_with<H, R, W>(handler: H, reader: AsyncDeserializer<R>, writer: AsyncSerializer<W>) -> crate::Result<()>
+where
+    H: TransactionHandler,
+    H::Record: Serialize,
+    R: io::AsyncRead + Send + Unpin,
+    W: io::AsyncWrite + Unpin,
+{
+    let stream = handler.handle_stream_strict(reader.into_deserialize().err_into());
+
+    write_records(stream, writer, WriterConfig::default()).await?;
+
+    Ok(())
+}
+
+/// Write all records of a transaction process, flushing them as configured.
+///
+/// The writer is flushed even if the process fails, so that any record written so far is not lost.
+///
+/// In resilient mode, a write error, eg. on a transient I/O error, is logged and counted while the remaining records are still
+/// written. The count does not tell the number of lost records though: bytes of the record in error could still be retried by
+/// the underlying CSV writer along with the next record, possibly duplicating bytes should the error occur after a partial write.
+/// The number of write errors is returned, which is always `0` otherwise.
+pub async fn write_records<W>(
+    stream: impl Stream<Item = crate::Result<impl Serialize>>,
+    mut writer: AsyncSerializer<W>,
+    config: WriterConfig,
+) -> crate::Result<usize>
+where
+    W: io::AsyncWrite + Unpin,
+{
+    use tokio::time::{interval_at, Instant, MissedTickBehavior};
+
     tokio::pin!(stream);
 
-    while let Some(record) = stream.try_next().await? {
-        writer.serialize(record).await?;
+    let mut ticker = config.flush_interval.map(|period| {
+        let mut ticker = interval_at(Instant::now() + period, period);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        ticker
+    });
+
+    let written: crate::Result<usize> = async {
+        let (mut pending, mut errors) = (0, 0);
+
+        loop {
+            tokio::select! {
+                record = stream.try_next() => {
+                    let Some(record) = record? else { break };
+
+                    match writer.serialize(record).await {
+                        Err(err) if config.resilient => {
+                            tracing::error!("Record failed to be written: {err}.");
+                            errors += 1;
+                            continue;
+                        }
+                        result => result?,
+                    }
+                    pending += 1;
+
+                    if config.flush_every.is_some_and(|flush_every| pending >= flush_every) {
+                        writer.flush().await?;
+                        pending = 0;
+
+                        if let Some(ticker) = &mut ticker {
+                            ticker.reset();
+                        }
+                    }
+                }
+                _ = async { ticker.as_mut().unwrap().tick().await }, if ticker.is_some() && pending > 0 => {
+                    writer.flush().await?;
+                    pending = 0;
+                }
+            }
+        }
+
+        Ok(errors)
     }
+    .await;
 
     writer.flush().await?;
 
-    Ok(())
+    written
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    use assert_matches::assert_matches;
+
+    use crate::{AccountStatus, Amount, Balance, Transaction, TransactionType};
+
     async fn test_process(input: &[u8], output: &mut Vec<u8>) -> crate::Result<()> {
+        test_process_with(Processor::default(), input, output).await
+    }
+
+    async fn test_process_with(
+        handler: impl TransactionHandler<Record: ClientRecord>,
+        input: &[u8],
+        output: &mut Vec<u8>,
+    ) -> crate::Result<()> {
         let buffer = std::io::Cursor::new(output);
 
         let reader = AsyncDeserializer::from_reader(input);
         let writer = AsyncSerializer::from_writer(buffer);
 
-        process(reader, writer).await
+        process_with(handler, reader, writer).await
+    }
+
+    /// Collect output records, sorted to get rid of accounts ordering.
+    fn sorted_records(output: &[u8]) -> Vec<&str> {
+        let mut records: Vec<_> = std::str::from_utf8(output).unwrap().lines().collect();
+        records[1..].sort_unstable();
+        records
+    }
+
+    /// Process transactions then write output accounts, each one wrapped as a record with `wrap`.
+    async fn output<T: Serialize>(transactions: &str, wrap: impl Fn(crate::Account) -> T) -> String {
+        let stream = Processor::default().process_stream(reader(transactions.as_bytes()).unwrap().into_deserialize().err_into());
+        let mut data = vec![];
+        write_records(stream.map_ok(wrap), writer(&mut data).unwrap(), WriterConfig::default())
+            .await
+            .unwrap();
+        String::from_utf8(data).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_transaction_columns() {
+        let input = "type,client,tx,amount\ndeposit,1,2,3.0\ndispute,1,2,\n";
+
+        let transactions: Vec<Transaction> = reader(input.as_bytes()).unwrap().into_deserialize().try_collect().await.unwrap();
+        assert_eq!(transactions.len(), 2);
+        assert_eq!(
+            (transactions[0].r#type, transactions[0].client, transactions[0].tx),
+            (TransactionType::Deposit, 1, 2)
+        );
+        assert_eq!(transactions[0].amount, Some(Amount::from(3)));
+        assert_eq!((transactions[1].r#type, transactions[1].amount), (TransactionType::Dispute, None));
+
+        let mut output = vec![];
+        let mut writer = writer(&mut output).unwrap();
+        for transaction in transactions {
+            writer.serialize(transaction).await.unwrap();
+        }
+        writer.flush().await.unwrap();
+        drop(writer);
+
+        assert_eq!(output, b"type,client,tx,amount\ndeposit,1,2,3\ndispute,1,2,\n");
+    }
+
+    #[tokio::test]
+    async fn test_reader_autodetect() {
+        let expected = [
+            (TransactionType::Deposit, 1, 2, Some(Amount::from(3))),
+            (TransactionType::Dispute, 1, 2, None),
+        ];
+
+        for delimiter in [",", "\t", ";", "|"] {
+            let input = ["type,client,tx,amount", "deposit,1,2,3.0", "dispute,1,2,", ""]
+                .join("\n")
+                .replace(',', delimiter);
+
+            let transactions: Vec<Transaction> = reader_autodetect(input.as_bytes())
+                .await
+                .unwrap()
+                .into_deserialize()
+                .try_collect()
+                .await
+                .unwrap();
+            let transactions: Vec<_> = transactions.into_iter().map(|t| (t.r#type, t.client, t.tx, t.amount)).collect();
+            assert_eq!(transactions, expected, "delimiter {delimiter:?}");
+        }
+
+        // Test: extra columns along with the expected ones
+        let input = "type;client;tx;amount;timestamp;source\ndeposit;1;2;3.0;10;api\n";
+        let transactions: Vec<Transaction> = reader_autodetect(input.as_bytes())
+            .await
+            .unwrap()
+            .into_deserialize()
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(transactions.len(), 1);
+        assert_eq!(
+            (transactions[0].amount, transactions[0].timestamp()),
+            (Some(Amount::from(3)), Some(10))
+        );
+
+        // Test: no transaction at all
+        let transactions: Vec<Transaction> = reader_autodetect(&b""[..])
+            .await
+            .unwrap()
+            .into_deserialize()
+            .try_collect()
+            .await
+            .unwrap();
+        assert!(transactions.is_empty());
+
+        // Test: undetectable or ambiguous delimiter
+        for input in [
+            "type client tx amount\n",
+            "type,client;tx;amount\n",
+            "type,client,tx,amount|a|b|c\n",
+        ] {
+            let err = reader_autodetect(input.as_bytes()).await.err().unwrap();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData, "input {input:?}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_header_layout() {
+        async fn transactions<R: io::AsyncRead + Send + Unpin>(
+            reader: AsyncDeserializer<R>,
+        ) -> Vec<(TransactionType, u16, u32, Option<Amount>)> {
+            let transactions: Vec<Transaction> = reader.into_deserialize().try_collect().await.unwrap();
+            transactions.into_iter().map(|t| (t.r#type, t.client, t.tx, t.amount)).collect()
+        }
+
+        let expected = [
+            (TransactionType::Deposit, 1, 2, Some(Amount::from(3))),
+            (TransactionType::Dispute, 1, 2, None),
+        ];
+
+        // Test: expected columns, whatever their order
+        for (input, layout) in [
+            ("type,client,tx,amount\ndeposit,1,2,3.0\ndispute,1,2,\n", HeaderLayout::Standard),
+            ("client,tx,amount,type\n1,2,3.0,deposit\n1,2,,dispute\n", HeaderLayout::Reordered),
+            (
+                "type , client , tx , amount , note\ndeposit,1,2,3.0,a\ndispute,1,2,,b\n",
+                HeaderLayout::Reordered,
+            ),
+        ] {
+            let mut reader = reader(input.as_bytes()).unwrap();
+            assert_eq!(header_layout(&mut reader).await.unwrap(), layout, "input {input:?}");
+            assert_eq!(transactions(reader).await, expected, "input {input:?}");
+        }
+
+        // Test: headerless input
+        let input = "deposit,1,2,3.0\ndispute,1,2,\n";
+        let err = header_layout(&mut reader(input.as_bytes()).unwrap()).await.unwrap_err();
+        assert_matches!(&err, crate::Error::Io(err) if err.kind() == io::ErrorKind::InvalidData);
+        assert_eq!(err.to_string(), "missing CSV header columns: type, client, tx, amount");
+
+        assert_eq!(transactions(reader_headerless(input.as_bytes()).unwrap()).await, expected);
+
+        // Test: missing columns
+        let err = header_layout(&mut reader(&b"type,tx,amount\n"[..]).unwrap()).await.unwrap_err();
+        assert_eq!(err.to_string(), "missing CSV header columns: client");
+    }
+
+    #[tokio::test]
+    async fn test_tagged_transactions() {
+        let transactions = "type,client,tx,amount,source\n\
+                            deposit,1,1,5.0,api\n\
+                            deposit,1,2,3.0,batch\n\
+                            deposit,1,x,1.0,api\n\
+                            withdrawal,1,3,1.5,api\n\
+                            dispute,1,2,,batch\n\
+                            deposit,1,4,2.0,\n";
+
+        // Test: extra columns as metadata
+        let tagged: Vec<_> = into_tagged_transactions(reader(transactions.as_bytes()).unwrap()).collect().await;
+        assert_eq!(tagged.len(), 6);
+        assert_matches!(tagged[2], Err(crate::Error::Csv(_)));
+
+        let sources: Vec<_> = tagged.iter().flatten().map(|t| (t.value.tx, t.metadata("source"))).collect();
+        assert_eq!(
+            sources,
+            [
+                (1, Some("api")),
+                (2, Some("batch")),
+                (3, Some("api")),
+                (2, Some("batch")),
+                (4, Some(""))
+            ]
+        );
+        assert!(tagged.iter().flatten().all(|t| t.metadata("amount").is_none()));
+
+        // Test: optional columns, not as metadata
+        let timestamped = "type,client,tx,amount,timestamp,source\ndeposit,1,1,5.0,10,api\ndispute,1,1,,,api\n";
+        let tagged: Vec<_> = into_tagged_transactions(reader(timestamped.as_bytes()).unwrap())
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!((tagged[0].value.timestamp(), tagged[1].value.timestamp()), (Some(10), None));
+        assert!(tagged
+            .iter()
+            .all(|t| t.metadata("timestamp").is_none() && t.metadata("source") == Some("api")));
+
+        // Test: only `api`-sourced transactions
+        let tagged = into_tagged_transactions(reader(transactions.as_bytes()).unwrap()).filter(|t| {
+            futures::future::ready(t.is_ok()) // skip the invalid record
+        });
+        let stream = Processor::default().process_stream(only_sources(tagged, &["api"]));
+        let mut data = vec![];
+        write_records(stream, writer(&mut data).unwrap(), WriterConfig::default())
+            .await
+            .unwrap();
+        assert_eq!(data, b"client,available,held,total,locked\n1,3.5,0,3.5,false\n");
+
+        // Test: untagged transactions
+        let untagged = reader(transactions.as_bytes()).unwrap().into_deserialize().filter_map(|t| {
+            futures::future::ready(t.ok().map(|t| Ok(Tagged::new(t)))) // skip the invalid record
+        });
+        let filtered: Vec<_> = only_sources(untagged, &["api"]).try_collect().await.unwrap();
+        assert!(filtered.is_empty());
+
+        // Test: stream errors passed through
+        let tagged = into_tagged_transactions(reader(transactions.as_bytes()).unwrap());
+        let filtered: Vec<_> = only_sources(tagged, &["batch"]).collect().await;
+        assert_eq!(filtered.len(), 3);
+        assert_matches!(filtered[1], Err(crate::Error::Csv(_)));
+    }
+
+    #[tokio::test]
+    async fn test_reader_bom() {
+        use tokio::io::AsyncReadExt;
+
+        async fn accounts(rdr: impl io::AsyncRead + Send + Unpin) -> Vec<u8> {
+            let mut output = vec![];
+            process_with(Processor::default(), reader(rdr).unwrap(), writer(&mut output).unwrap())
+                .await
+                .unwrap();
+            output
+        }
+
+        let transactions = "type,client,tx,amount\n\ndeposit,1,1,5.1\n  \t\n\nwithdrawal,1,2,4.2\n\n";
+        let expected = accounts(transactions.as_bytes()).await;
+        assert_eq!(expected, b"client,available,held,total,locked\n1,0.9,0,0.9,false\n");
+
+        // Test: BOM-prefixed input, even when read in several chunks
+        let input = [&b"\xEF\xBB\xBF"[..], transactions.as_bytes()].concat();
+        assert_eq!(accounts(&input[..]).await, expected);
+        assert_eq!(accounts(AsyncReadExt::chain(&input[..1], &input[1..])).await, expected);
+
+        let transactions: Vec<Transaction> = reader_autodetect(&input[..])
+            .await
+            .unwrap()
+            .into_deserialize()
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(transactions.len(), 2);
+
+        // Test: no BOM stripped past the input start, nor from a partial BOM
+        for input in [&b"a\xEF\xBB\xBF"[..], b"\xEF\xBBa", b"\xEF"] {
+            let mut output = vec![];
+            Sanitize::new(input).read_to_end(&mut output).await.unwrap();
+            assert_eq!(output, input);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_chain_readers() {
+        let inputs = [
+            "type,client,tx,amount\ndeposit,1,1,1.0\n",
+            "type,client,tx,amount\n",
+            "type,client,tx,amount\ndispute,1,1,\n",
+        ];
+
+        let readers = inputs.map(|input| reader(input.as_bytes()).unwrap());
+        let transactions: Vec<Transaction> = chain_readers(readers).try_collect().await.unwrap();
+        assert_eq!(
+            transactions.iter().map(|t| (t.r#type, t.tx)).collect::<Vec<_>>(),
+            [(TransactionType::Deposit, 1), (TransactionType::Dispute, 1)]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_dedup_accounts_last_wins() {
+        let account = |client, available: u64| Ok(crate::Account::from((client, Amount::from(available).into())));
+        let accounts = [account(1, 1), account(2, 2), account(1, 3), account(3, 4), account(2, 5)];
+
+        let accounts: Vec<_> = dedup_accounts_last_wins(futures::stream::iter(accounts))
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(
+            accounts
+                .iter()
+                .map(|account| (account.client, account.status.available))
+                .collect::<Vec<_>>(),
+            [
+                (1, Amount::from(3).into()),
+                (2, Amount::from(5).into()),
+                (3, Amount::from(4).into())
+            ]
+        );
+
+        // Test: nothing emitted but the error
+        let err = std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into();
+        let accounts = [account(1, 1), Err(err), account(1, 3)];
+        let accounts: Vec<_> = dedup_accounts_last_wins(futures::stream::iter(accounts)).collect().await;
+        assert_matches!(accounts[..], [Err(crate::Error::Io(_))]);
+    }
+
+    #[cfg(feature = "gzip")]
+    #[tokio::test]
+    async fn test_process_gzip() {
+        use tokio::io::AsyncReadExt;
+
+        let transactions = r"type,client,tx,amount
+deposit,1,1,5.1
+deposit,2,2,2.0
+withdrawal,1,3,4.2
+dispute,2,2,
+";
+
+        let mut compressed = vec![];
+        async_compression::tokio::bufread::GzipEncoder::new(transactions.as_bytes())
+            .read_to_end(&mut compressed)
+            .await
+            .unwrap();
+        assert_ne!(compressed, transactions.as_bytes());
+
+        let mut plain = vec![];
+        let stream = Processor::default().process_stream(reader(transactions.as_bytes()).unwrap().into_deserialize().err_into());
+        write_records(stream, writer(&mut plain).unwrap(), WriterConfig::default())
+            .await
+            .unwrap();
+
+        let mut data = vec![];
+        let stream = Processor::default().process_stream(gzip_reader(&compressed[..]).unwrap().into_deserialize().err_into());
+        write_records(stream, writer(&mut data).unwrap(), WriterConfig::default())
+            .await
+            .unwrap();
+
+        assert_eq!(sorted_records(&data), sorted_records(&plain));
+        assert_eq!(sorted_records(&data).len(), 3);
+
+        // Test: not gzip-compressed
+        let err = gzip_reader(transactions.as_bytes())
+            .unwrap()
+            .into_deserialize::<Transaction>()
+            .try_next()
+            .await;
+        assert!(err.is_err());
     }
 
     #[tokio::test/* (flavor = "multi_thread") */]
@@ -78,4 +1240,538 @@ chargeback,1,3,
         test_process(transactions.as_bytes(), &mut data).await.unwrap();
         assert_eq!(data, b"client,available,held,total,locked\n1,1.1,0,1.1,true\n");
     }
+
+    #[cfg(feature = "json")]
+    #[tokio::test]
+    async fn test_process_json() {
+        let transactions = r#"
+{"type":"deposit","client":1,"tx":1,"amount":"5.1"}
+{"type":"deposit","client":1,"tx":2,"amount":"0.2"}
+
+{"type":"withdrawal","client":1,"tx":3,"amount":"4.2"}
+{"type":"dispute","client":1,"tx":2}
+"#;
+
+        let mut data = vec![];
+        let records = Processor::default().process_stream(json_reader(transactions.as_bytes()));
+        json_writer(records, &mut data).await.unwrap();
+        assert_eq!(
+            std::str::from_utf8(&data).unwrap(),
+            "{\"client\":1,\"available\":\"0.9\",\"held\":\"0.2\",\"total\":\"1.1\",\"locked\":false}\n"
+        );
+
+        let err = json_writer(Processor::process(json_reader(&b"{\"type\":\"unknown\"}"[..])), &mut vec![])
+            .await
+            .unwrap_err();
+        assert_matches!(err, crate::Error::Json(_));
+    }
+
+    #[cfg(feature = "json")]
+    #[tokio::test]
+    async fn test_process_with_summary() {
+        let transactions = r"
+type,client,tx,amount
+deposit,1,1,5.1
+deposit,2,2,2.0
+withdrawal,1,3,4.2
+deposit,3,4,1.0
+dispute,2,2,
+chargeback,2,2,
+dispute,3,4,
+deposit,1,1,1.0
+";
+
+        let (mut accounts, mut summary) = (vec![], vec![]);
+        process_with_summary(
+            AsyncDeserializer::from_reader(transactions.as_bytes()),
+            AsyncSerializer::from_writer(&mut accounts),
+            &mut summary,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            sorted_records(&accounts),
+            [
+                "client,available,held,total,locked",
+                "1,0.9,0,0.9,false",
+                "2,0,0,0,true",
+                "3,0,1,1,false"
+            ]
+        );
+        assert_eq!(
+            std::str::from_utf8(&summary).unwrap(),
+            concat!(
+                r#"{"metrics":{"deposits":3,"withdrawals":1,"disputes":2,"resolves":0,"chargebacks":1,"closes":0,"#,
+                r#""errors":{"transaction-already-exists":1}},"#,
+                r#""accounts":3,"available":"0.9","held":"1","total":"1.9","locked_clients":[2]}"#
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn test_process_with_rejects() {
+        let transactions = r"
+type,client,tx,amount
+deposit,1,1,5.1
+withdrawal,1,2,6.0
+deposit,2,3,2.0
+dispute,2,4,
+withdrawal,2,5,1.0
+";
+
+        let (mut accounts, mut rejects) = (vec![], vec![]);
+        process_with_rejects(
+            AsyncDeserializer::from_reader(transactions.as_bytes()),
+            AsyncSerializer::from_writer(&mut accounts),
+            AsyncSerializer::from_writer(&mut rejects),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            sorted_records(&accounts),
+            ["client,available,held,total,locked", "1,5.1,0,5.1,false", "2,1,0,1,false"]
+        );
+        assert_eq!(
+            std::str::from_utf8(&rejects).unwrap(),
+            "type,client,tx,amount,error\nwithdrawal,1,2,6,not-enough-funds\ndispute,2,4,,transaction-not-found\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_process_with_trailer() {
+        let transactions = r"
+type,client,tx,amount
+deposit,1,1,5.1
+deposit,2,2,2.0
+withdrawal,1,3,4.2
+deposit,3,4,1.0
+dispute,2,2,
+chargeback,2,2,
+dispute,3,4,
+";
+
+        let mut output = vec![];
+        process_with_trailer(
+            Processor::default(),
+            AsyncDeserializer::from_reader(transactions.as_bytes()),
+            AsyncSerializer::from_writer(&mut output),
+        )
+        .await
+        .unwrap();
+
+        let records: Vec<_> = std::str::from_utf8(&output).unwrap().lines().collect();
+        assert_eq!(records.last(), Some(&"total,0.9,1,1.9,1"));
+        assert_eq!(
+            sorted_records(&output),
+            [
+                "client,available,held,total,locked",
+                "1,0.9,0,0.9,false",
+                "2,0,0,0,true",
+                "3,0,1,1,false",
+                "total,0.9,1,1.9,1"
+            ]
+        );
+
+        // Test: trailer only, with no account at all
+        let mut output = vec![];
+        process_with_trailer(
+            Processor::default(),
+            AsyncDeserializer::from_reader("type,client,tx,amount\n".as_bytes()),
+            AsyncSerializer::from_writer(&mut output),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            std::str::from_utf8(&output).unwrap(),
+            "client,available,held,total,locked\ntotal,0,0,0,0\n"
+        );
+    }
+
+    #[test]
+    fn test_trailer_overflow() {
+        let account = |client, status| crate::Account::from((client, status));
+        let accounts = [
+            account(1, AccountStatus::from(Amount::MAX)),
+            account(2, AccountStatus::from(Amount::MIN).held(Amount::MAX)),
+            account(3, AccountStatus::from_parts(Balance::ZERO - Amount::from(1), Amount::MIN, true)),
+        ];
+
+        // Test: grand totals past the bounds of a single balance
+        let trailer = Trailer::new(&accounts);
+        assert_eq!(trailer.available.to_string(), "1844674407370954.1615");
+        assert_eq!(trailer.held.to_string(), "1844674407370955.1615");
+        assert_eq!(trailer.total.to_string(), "3689348814741909.323");
+        assert_eq!(trailer.locked, 1);
+    }
+
+    #[tokio::test]
+    async fn test_process_sorted_accounts() {
+        let transactions = r"
+type,client,tx,amount
+deposit,3,1,3.0
+deposit,1,2,1.0
+close,3,3,
+deposit,2,4,2.0
+deposit,3,5,4.0
+";
+
+        // Test: accounts in ascending client ID order, whatever their insertion order
+        let mut data = vec![];
+        test_process(transactions.as_bytes(), &mut data).await.unwrap();
+        assert_eq!(
+            String::from_utf8(data).unwrap(),
+            "client,available,held,total,locked\n\
+             1,1,0,1,false\n\
+             2,2,0,2,false\n\
+             3,3,0,3,false\n\
+             3,4,0,4,false\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_fixed_precision() {
+        let transactions = "type,client,tx,amount\ndeposit,1,1,1.1\ndeposit,2,2,3.0\nwithdrawal,2,3,3.0\n";
+
+        // Test: trimmed by default
+        assert_eq!(
+            output(transactions, |account| account).await,
+            "client,available,held,total,locked\n1,1.1,0,1.1,false\n2,0,0,0,false\n"
+        );
+
+        // Test: all places past the decimal in fixed precision
+        assert_eq!(
+            output(transactions, FixedPrecision).await,
+            "client,available,held,total,locked\n1,1.1000,0.0000,1.1000,false\n2,0.0000,0.0000,0.0000,false\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_padded_client() {
+        let transactions = "type,client,tx,amount\ndeposit,1,1,1.1\ndeposit,65535,2,3.0\n";
+
+        // Test: zero-padded client IDs, wider ones being left untouched
+        assert_eq!(
+            output(transactions, |account| PaddedClient(account, 5)).await,
+            "client,available,held,total,locked\n00001,1.1,0,1.1,false\n65535,3,0,3,false\n"
+        );
+        assert_eq!(
+            output(transactions, |account| PaddedClient(account, 3)).await,
+            "client,available,held,total,locked\n001,1.1,0,1.1,false\n65535,3,0,3,false\n"
+        );
+
+        // Test: along with other record wrappers
+        assert_eq!(
+            output(transactions, |account| PaddedClient(FixedPrecision(account), 5)).await,
+            "client,available,held,total,locked\n00001,1.1000,0.0000,1.1000,false\n65535,3.0000,0.0000,3.0000,false\n"
+        );
+
+        // Test: as configured for a process
+        let config = WriterConfig {
+            client_id_width: Some(5),
+            ..Default::default()
+        };
+        let mut data = vec![];
+        process_with_config(
+            Processor::default(),
+            reader(transactions.as_bytes()).unwrap(),
+            writer(&mut data).unwrap(),
+            config,
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(data).unwrap(),
+            "client,available,held,total,locked\n00001,1.1,0,1.1,false\n65535,3,0,3,false\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_process_negative_funds() {
+        let transactions = r"
+type,client,tx,amount
+deposit,1,1,3.0
+withdrawal,1,2,2.5
+dispute,1,1,
+chargeback,1,1,
+";
+
+        let mut data = vec![];
+        test_process(transactions.as_bytes(), &mut data).await.unwrap();
+        assert_eq!(data, b"client,available,held,total,locked\n1,-2.5,0,-2.5,true\n");
+    }
+
+    #[tokio::test]
+    async fn test_process_buffered() {
+        let mut transactions = String::from("type,client,tx,amount\n");
+        for tx in 1..=200 {
+            let client = tx % 7;
+            match tx % 5 {
+                0 => transactions.push_str(&format!("dispute,{client},{},\n", tx - 3)),
+                1 => transactions.push_str(&format!("withdrawal,{client},{tx},0.5\n")),
+                _ => transactions.push_str(&format!("deposit,{client},{tx},1.25\n")),
+            }
+        }
+
+        let mut expected = vec![];
+        test_process(transactions.as_bytes(), &mut expected).await.unwrap();
+        assert_eq!(sorted_records(&expected).len(), 8);
+
+        // Test: same output as the serial process, whatever the buffer size
+        for buffer in [0, 1, 4, 1024] {
+            let mut data = vec![];
+            let errors = process_buffered(
+                Processor::default(),
+                reader(transactions.as_bytes()).unwrap(),
+                writer(&mut data).unwrap(),
+                WriterConfig::default(),
+                buffer,
+            )
+            .await
+            .unwrap();
+            assert_eq!(errors, 0);
+            assert_eq!(data, expected, "buffer {buffer}");
+        }
+
+        // Test: aborted on error
+        let err = process_buffered(
+            Processor::default(),
+            AsyncDeserializer::from_reader("type,client,tx,amount\ndeposit,1,x,1.0\ndeposit,1,2,1.0\n".as_bytes()),
+            writer(&mut vec![]).unwrap(),
+            WriterConfig::default(),
+            1,
+        )
+        .await
+        .unwrap_err();
+        assert_matches!(err, crate::Error::Csv(_));
+    }
+
+    #[tokio::test]
+    async fn test_process_strict() {
+        let transactions = r"
+type,client,tx,amount
+deposit,1,1,1.0
+dispute,1,2,
+deposit,2,3,2.0
+";
+
+        let mut data = vec![];
+        test_process(transactions.as_bytes(), &mut data).await.unwrap();
+        assert_eq!(
+            sorted_records(&data),
+            ["client,available,held,total,locked", "1,1,0,1,false", "2,2,0,2,false"]
+        );
+
+        let mut data = vec![];
+        let buffer = std::io::Cursor::new(&mut data);
+        let err = process_strict_with(
+            Processor::default(),
+            AsyncDeserializer::from_reader(transactions.as_bytes()),
+            AsyncSerializer::from_writer(buffer),
+        )
+        .await
+        .unwrap_err();
+        assert_matches!(err, crate::Error::Process(crate::process::Error::TransactionNotFound(2)));
+        assert!(data.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_process_flush_every() {
+        /// A writer recording its written length at each flush.
+        #[derive(Default)]
+        struct FlushRecorder {
+            data: Vec<u8>,
+            flushes: Vec<usize>,
+        }
+
+        impl io::AsyncWrite for FlushRecorder {
+            fn poll_write(
+                mut self: std::pin::Pin<&mut Self>,
+                _: &mut std::task::Context<'_>,
+                buf: &[u8],
+            ) -> std::task::Poll<std::io::Result<usize>> {
+                self.data.extend_from_slice(buf);
+                std::task::Poll::Ready(Ok(buf.len()))
+            }
+
+            fn poll_flush(mut self: std::pin::Pin<&mut Self>, _: &mut std::task::Context<'_>) -> std::task::Poll<std::io::Result<()>> {
+                let len = self.data.len();
+                self.flushes.push(len);
+                std::task::Poll::Ready(Ok(()))
+            }
+
+            fn poll_shutdown(self: std::pin::Pin<&mut Self>, _: &mut std::task::Context<'_>) -> std::task::Poll<std::io::Result<()>> {
+                std::task::Poll::Ready(Ok(()))
+            }
+        }
+
+        let transactions = r"
+type,client,tx,amount
+deposit,1,1,1.0
+deposit,2,2,2.0
+deposit,3,3,3.0
+";
+
+        let mut recorder = FlushRecorder::default();
+        let config = WriterConfig {
+            flush_every: Some(2),
+            ..Default::default()
+        };
+        process_with_config(
+            Processor::default(),
+            AsyncDeserializer::from_reader(transactions.as_bytes()),
+            AsyncSerializer::from_writer(&mut recorder),
+            config,
+        )
+        .await
+        .unwrap();
+
+        // header and 2 records, then the last record (flushed again on drop)
+        recorder.flushes.dedup();
+        let header = "client,available,held,total,locked\n".len();
+        let record = "1,1,0,1,false\n".len();
+        assert_eq!(recorder.flushes, [header + 2 * record, header + 3 * record]);
+    }
+
+    #[tokio::test]
+    async fn test_process_resilient() {
+        /// A writer failing once to write a given record.
+        struct FailingWriter {
+            data: Vec<u8>,
+            failing_record: Option<&'static [u8]>,
+        }
+
+        impl io::AsyncWrite for FailingWriter {
+            fn poll_write(
+                mut self: std::pin::Pin<&mut Self>,
+                _: &mut std::task::Context<'_>,
+                buf: &[u8],
+            ) -> std::task::Poll<std::io::Result<usize>> {
+                if self.failing_record.is_some_and(|record| buf.starts_with(record)) {
+                    self.failing_record = None;
+                    return std::task::Poll::Ready(Err(std::io::ErrorKind::BrokenPipe.into()));
+                }
+                self.data.extend_from_slice(buf);
+                std::task::Poll::Ready(Ok(buf.len()))
+            }
+
+            fn poll_flush(self: std::pin::Pin<&mut Self>, _: &mut std::task::Context<'_>) -> std::task::Poll<std::io::Result<()>> {
+                std::task::Poll::Ready(Ok(()))
+            }
+
+            fn poll_shutdown(self: std::pin::Pin<&mut Self>, _: &mut std::task::Context<'_>) -> std::task::Poll<std::io::Result<()>> {
+                std::task::Poll::Ready(Ok(()))
+            }
+        }
+
+        let accounts = || (1..=3).map(|client| Ok(crate::Account::from((client, Amount::from(u64::from(client)).into()))));
+
+        // Test: abort on failure by default
+        let mut wtr = FailingWriter {
+            data: Vec::new(),
+            failing_record: Some(b"2,"),
+        };
+        let result = write_records(
+            futures::stream::iter(accounts()),
+            writer(&mut wtr).unwrap(),
+            WriterConfig::default(),
+        )
+        .await;
+        assert_matches!(result, Err(crate::Error::Csv(_)));
+        assert_eq!(
+            std::str::from_utf8(&wtr.data).unwrap(),
+            "client,available,held,total,locked\n1,1,0,1,false\n"
+        );
+
+        // Test: keep writing remaining records on failure
+        let mut wtr = FailingWriter {
+            data: Vec::new(),
+            failing_record: Some(b"2,"),
+        };
+        let config = WriterConfig {
+            resilient: true,
+            ..Default::default()
+        };
+        let errors = write_records(futures::stream::iter(accounts()), writer(&mut wtr).unwrap(), config).await;
+        assert_matches!(errors, Ok(1));
+        let data = std::str::from_utf8(&wtr.data).unwrap();
+        assert!(data.starts_with("client,available,held,total,locked\n1,1,0,1,false\n"));
+        assert!(data.ends_with("3,3,0,3,false\n"));
+    }
+
+    #[tokio::test]
+    async fn test_process_suppress_empty() {
+        let transactions = r"
+type,client,tx,amount
+deposit,1,1,1.0
+withdrawal,1,2,1.0
+deposit,2,3,2.0
+dispute,2,3,
+chargeback,2,3,
+deposit,3,4,3.0
+";
+
+        let mut data = vec![];
+        test_process(transactions.as_bytes(), &mut data).await.unwrap();
+        assert_eq!(
+            sorted_records(&data),
+            [
+                "client,available,held,total,locked",
+                "1,0,0,0,false",
+                "2,0,0,0,true",
+                "3,3,0,3,false"
+            ]
+        );
+
+        let mut data = vec![];
+        let processor = Processor::default().with_suppress_empty(true);
+        test_process_with(processor, transactions.as_bytes(), &mut data).await.unwrap();
+        assert_eq!(
+            sorted_records(&data),
+            ["client,available,held,total,locked", "2,0,0,0,true", "3,3,0,3,false"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_process_custom_handler() {
+        /// A trivial handler counting transactions.
+        #[derive(Default)]
+        struct Counter {
+            count: usize,
+        }
+
+        #[derive(Serialize)]
+        struct CounterRecord {
+            count: usize,
+        }
+
+        impl ClientRecord for CounterRecord {}
+
+        impl TransactionHandler for Counter {
+            type Record = CounterRecord;
+
+            async fn handle(&mut self, _: crate::Transaction) -> Result<(), crate::process::Error> {
+                self.count += 1;
+                Ok(())
+            }
+
+            fn into_records(self) -> impl Iterator<Item = Self::Record> {
+                std::iter::once(CounterRecord { count: self.count })
+            }
+        }
+
+        let transactions = r"
+type,client,tx,amount
+deposit,1,1,1.0
+withdrawal,1,2,1.0
+dispute,1,3,
+";
+
+        let mut data = vec![];
+        test_process_with(Counter::default(), transactions.as_bytes(), &mut data)
+            .await
+            .unwrap();
+        assert_eq!(data, b"count\n3\n");
+    }
 }
@@ -0,0 +1,166 @@
+//! A module providing Apache Parquet transaction I/O features.
+//!
+//! Transactions are read from the `type`, `client`, `tx` and `amount` columns of a Parquet file, the latter being optional.
+//! Columns are cast to their expected Arrow types, so that eg. an integer `client` column of any width is accepted
+//! as long as its values fit, while amounts are decoded from a decimal column without any floating-point rounding.
+
+use std::path::Path;
+
+use arrow_array::cast::AsArray;
+use arrow_array::types::{Decimal128Type, UInt16Type, UInt32Type};
+use arrow_array::{Array, ArrayRef, RecordBatch};
+use arrow_cast::cast::{cast_with_options, CastOptions};
+use arrow_schema::DataType;
+use futures::stream::{self, Stream, StreamExt, TryStreamExt};
+use parquet::arrow::async_reader::{AsyncFileReader, ParquetRecordBatchStreamBuilder};
+use serde::de::{value, Deserialize, IntoDeserializer};
+use tokio::io;
+
+use crate::{amount_precision, Amount, Transaction, TransactionType};
+
+/// Stream transactions from a Parquet file, batch by batch.
+pub fn stream_from_parquet(path: impl AsRef<Path>) -> impl Stream<Item = crate::Result<Transaction>> {
+    let path = path.as_ref().to_owned();
+
+    stream::once(async move { Ok::<_, crate::Error>(parquet_reader(tokio::fs::File::open(path).await?)) }).try_flatten()
+}
+
+/// Stream transactions from any Parquet input, eg. an in-memory buffer.
+pub fn parquet_reader<R>(rdr: R) -> impl Stream<Item = crate::Result<Transaction>>
+where
+    R: AsyncFileReader + Unpin + Send + 'static,
+{
+    stream::once(async move { Ok::<_, crate::Error>(ParquetRecordBatchStreamBuilder::new(rdr).await?.build()?) })
+        .map_ok(|batches| batches.map_err(crate::Error::from))
+        .try_flatten()
+        .and_then(|batch| async move { batch_transactions(&batch) })
+        .map_ok(|transactions| stream::iter(transactions).map(Ok))
+        .try_flatten()
+}
+
+/// Decode all transactions of a record batch.
+fn batch_transactions(batch: &RecordBatch) -> crate::Result<Vec<Transaction>> {
+    let types = required_column(batch, "type", &DataType::Utf8)?;
+    let clients = required_column(batch, "client", &DataType::UInt16)?;
+    let txs = required_column(batch, "tx", &DataType::UInt32)?;
+    let amounts = column(batch, "amount", &DataType::Decimal128(38, amount_precision() as i8))?;
+
+    let (types, clients, txs) = (
+        types.as_string::<i32>(),
+        clients.as_primitive::<UInt16Type>(),
+        txs.as_primitive::<UInt32Type>(),
+    );
+    let amounts = amounts.as_ref().map(|amounts| amounts.as_primitive::<Decimal128Type>());
+
+    (0..batch.num_rows())
+        .map(|row| {
+            if types.is_null(row) || clients.is_null(row) || txs.is_null(row) {
+                return Err(invalid_data(format!("missing transaction value at row {row}")));
+            }
+
+            let r#type = TransactionType::deserialize(types.value(row).into_deserializer())
+                .map_err(|err: value::Error| invalid_data(err.to_string()))?;
+            let amount = match amounts {
+                Some(amounts) if amounts.is_valid(row) => {
+                    let units = u64::try_from(amounts.value(row)).map_err(|_| invalid_data(format!("invalid amount at row {row}")))?;
+                    Some(Amount::from_minor_units(units))
+                }
+                _ => None,
+            };
+
+            Ok(Transaction::new(r#type, txs.value(row), amount).with_client(clients.value(row)))
+        })
+        .collect()
+}
+
+/// Get a column of a record batch by name, if any, cast to the expected data type.
+fn column(batch: &RecordBatch, name: &str, data_type: &DataType) -> crate::Result<Option<ArrayRef>> {
+    let options = CastOptions {
+        safe: false, // fail on values that cannot be represented, rather than nulling them
+        ..Default::default()
+    };
+
+    batch
+        .column_by_name(name)
+        .map(|column| cast_with_options(column, data_type, &options))
+        .transpose()
+        .map_err(|err| invalid_data(format!("invalid `{name}` column: {err}")))
+}
+
+/// Get a mandatory column of a record batch by name, cast to the expected data type.
+fn required_column(batch: &RecordBatch, name: &str, data_type: &DataType) -> crate::Result<ArrayRef> {
+    column(batch, name, data_type)?.ok_or_else(|| invalid_data(format!("missing `{name}` column")))
+}
+
+#[inline]
+fn invalid_data(msg: String) -> crate::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Arc;
+
+    use arrow_array::{Decimal128Array, StringArray, UInt16Array, UInt32Array};
+    use assert_matches::assert_matches;
+    use parquet::arrow::ArrowWriter;
+
+    use crate::{AccountStatus, Processor};
+
+    fn parquet_buffer(columns: Vec<(&str, ArrayRef)>) -> std::io::Cursor<Vec<u8>> {
+        let batch = RecordBatch::try_from_iter(columns).unwrap();
+
+        let mut buffer = vec![];
+        let mut writer = ArrowWriter::try_new(&mut buffer, batch.schema(), None).unwrap();
+        writer.write(&batch).unwrap();
+        writer.close().unwrap();
+
+        std::io::Cursor::new(buffer)
+    }
+
+    #[tokio::test]
+    async fn test_stream_from_parquet() {
+        let buffer = parquet_buffer(vec![
+            (
+                "type",
+                Arc::new(StringArray::from(vec!["deposit", "deposit", "withdrawal", "dispute", "deposit"])),
+            ),
+            ("client", Arc::new(UInt16Array::from(vec![1, 1, 1, 1, 2]))),
+            ("tx", Arc::new(UInt32Array::from(vec![1, 2, 3, 2, 4]))),
+            (
+                "amount",
+                Arc::new(
+                    Decimal128Array::from(vec![Some(51_000), Some(2_000), Some(42_000), None, Some(15)])
+                        .with_precision_and_scale(10, 4)
+                        .unwrap(),
+                ),
+            ),
+        ]);
+
+        let mut accounts: Vec<_> = Processor::process(parquet_reader(buffer)).try_collect().await.unwrap();
+        accounts.sort_unstable_by_key(|account| account.client);
+        assert_eq!(accounts.len(), 2);
+        assert_eq!(accounts[0].client, 1);
+        assert_eq!(
+            accounts[0].status,
+            AccountStatus::from_parts(Amount::from_minor_units(9_000), Amount::from_minor_units(2_000), false)
+        );
+        assert_eq!(accounts[1].client, 2);
+        assert_eq!(
+            accounts[1].status,
+            AccountStatus::from_parts(Amount::from_minor_units(15), Amount::MIN, false)
+        );
+
+        let buffer = parquet_buffer(vec![
+            ("type", Arc::new(StringArray::from(vec!["deposit"]))),
+            ("tx", Arc::new(UInt32Array::from(vec![1]))),
+        ]);
+        let err = parquet_reader(buffer).try_collect::<Vec<_>>().await.unwrap_err();
+        assert_matches!(err, crate::Error::Io(err) if err.kind() == io::ErrorKind::InvalidData);
+
+        let err = stream_from_parquet("missing.parquet").try_collect::<Vec<_>>().await.unwrap_err();
+        assert_matches!(err, crate::Error::Io(err) if err.kind() == io::ErrorKind::NotFound);
+    }
+}
@@ -3,26 +3,38 @@
 use serde::{Deserialize, Serialize};
 
 mod error;
-pub use error::{Error, Result};
-
-mod process;
-pub use process::Processor;
+pub use error::{AccountError, Error, Result};
 
 pub mod io;
 pub mod num;
+pub mod process;
+pub use process::{LockPolicy, MergePolicy, Processor, TransactionHandler};
 
 /// Decimal precision used for transaction amounts.
 const PREC: u8 = 4;
+const _: () = assert!(PREC <= num::MAX_N, "transaction amount precision cannot be represented");
+
+/// Get the decimal precision used for transaction amounts, ie. the max. number of places past the decimal.
+#[inline]
+pub const fn amount_precision() -> u8 {
+    PREC
+}
 
 /// Convenient alias for a client ID.
 pub type ClientID = u16;
 /// Convenient alias for a transaction ID.
 pub type TransactionID = u32;
+/// Convenient alias for a transaction timestamp, as a logical clock, eg. seconds since the Unix epoch.
+pub type Timestamp = u64;
 /// Convenient alias for a transaction amount.
 pub type Amount = num::Decimal<PREC>;
+/// Convenient alias for an account balance, which could be negative, eg. when disputing already withdrawn funds.
+pub type Balance = num::SignedDecimal<PREC>;
+/// Convenient alias for a grand total of balances, which could not overflow when summed up across all accounts.
+pub type BalanceTotal = num::DecimalAccumulator<PREC>;
 
 /// A transaction type.
-#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
 #[serde(rename_all = "lowercase")]
 pub enum TransactionType {
     Deposit,
@@ -30,15 +42,21 @@ pub enum TransactionType {
     Dispute,
     Resolve,
     Chargeback,
+    /// An end-of-client marker, after which this client's account is considered final.
+    #[serde(rename = "close")]
+    CloseClient,
 }
 
 /// A transaction.
-#[derive(Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct Transaction {
+    #[serde(rename = "type")] // explicit column name, not relying on raw identifier handling
     r#type: TransactionType,
     client: ClientID,
     tx: TransactionID,
     amount: Option<Amount>,
+    #[serde(default, skip_serializing_if = "Option::is_none")] // optional column, eg. to expire disputes
+    timestamp: Option<Timestamp>,
 }
 
 impl Transaction {
@@ -50,6 +68,27 @@ impl Transaction {
             tx,
             amount,
             client: Default::default(),
+            timestamp: None,
+        }
+    }
+
+    /// Parse a transaction from a single headerless CSV row, eg. `deposit,1,2,5.1` or `dispute,1,2`, to set up a test scenario.
+    #[cfg(any(test, feature = "test-util"))]
+    pub fn from_csv_row(row: &str) -> Result<Self> {
+        use futures::StreamExt;
+
+        let input = format!("type,client,tx,amount\n{row}");
+        let mut reader = csv_async::AsyncReaderBuilder::default()
+            .trim(csv_async::Trim::All)
+            .has_headers(true)
+            .flexible(true) // allow rows with no amount at all
+            .create_deserializer(input.as_bytes());
+
+        let record = futures::executor::block_on(reader.deserialize().next());
+
+        match record {
+            Some(transaction) => Ok(transaction?),
+            None => Err(std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "empty CSV row").into()),
         }
     }
 
@@ -59,6 +98,66 @@ impl Transaction {
         Self { client, ..self }
     }
 
+    /// Build a transaction with an amount.
+    #[inline]
+    pub fn with_amount(self, amount: Amount) -> Self {
+        Self {
+            amount: Some(amount),
+            ..self
+        }
+    }
+
+    /// Build a transaction with a timestamp, eg. to expire its dispute with `Processor::expire_disputes()`.
+    #[inline]
+    pub fn with_timestamp(self, timestamp: Timestamp) -> Self {
+        Self {
+            timestamp: Some(timestamp),
+            ..self
+        }
+    }
+
+    /// Get the type of this transaction.
+    #[inline]
+    pub fn kind(&self) -> TransactionType {
+        self.r#type
+    }
+
+    /// Get the client related to this transaction.
+    #[inline]
+    pub fn client(&self) -> ClientID {
+        self.client
+    }
+
+    /// Get the ID of this transaction, or of the disputed transaction it refers to.
+    #[inline]
+    pub fn tx(&self) -> TransactionID {
+        self.tx
+    }
+
+    /// Get the amount of this transaction, if any.
+    #[inline]
+    pub fn amount(&self) -> Option<Amount> {
+        self.amount
+    }
+
+    /// Get the timestamp of this transaction, if any.
+    #[inline]
+    pub fn timestamp(&self) -> Option<Timestamp> {
+        self.timestamp
+    }
+
+    /// Validate that this transaction carries an amount if and only if its type expects one, eg. when generated dynamically.
+    ///
+    /// A deposit or withdrawal without amount is rejected as a `process::Error::MissingAmount`, while any other transaction
+    /// with an amount is rejected as a `process::Error::UnexpectedAmount`.
+    pub fn try_build(self) -> Result<Self, process::Error> {
+        match (self.r#type, self.amount) {
+            (TransactionType::Deposit | TransactionType::Withdrawal, None) => Err(process::Error::MissingAmount(self.tx)),
+            (TransactionType::Deposit | TransactionType::Withdrawal, Some(_)) | (_, None) => Ok(self),
+            (_, Some(_)) => Err(process::Error::UnexpectedAmount(self.tx)),
+        }
+    }
+
     /// Convenient constructor for a `Deposit` transaction.
     #[inline]
     pub fn deposit(tx: TransactionID, amount: Amount) -> Self {
@@ -88,24 +187,38 @@ impl Transaction {
     pub fn chargeback(tx: TransactionID) -> Self {
         Self::new(TransactionType::Chargeback, tx, None)
     }
+
+    /// Convenient constructor for a `CloseClient` transaction.
+    #[inline]
+    pub fn close_client(tx: TransactionID) -> Self {
+        Self::new(TransactionType::CloseClient, tx, None)
+    }
 }
 
 /// A client's account status.
-#[derive(Clone, Debug, Default, Serialize, PartialEq, Eq)]
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
 pub struct AccountStatus {
     /// Available funds for this account.
-    available: Amount,
+    available: Balance,
     /// Held funds for this account, ie. disputed amounts.
-    held: Amount,
+    held: Balance,
     /// An account can be locked/frozen if a transaction has been charged back.
     locked: bool,
 }
 
 impl AccountStatus {
+    /// Create an account status from its raw parts, eg. to set up a test scenario.
+    #[cfg(any(test, feature = "test-util"))]
+    pub fn from_parts(available: impl Into<Balance>, held: impl Into<Balance>, locked: bool) -> Self {
+        let (available, held) = (available.into(), held.into());
+
+        Self { available, held, locked }
+    }
+
     /// Set held funds for this account status.
     #[inline]
     pub fn held(self, held: Amount) -> Self {
-        Self { held, ..self }
+        Self { held: held.into(), ..self }
     }
 
     /// Set this account status as locked.
@@ -114,44 +227,92 @@ impl AccountStatus {
         Self { locked: true, ..self }
     }
 
-    /// Hold more funds for this account status.
+    /// Hold more funds for this account status, ie. move them from available to held funds.
     ///
-    /// # Panics
-    /// This function overflows if `amount` is larger than available funds.
-    pub fn hold(&mut self, amount: Amount) {
-        if !self.locked {
-            self.available -= amount;
-            self.held += amount;
-        }
+    /// Available funds could become negative if `amount` is larger than them, eg. if disputed funds have already been withdrawn.
+    ///
+    /// # Errors
+    /// This function returns an `AccountError::Locked` if this account status is locked, or an `AccountError::Funds` if resulting
+    /// funds cannot be represented, the account status being left untouched.
+    pub fn hold(&mut self, amount: Amount) -> Result<(), AccountError> {
+        self.check_unlocked()?;
+        Ok(self.force_hold(amount)?)
     }
 
-    /// Release held funds for this account status.
+    /// Release held funds for this account status, ie. move them from held back to available funds.
     ///
-    /// # Panics
-    /// This function overflows if `amount` is larger than held funds.
-    pub fn release(&mut self, amount: Amount) {
-        if !self.locked {
-            self.available += amount;
-            self.held -= amount;
-        }
+    /// # Errors
+    /// This function returns an `AccountError::Locked` if this account status is locked, or an `AccountError::Funds` if resulting
+    /// funds cannot be represented, the account status being left untouched.
+    pub fn release(&mut self, amount: Amount) -> Result<(), AccountError> {
+        self.check_unlocked()?;
+        Ok(self.force_release(amount)?)
     }
 
-    /// Lock this account status.
+    /// Lock this account status, held funds being removed.
     ///
-    /// # Panics
-    /// This function overflows if `amount` is larger than held funds.
+    /// # Errors
+    /// This function returns an `AccountError::Locked` if this account status is already locked, or an `AccountError::Funds` if
+    /// resulting funds cannot be represented, the account status being left untouched.
+    pub fn lock(&mut self, amount: Amount) -> Result<(), AccountError> {
+        self.check_unlocked()?;
+        Ok(self.force_lock(amount)?)
+    }
+
+    /// Check that this account status is unlocked, ie. that its funds could be moved.
     #[inline]
-    pub fn lock(&mut self, amount: Amount) {
-        if !self.locked {
-            self.held -= amount;
-            self.locked = true;
+    fn check_unlocked(&self) -> Result<(), AccountError> {
+        if self.locked {
+            Err(AccountError::Locked)
+        } else {
+            Ok(())
         }
     }
 
-    /// Compute total funds for this account status.
+    /// Hold more funds for this account status, whether it is locked or not, eg. as allowed by `LockPolicy::AllowDisputes`.
+    pub(crate) fn force_hold(&mut self, amount: Amount) -> Result<(), num::Error> {
+        let available = self.available.checked_sub(amount.into()).ok_or(num::Error::Overflow)?;
+        let held = self.held.checked_add(amount.into()).ok_or(num::Error::Overflow)?;
+        (self.available, self.held) = (available, held);
+
+        Ok(())
+    }
+
+    /// Release held funds for this account status, whether it is locked or not.
     #[inline]
-    pub fn total(&self) -> Amount {
-        self.available + self.held
+    pub(crate) fn force_release(&mut self, amount: Amount) -> Result<(), num::Error> {
+        self.checked_release(amount.into())
+    }
+
+    /// Remove held funds and lock this account status, whether it is already locked or not.
+    pub(crate) fn force_lock(&mut self, amount: Amount) -> Result<(), num::Error> {
+        self.held = self.held.checked_sub(amount.into()).ok_or(num::Error::Overflow)?;
+        self.locked = true;
+
+        Ok(())
+    }
+
+    /// Move any funds from held to available funds, eg. negative funds should held funds be missing.
+    pub(crate) fn checked_release(&mut self, released: Balance) -> Result<(), num::Error> {
+        let available = self.available.checked_add(released).ok_or(num::Error::Overflow)?;
+        let held = self.held.checked_sub(released).ok_or(num::Error::Overflow)?;
+        (self.available, self.held) = (available, held);
+
+        Ok(())
+    }
+
+    /// Whether this account status is unlocked with no funds.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        !self.locked && self.total() == BalanceTotal::ZERO
+    }
+
+    /// Compute total funds for this account status, summed up without any overflow.
+    #[inline]
+    pub fn total(&self) -> BalanceTotal {
+        let mut total = BalanceTotal::from(self.available);
+        total += self.held;
+        total
     }
 }
 
@@ -160,7 +321,7 @@ impl From<Amount> for AccountStatus {
     #[inline]
     fn from(available: Amount) -> Self {
         Self {
-            available,
+            available: available.into(),
             ..Default::default()
         }
     }
@@ -181,13 +342,13 @@ impl From<(ClientID, AccountStatus)> for Account {
     }
 }
 
-/// A helper to serialize a client's account record.
+/// A helper to serialize a client's account record, with balances serialized as `B`, their total as `T` and the client as `C`.
 #[derive(Debug, Serialize)]
-struct AccountRecord {
-    client: ClientID,
-    available: Amount,
-    held: Amount,
-    total: Amount,
+struct AccountRecord<B = Balance, T = BalanceTotal, C = ClientID> {
+    client: C,
+    available: B,
+    held: B,
+    total: T,
     locked: bool,
 }
 
@@ -203,3 +364,163 @@ impl From<Account> for AccountRecord {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_amount_precision() {
+        assert_eq!(amount_precision(), 4);
+        assert_eq!(num::Precision::default().places(), amount_precision());
+    }
+
+    #[test]
+    fn test_transaction_accessors() {
+        let amount = Amount::new(5, 1000);
+
+        let transaction = Transaction::deposit(1, amount).with_client(2);
+        assert_eq!(transaction.kind(), TransactionType::Deposit);
+        assert_eq!(transaction.client(), 2);
+        assert_eq!(transaction.tx(), 1);
+        assert_eq!(transaction.amount(), Some(amount));
+
+        let transaction = Transaction::dispute(1);
+        assert_eq!(
+            (transaction.kind(), transaction.client(), transaction.amount()),
+            (TransactionType::Dispute, 0, None)
+        );
+    }
+
+    #[test]
+    fn test_transaction_try_build() {
+        use assert_matches::assert_matches;
+
+        let amount = Amount::new(5, 1000);
+
+        let transaction = Transaction::new(TransactionType::Deposit, 1, None)
+            .with_amount(amount)
+            .with_client(2);
+        assert_matches!(transaction.try_build(), Ok(Transaction { client: 2, tx: 1, amount: Some(a), .. }) if a == amount);
+        assert_matches!(Transaction::withdrawal(1, amount).try_build(), Ok(_));
+        assert_matches!(Transaction::dispute(1).try_build(), Ok(_));
+
+        assert_matches!(
+            Transaction::new(TransactionType::Deposit, 1, None).try_build(),
+            Err(process::Error::MissingAmount(1))
+        );
+        assert_matches!(
+            Transaction::new(TransactionType::Withdrawal, 1, None).try_build(),
+            Err(process::Error::MissingAmount(1))
+        );
+        for transaction in [
+            Transaction::dispute(1),
+            Transaction::resolve(1),
+            Transaction::chargeback(1),
+            Transaction::close_client(1),
+        ] {
+            assert_matches!(
+                transaction.with_amount(amount).try_build(),
+                Err(process::Error::UnexpectedAmount(1))
+            );
+        }
+    }
+
+    #[test]
+    fn test_account_status_hold() {
+        let mut status = AccountStatus::from(Amount::from(10));
+
+        status.hold(Amount::from(4)).unwrap();
+        assert_eq!(status, AccountStatus::from_parts(Amount::from(6), Amount::from(4), false));
+
+        // available funds could become negative
+        status.hold(Amount::from(10)).unwrap();
+        assert_eq!(
+            status,
+            AccountStatus::from_parts(Balance::ZERO - Amount::from(4), Amount::from(14), false)
+        );
+        assert_eq!(status.total(), Amount::from(10).into());
+
+        // funds that cannot be represented
+        let mut status = AccountStatus::from_parts(Balance::MIN, Amount::MIN, false);
+        assert_eq!(status.hold(Amount::new(0, 1)), Err(AccountError::Funds(num::Error::Overflow)));
+        assert_eq!(status, AccountStatus::from_parts(Balance::MIN, Amount::MIN, false));
+
+        // locked account
+        let mut status = AccountStatus::from(Amount::from(10)).locked();
+        assert_eq!(status.hold(Amount::from(4)), Err(AccountError::Locked));
+        assert_eq!(status, AccountStatus::from(Amount::from(10)).locked());
+    }
+
+    #[test]
+    fn test_account_status_release() {
+        let mut status = AccountStatus::from(Amount::from(6)).held(Amount::from(4));
+
+        status.release(Amount::from(4)).unwrap();
+        assert_eq!(status, AccountStatus::from(Amount::from(10)));
+
+        // funds that cannot be represented
+        let mut status = AccountStatus::from(Amount::MAX).held(Amount::new(0, 1));
+        assert_eq!(status.release(Amount::new(0, 1)), Err(AccountError::Funds(num::Error::Overflow)));
+        assert_eq!(status, AccountStatus::from(Amount::MAX).held(Amount::new(0, 1)));
+
+        // locked account
+        let mut status = AccountStatus::from(Amount::from(6)).held(Amount::from(4)).locked();
+        assert_eq!(status.release(Amount::from(4)), Err(AccountError::Locked));
+        assert_eq!(status, AccountStatus::from(Amount::from(6)).held(Amount::from(4)).locked());
+    }
+
+    #[test]
+    fn test_account_status_lock() {
+        let mut status = AccountStatus::from(Amount::from(6)).held(Amount::from(4));
+
+        status.lock(Amount::from(4)).unwrap();
+        assert_eq!(status, AccountStatus::from(Amount::from(6)).locked());
+        assert_eq!(status.total(), Amount::from(6).into());
+
+        // already locked account
+        assert_eq!(status.lock(Amount::from(6)), Err(AccountError::Locked));
+        assert_eq!(status, AccountStatus::from(Amount::from(6)).locked());
+
+        // funds that cannot be represented
+        let mut status = AccountStatus::from_parts(Amount::MIN, Balance::MIN, false);
+        assert_eq!(status.lock(Amount::new(0, 1)), Err(AccountError::Funds(num::Error::Overflow)));
+        assert_eq!(status, AccountStatus::from_parts(Amount::MIN, Balance::MIN, false));
+    }
+
+    #[test]
+    fn test_account_status_total() {
+        let status = AccountStatus::from(Amount::from(6)).held(Amount::from(4));
+        assert_eq!(status.total(), Amount::from(10).into());
+
+        // total funds past the bounds of a single balance
+        let status = AccountStatus::from(Amount::MAX).held(Amount::MAX);
+        assert_eq!(status.total().to_string(), "3689348814741910.323");
+        assert!(!status.is_empty());
+        assert!(AccountStatus::default().is_empty());
+    }
+
+    #[test]
+    fn test_transaction_from_csv_row() {
+        let transaction = Transaction::from_csv_row("deposit,1,2,5.1").unwrap();
+        assert_eq!(
+            (transaction.r#type, transaction.client, transaction.tx),
+            (TransactionType::Deposit, 1, 2)
+        );
+        assert_eq!(transaction.amount, Some(Amount::new(5, 1000)));
+
+        let transaction = Transaction::from_csv_row(" dispute, 1, 2 ").unwrap();
+        assert_eq!(
+            (transaction.r#type, transaction.client, transaction.tx),
+            (TransactionType::Dispute, 1, 2)
+        );
+        assert_eq!(transaction.amount, None);
+
+        let transaction = Transaction::from_csv_row("resolve,1,2,").unwrap();
+        assert_eq!((transaction.r#type, transaction.amount), (TransactionType::Resolve, None));
+
+        assert!(Transaction::from_csv_row("").is_err());
+        assert!(Transaction::from_csv_row("unknown,1,2").is_err());
+        assert!(Transaction::from_csv_row("deposit,1").is_err());
+    }
+}
@@ -1,442 +1,3589 @@
 //! A module providing transaction processing features.
 
-use std::collections::HashMap;
+use std::collections::hash_map::{Entry, RandomState};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::future::Future;
+use std::hash::BuildHasher;
+use std::{fmt, sync::Arc};
 
 use futures::{stream, Stream, StreamExt, TryFutureExt, TryStreamExt};
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
-use crate::{Account, AccountStatus, Amount, ClientID, Result, Transaction, TransactionID, TransactionType};
+use crate::{
+    Account, AccountStatus, Amount, Balance, BalanceTotal, ClientID, Result, Timestamp, Transaction, TransactionID, TransactionType,
+};
 
 const DEFAULT_TRANSACTION_CAPACITY: usize = 10_000;
 const MAX_TRANSACTION_CAPACITY: usize = 1_000_000;
 const ROLLOUT_TRANSACTION_THRESHOLD: usize = 1_000;
 
 /// A transaction process error.
-#[derive(Debug, Error)]
+#[derive(Clone, Debug, Error)]
 pub enum Error {
     #[error("missing amount in transaction '{0}'")]
     MissingAmount(TransactionID),
+    #[error("zero amount in transaction '{0}'")]
+    ZeroAmount(TransactionID),
+    #[error("unexpected amount in transaction '{0}'")]
+    UnexpectedAmount(TransactionID),
     #[error("transaction '{0}' already exists")]
     TransactionAlreadyExists(TransactionID),
     #[error("transaction '{0}' does not exist")]
     TransactionNotFound(TransactionID),
     #[error("operation not supported in transaction '{0}' ({1:?} -> {2:?})")]
     OperationNotSupported(TransactionID, Option<TransactionType>, TransactionType),
+    #[error("no disputable amount in transaction '{0}'")]
+    NotDisputable(TransactionID),
     #[error("too much funds to operate transaction '{0}' for client '{1}'")]
     TooManyFunds(TransactionID, ClientID),
     #[error("not enough funds to operate transaction '{0}' for client '{1}'")]
     NotEnoughFunds(TransactionID, ClientID),
     #[error("account locked, cannot operate transaction '{0}' for client '{1}'")]
     AccountLocked(TransactionID, ClientID),
+    #[error("system frozen, cannot operate transaction '{0}' for client '{1}'")]
+    SystemFrozen(TransactionID, ClientID),
+    #[error("client mismatch in transaction '{0}' (expected '{1}', got '{2}')")]
+    ClientMismatch(TransactionID, ClientID, ClientID),
+    #[error("conflicting accounts for client '{0}' in merged processors")]
+    AccountConflict(ClientID),
+    #[error("duplicate account for client '{0}'")]
+    DuplicateClient(ClientID),
+    #[error("too much funds to merge accounts for client '{0}'")]
+    MergeOverflow(ClientID),
+    #[error("amount '{amount}' exceeds limit '{limit}' in transaction '{tx}'")]
+    AmountExceedsLimit { tx: TransactionID, amount: Amount, limit: Amount },
+}
+
+impl Error {
+    /// Get a stable, machine-readable code of this error, eg. `"transaction-already-exists"` to feed dashboards.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::MissingAmount(..) => "missing-amount",
+            Self::ZeroAmount(..) => "zero-amount",
+            Self::UnexpectedAmount(..) => "unexpected-amount",
+            Self::TransactionAlreadyExists(..) => "transaction-already-exists",
+            Self::TransactionNotFound(..) => "transaction-not-found",
+            Self::OperationNotSupported(..) => "operation-not-supported",
+            Self::NotDisputable(..) => "not-disputable",
+            Self::TooManyFunds(..) => "too-many-funds",
+            Self::NotEnoughFunds(..) => "not-enough-funds",
+            Self::AccountLocked(..) => "account-locked",
+            Self::SystemFrozen(..) => "system-frozen",
+            Self::ClientMismatch(..) => "client-mismatch",
+            Self::AccountConflict(..) => "account-conflict",
+            Self::DuplicateClient(..) => "duplicate-client",
+            Self::MergeOverflow(..) => "merge-overflow",
+            Self::AmountExceedsLimit { .. } => "amount-exceeds-limit",
+        }
+    }
+
+    /// Get the ID of the transaction this error relates to, if any.
+    pub fn tx(&self) -> Option<TransactionID> {
+        match *self {
+            Self::MissingAmount(tx)
+            | Self::ZeroAmount(tx)
+            | Self::UnexpectedAmount(tx)
+            | Self::TransactionAlreadyExists(tx)
+            | Self::TransactionNotFound(tx)
+            | Self::OperationNotSupported(tx, ..)
+            | Self::NotDisputable(tx)
+            | Self::TooManyFunds(tx, _)
+            | Self::NotEnoughFunds(tx, _)
+            | Self::AccountLocked(tx, _)
+            | Self::SystemFrozen(tx, _)
+            | Self::ClientMismatch(tx, ..)
+            | Self::AmountExceedsLimit { tx, .. } => Some(tx),
+            Self::AccountConflict(_) | Self::DuplicateClient(_) | Self::MergeOverflow(_) => None,
+        }
+    }
+
+    /// Get the ID of the client this error relates to, if known, ie. the client of the transaction in error.
+    pub fn client(&self) -> Option<ClientID> {
+        match *self {
+            Self::TooManyFunds(_, client)
+            | Self::NotEnoughFunds(_, client)
+            | Self::AccountLocked(_, client)
+            | Self::SystemFrozen(_, client)
+            | Self::ClientMismatch(.., client)
+            | Self::AccountConflict(client)
+            | Self::DuplicateClient(client)
+            | Self::MergeOverflow(client) => Some(client),
+            _ => None,
+        }
+    }
+}
+
+impl Serialize for Error {
+    /// Serialize this error as a structured record, ie. `{code, tx, client, message}`.
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+
+        let mut record = serializer.serialize_struct("Error", 4)?;
+        record.serialize_field("code", self.code())?;
+        record.serialize_field("tx", &self.tx())?;
+        record.serialize_field("client", &self.client())?;
+        record.serialize_field("message", &self.to_string())?;
+        record.end()
+    }
+}
+
+/// A policy to operate transactions on locked accounts, ie. accounts frozen by a chargeback.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LockPolicy {
+    /// Reject any transaction with an `Error::AccountLocked`.
+    #[default]
+    StrictAll,
+    /// Still allow disputes, resolves and chargebacks, eg. to resolve a pending dispute, while rejecting deposits and withdrawals.
+    ///
+    /// Such transactions move funds as on any unlocked account, eg. a resolve releasing held funds back to available funds, while a
+    /// chargeback removes held funds, the account staying locked.
+    AllowDisputes,
+}
+
+/// A policy to resolve accounts of the same client held by two merged processors.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MergePolicy {
+    /// Sum balances of both accounts, the merged account being locked if any of them is locked.
+    #[default]
+    Sum,
+    /// Keep the locked account as is when only one of them is locked, otherwise sum balances of both accounts.
+    LockedWins,
+    /// Reject merging with an `Error::AccountConflict` as soon as a client is held by both processors.
+    Reject,
+}
+
+/// A compact audit snapshot of a processor, ie. its open disputes and locked accounts only.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct AuditSnapshot {
+    /// Transactions currently under dispute, sorted by transaction ID.
+    pub disputes: Vec<DisputeRecord>,
+    /// Locked accounts, sorted by client ID.
+    pub locked_accounts: Vec<Account>,
+}
+
+/// A snapshot of a processor state, to persist then restore it, eg. for crash recovery.
+///
+/// Accounts, including closed ones, transaction history along with dispute timestamps and the frozen state are captured, neither
+/// the processor configuration nor its metrics. Fields added after accounts and transaction history default when missing, so that
+/// snapshots persisted by earlier versions can still be restored.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct ProcessorSnapshot {
+    accounts: Accounts,
+    transactions: Transactions,
+    #[serde(default)]
+    dispute_timestamps: HashMap<TransactionID, Timestamp>,
+    #[serde(default)]
+    frozen: bool,
+    #[serde(default)]
+    closed_accounts: Vec<(ClientID, AccountStatus)>,
+}
+
+/// A disputed transaction record, as part of an audit snapshot.
+#[derive(Clone, Debug, Serialize, PartialEq, Eq)]
+pub struct DisputeRecord {
+    pub tx: TransactionID,
+    pub client: ClientID,
+    pub amount: Amount,
+    pub state: TransactionType,
+}
+
+/// Metrics of a processor, ie. counts of processed transactions.
+#[derive(Clone, Debug, Default, Serialize, PartialEq, Eq)]
+pub struct Metrics {
+    /// Number of successfully processed deposits.
+    pub deposits: u64,
+    /// Number of successfully processed withdrawals.
+    pub withdrawals: u64,
+    /// Number of successfully processed disputes.
+    pub disputes: u64,
+    /// Number of successfully processed resolves.
+    pub resolves: u64,
+    /// Number of successfully processed chargebacks.
+    pub chargebacks: u64,
+    /// Number of successfully processed client closings.
+    pub closes: u64,
+    /// Number of rejected transactions, by error code, eg. `"transaction-already-exists"`, see `Error::code()`.
+    pub errors: HashMap<&'static str, u64>,
+}
+
+impl Metrics {
+    /// Record the result of a processed transaction.
+    fn record(&mut self, r#type: TransactionType, result: &Result<(), Error>) {
+        match result {
+            Ok(()) => {
+                let count = match r#type {
+                    TransactionType::Deposit => &mut self.deposits,
+                    TransactionType::Withdrawal => &mut self.withdrawals,
+                    TransactionType::Dispute => &mut self.disputes,
+                    TransactionType::Resolve => &mut self.resolves,
+                    TransactionType::Chargeback => &mut self.chargebacks,
+                    TransactionType::CloseClient => &mut self.closes,
+                };
+                *count += 1;
+            }
+            Err(err) => *self.errors.entry(err.code()).or_default() += 1,
+        }
+    }
+
+    /// Add up the metrics of another processor.
+    fn merge(&mut self, other: Self) {
+        self.deposits += other.deposits;
+        self.withdrawals += other.withdrawals;
+        self.disputes += other.disputes;
+        self.resolves += other.resolves;
+        self.chargebacks += other.chargebacks;
+        self.closes += other.closes;
+
+        for (code, count) in other.errors {
+            *self.errors.entry(code).or_default() += count;
+        }
+    }
+}
+
+/// The outcome of a processed transaction, ie. either `Ok(())` or its error code, eg. `"transaction-already-exists"`.
+pub type Outcome = std::result::Result<(), &'static str>;
+
+/// An ordered log of transaction outcomes, to diff two runs against each other, eg. a regression run against a golden log.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct OutcomeLog(Vec<(TransactionID, Outcome)>);
+
+/// A difference between two outcome logs, at a given position.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OutcomeDiff {
+    /// Position of the differing entries in both logs.
+    pub position: usize,
+    /// Entry of the left log at this position, if any.
+    pub left: Option<(TransactionID, Outcome)>,
+    /// Entry of the right log at this position, if any.
+    pub right: Option<(TransactionID, Outcome)>,
+}
+
+impl OutcomeLog {
+    /// Iterate over the logged outcomes, in processing order.
+    #[inline]
+    pub fn iter(&self) -> impl Iterator<Item = &(TransactionID, Outcome)> {
+        self.0.iter()
+    }
+
+    /// Compare this log against another one, entry by entry, eg. `golden.diff(&actual)`.
+    ///
+    /// A difference is reported for each position where both logs do not hold the same transaction with the same outcome,
+    /// including positions past the end of the shortest log. Two identical logs have no difference at all.
+    pub fn diff(&self, other: &Self) -> Vec<OutcomeDiff> {
+        (0..self.0.len().max(other.0.len()))
+            .filter_map(|position| {
+                let (left, right) = (self.0.get(position).copied(), other.0.get(position).copied());
+                (left != right).then_some(OutcomeDiff { position, left, right })
+            })
+            .collect()
+    }
+
+    /// Log the outcome of a processed transaction.
+    fn record(&mut self, tx: TransactionID, result: &Result<(), Error>) {
+        self.0.push((tx, result.as_ref().map(|_| ()).map_err(Error::code)));
+    }
+}
+
+impl FromIterator<(TransactionID, Outcome)> for OutcomeLog {
+    fn from_iter<I: IntoIterator<Item = (TransactionID, Outcome)>>(iter: I) -> Self {
+        Self(iter.into_iter().collect())
+    }
+}
+
+/// A transaction handler, driven by a transaction process.
+///
+/// This allows any custom engine to be plugged into the transaction process, `Processor` being the default one.
+pub trait TransactionHandler: Sized {
+    /// A record produced by this handler once all transactions have been handled.
+    type Record;
+
+    /// Handle a single transaction.
+    fn handle(&mut self, transaction: Transaction) -> impl Future<Output = Result<(), Error>>;
+
+    /// Turn this handler into its produced records.
+    fn into_records(self) -> impl Iterator<Item = Self::Record>;
+
+    /// Handle a whole stream of transactions, then give this handler back, eg. to persist its state before producing its records.
+    ///
+    /// Any transaction error is considered recoverable: the transaction is just logged then discarded.
+    fn handle_all(self, transactions: impl Stream<Item = Result<Transaction>>) -> impl Future<Output = Result<Self>> {
+        transactions.try_fold(self, |mut handler, transaction| async move {
+            tracing::debug!("{transaction:?}");
+
+            let summary = TransactionSummary::from(&transaction);
+            if let Err(err) = handler.handle(transaction).await {
+                log_ignored(summary, &err);
+            }
+
+            Ok(handler)
+        })
+    }
+
+    /// Handle a whole stream of transactions, failing fast, then give this handler back.
+    ///
+    /// Unlike `Self::handle_all()`, the first transaction error aborts the whole stream as a `crate::Error::Process`.
+    fn handle_all_strict(self, transactions: impl Stream<Item = Result<Transaction>>) -> impl Future<Output = Result<Self>> {
+        transactions.try_fold(self, |mut handler, transaction| async move {
+            tracing::debug!("{transaction:?}");
+
+            handler.handle(transaction).await?;
+
+            Ok(handler)
+        })
+    }
+
+    /// Handle a stream of transactions on-the-fly.
+    ///
+    /// Any transaction error is considered recoverable: the transaction is just logged then discarded.
+    fn handle_stream(self, transactions: impl Stream<Item = Result<Transaction>>) -> impl Stream<Item = Result<Self::Record>> {
+        self.handle_all(transactions)
+            .map_ok(|handler| stream::iter(handler.into_records()).map(Ok))
+            .try_flatten_stream()
+    }
+
+    /// Handle a stream of transactions on-the-fly, failing fast.
+    ///
+    /// Unlike `Self::handle_stream()`, the first transaction error aborts the whole stream as a `crate::Error::Process`.
+    fn handle_stream_strict(self, transactions: impl Stream<Item = Result<Transaction>>) -> impl Stream<Item = Result<Self::Record>> {
+        self.handle_all_strict(transactions)
+            .map_ok(|handler| stream::iter(handler.into_records()).map(Ok))
+            .try_flatten_stream()
+    }
+}
+
+/// A summary of a transaction, captured before it is handled so that it could still be logged should it be ignored.
+#[derive(Clone, Copy, Debug)]
+struct TransactionSummary {
+    r#type: TransactionType,
+    client: ClientID,
+    tx: TransactionID,
+    amount: Option<Amount>,
+}
+
+impl From<&Transaction> for TransactionSummary {
+    #[inline]
+    fn from(transaction: &Transaction) -> Self {
+        Self {
+            r#type: transaction.r#type,
+            client: transaction.client,
+            tx: transaction.tx,
+            amount: transaction.amount,
+        }
+    }
+}
+
+/// Log a transaction ignored on error, along with the whole transaction as structured fields.
+fn log_ignored(transaction: TransactionSummary, err: &Error) {
+    tracing::error!(
+        code = err.code(),
+        "type" = ?transaction.r#type,
+        client = transaction.client,
+        tx = transaction.tx,
+        amount = transaction.amount.map(tracing::field::display),
+        "Transaction ignored: {err}."
+    )
 }
 
 /// A transaction process status.
-#[derive(Debug)]
-struct TransactionStatus(TransactionType, Amount);
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct TransactionStatus(TransactionType, ClientID, Amount);
 
 impl TransactionStatus {
     fn as_mut(&mut self) -> (&mut TransactionType, Amount) {
-        (&mut self.0, self.1)
+        (&mut self.0, self.2)
+    }
+
+    /// Whether this transaction status originates from the same transaction, ie. with the same type, client and amount.
+    ///
+    /// Only a deposit could be disputed, so that any dispute state originates from a deposit.
+    fn is_replayed_by(&self, transaction: &Transaction) -> bool {
+        let r#type = match self.0 {
+            TransactionType::Withdrawal => TransactionType::Withdrawal,
+            _ => TransactionType::Deposit,
+        };
+
+        r#type == transaction.r#type && self.1 == transaction.client && Some(self.2) == transaction.amount
+    }
+}
+
+type Accounts<S = RandomState> = HashMap<ClientID, AccountStatus, S>;
+type Transactions<S = RandomState> = HashMap<TransactionID, TransactionStatus, S>;
+
+/// A transaction processor configuration.
+#[derive(Clone, Debug, Default)]
+struct Config {
+    /// Suppress empty accounts from the output.
+    suppress_empty: bool,
+    /// Max. amount allowed for a single deposit or withdrawal, if any.
+    max_transaction_amount: Option<Amount>,
+    /// Skip redundant or out-of-order dispute transitions instead of rejecting them.
+    lenient_transitions: bool,
+    /// Which transactions are allowed to operate on locked accounts.
+    lock_policy: LockPolicy,
+    /// Skip exact replays of already registered deposits or withdrawals instead of rejecting them.
+    idempotent_replay: bool,
+    /// Overdraft allowed for any account, ie. how far a withdrawal could drive available funds below zero.
+    overdraft_limit: Amount,
+    /// Track the high-water mark of held funds per client.
+    track_held_high_water: bool,
+    /// Log the outcome of each processed transaction.
+    record_outcomes: bool,
+    /// Collect rejected transactions along with their error.
+    record_rejects: bool,
+    /// Reject transactions carrying an amount they do not expect, ie. disputes, resolves, chargebacks and client closings.
+    reject_unexpected_amount: bool,
+    /// Max. number of accounts to keep, evicting the least recently used ones, if any.
+    account_lru_capacity: Option<usize>,
+    /// Capacity limits of the transaction history.
+    transaction_capacity: TransactionCapacity,
+    /// A hook invoked for each transaction dropped from the transaction history, if any.
+    on_transaction_dropped: Option<DroppedTransactionHook>,
+}
+
+/// A hook invoked for each transaction dropped from the transaction history.
+#[derive(Clone)]
+struct DroppedTransactionHook(Arc<dyn Fn(TransactionID, ClientID) + Send + Sync>);
+
+impl fmt::Debug for DroppedTransactionHook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("DroppedTransactionHook")
+    }
+}
+
+/// Capacity limits of the transaction history.
+#[derive(Clone, Copy, Debug)]
+struct TransactionCapacity {
+    /// Number of transactions from which ended disputes are rolled out.
+    rollout_threshold: usize,
+    /// Max. number of transactions to keep, rolling out the oldest ones.
+    max: usize,
+}
+
+impl Default for TransactionCapacity {
+    fn default() -> Self {
+        Self {
+            rollout_threshold: ROLLOUT_TRANSACTION_THRESHOLD,
+            max: MAX_TRANSACTION_CAPACITY,
+        }
     }
 }
 
-type Accounts = HashMap<ClientID, AccountStatus>;
-type Transactions = HashMap<TransactionID, TransactionStatus>;
+/// A recency tracker of accounts, to find out the least recently used ones.
+#[derive(Clone, Debug, Default)]
+struct AccountRecency {
+    clock: u64,
+    stamps: HashMap<ClientID, u64>,
+    clients: BTreeMap<u64, ClientID>,
+}
+
+impl AccountRecency {
+    /// Mark a client's account as the most recently used one.
+    fn touch(&mut self, client: ClientID) {
+        self.clock += 1;
+
+        if let Some(stamp) = self.stamps.insert(client, self.clock) {
+            self.clients.remove(&stamp);
+        }
+        self.clients.insert(self.clock, client);
+    }
+
+    /// Stop tracking a client's account, eg. once closed.
+    fn remove(&mut self, client: ClientID) {
+        if let Some(stamp) = self.stamps.remove(&client) {
+            self.clients.remove(&stamp);
+        }
+    }
+
+    /// Pop the least recently used client's account.
+    fn pop_coldest(&mut self) -> Option<ClientID> {
+        let (_, client) = self.clients.pop_first()?;
+        self.stamps.remove(&client);
+
+        Some(client)
+    }
+}
 
 /// A transaction processor.
-#[derive(Debug)]
-pub struct Processor {
-    accounts: Accounts,
-    transactions: Transactions,
+///
+/// Accounts and transactions are hashed with `S`, eg. a fixed-seed hasher to get a reproducible iteration order.
+///
+/// A processor can be cloned to checkpoint its whole state in memory, eg. to branch off a "what-if" run.
+#[derive(Clone, Debug)]
+pub struct Processor<S = RandomState> {
+    accounts: Accounts<S>,
+    transactions: Transactions<S>,
+    dispute_timestamps: HashMap<TransactionID, Timestamp>, // opening timestamps of open disputes, see `Self::expire_disputes()`
+    config: Config,
+    recency: AccountRecency,
+    frozen: bool,
+    closed_accounts: Vec<Account>,
+    metrics: Metrics,
+    held_high_water: HashMap<ClientID, Amount>,
+    outcomes: OutcomeLog,
+    rejects: Vec<(Transaction, Error)>,
+    #[cfg(feature = "audit")]
+    ledger: Option<Balance>, // expected total funds of all accounts, if representable
 }
 
 impl Default for Processor {
     fn default() -> Self {
+        Self::with_hasher(RandomState::new())
+    }
+}
+
+impl<S: BuildHasher + Clone> Processor<S> {
+    /// Create a processor hashing its accounts and transactions with `hasher`.
+    pub fn with_hasher(hasher: S) -> Self {
         Self {
-            accounts: Accounts::default(),
-            transactions: Transactions::with_capacity(DEFAULT_TRANSACTION_CAPACITY),
+            accounts: Accounts::with_hasher(hasher.clone()),
+            transactions: Transactions::with_capacity_and_hasher(DEFAULT_TRANSACTION_CAPACITY, hasher),
+            dispute_timestamps: HashMap::new(),
+            config: Config::default(),
+            recency: AccountRecency::default(),
+            frozen: false,
+            closed_accounts: Vec::new(),
+            metrics: Metrics::default(),
+            held_high_water: HashMap::new(),
+            outcomes: OutcomeLog::default(),
+            rejects: Vec::new(),
+            #[cfg(feature = "audit")]
+            ledger: Some(Balance::ZERO),
         }
     }
+
+    /// Validate a stream of transactions against the current state, without mutating it, eg. to check an input before processing it.
+    ///
+    /// Transactions are processed by a throwaway clone of this processor, each one being reported as valid or not as an inner
+    /// result, while errors of the stream itself are passed through as outer ones: unlike `Self::process_strict()`, nothing is
+    /// short-circuited on errors.
+    ///
+    /// Note that the whole state of this processor, ie. all its accounts and transaction history, is cloned upfront, so that
+    /// validating a stream costs as much memory as this processor itself.
+    pub fn validate(&self, transactions: impl Stream<Item = Result<Transaction>>) -> impl Stream<Item = Result<Result<(), Error>>> {
+        let mut processor = self.clone();
+        processor.config.on_transaction_dropped = None;
+        processor.config.record_outcomes = false;
+        processor.config.record_rejects = false;
+
+        transactions.scan(processor, |processor, transaction| {
+            let validity = transaction.map(|transaction| processor.process_transaction(transaction));
+
+            futures::future::ready(Some(validity))
+        })
+    }
 }
 
-impl Processor {
-    /// Process a stream of transactions on-the-fly.
-    pub fn process(transactions: impl Stream<Item = Result<Transaction>>) -> impl Stream<Item = Result<Account>> {
-        transactions
-            .try_fold(Self::default(), |mut processor, transaction| async move {
-                tracing::debug!("{transaction:?}");
+impl<S: BuildHasher> Processor<S> {
+    /// Build a processor suppressing empty accounts from its output, ie. unlocked accounts with no funds.
+    #[inline]
+    pub fn with_suppress_empty(mut self, suppress_empty: bool) -> Self {
+        self.config.suppress_empty = suppress_empty;
+        self
+    }
+
+    /// Build a processor rejecting any deposit or withdrawal whose amount exceeds `max_amount`.
+    #[inline]
+    pub fn with_max_transaction_amount(mut self, max_amount: Amount) -> Self {
+        self.config.max_transaction_amount = Some(max_amount);
+        self
+    }
+
+    /// Build a processor allowing any account to be overdrawn by withdrawals, down to `-limit` available funds.
+    ///
+    /// Without overdraft, ie. a `limit` equal to `0`, a withdrawal exceeding available funds is rejected as an `Error::NotEnoughFunds`.
+    #[inline]
+    pub fn with_overdraft_limit(mut self, limit: Amount) -> Self {
+        self.config.overdraft_limit = limit;
+        self
+    }
+
+    /// Build a processor tracking the high-water mark of held funds per client, see `Self::held_high_water()`.
+    #[inline]
+    pub fn with_held_high_water(mut self, track_held_high_water: bool) -> Self {
+        self.config.track_held_high_water = track_held_high_water;
+        self
+    }
+
+    /// Build a processor logging the outcome of each processed transaction, see `Self::outcomes()`.
+    #[inline]
+    pub fn with_outcome_log(mut self, record_outcomes: bool) -> Self {
+        self.config.record_outcomes = record_outcomes;
+        self
+    }
+
+    /// Build a processor collecting rejected transactions along with their error, see `Self::take_rejects()`.
+    #[inline]
+    pub fn with_reject_log(mut self, record_rejects: bool) -> Self {
+        self.config.record_rejects = record_rejects;
+        self
+    }
+
+    /// Build a processor rejecting any dispute, resolve, chargeback or client closing carrying an amount,
+    /// as an `Error::UnexpectedAmount`, rather than just ignoring this amount.
+    #[inline]
+    pub fn with_reject_unexpected_amount(mut self, reject_unexpected_amount: bool) -> Self {
+        self.config.reject_unexpected_amount = reject_unexpected_amount;
+        self
+    }
+
+    /// Build a processor skipping redundant or out-of-order dispute transitions instead of rejecting them.
+    ///
+    /// When enabled, disputing a transaction already under dispute, or resolving/charging back a transaction not under dispute,
+    /// is considered a no-op rather than an `Error::OperationNotSupported`.
+    ///
+    /// Caution: this lenient ingestion mode is not compliant with the specifications of the transaction process.
+    #[inline]
+    pub fn with_lenient_transitions(mut self, lenient_transitions: bool) -> Self {
+        self.config.lenient_transitions = lenient_transitions;
+        self
+    }
+
+    /// Build a processor operating transactions on locked accounts according to `lock_policy`.
+    #[inline]
+    pub fn with_lock_policy(mut self, lock_policy: LockPolicy) -> Self {
+        self.config.lock_policy = lock_policy;
+        self
+    }
+
+    /// Build a processor skipping exact replays of already registered deposits or withdrawals instead of rejecting them,
+    /// eg. to resume processing a replayed input.
+    ///
+    /// When enabled, a deposit or withdrawal with the same transaction ID, type, client and amount as an already registered one
+    /// is considered a no-op, while a conflicting reuse of its transaction ID is still rejected as an `Error::TransactionAlreadyExists`.
+    ///
+    /// Caution: transactions rolled out of the transaction history could not be recognized, and would be registered again.
+    #[inline]
+    pub fn with_idempotent_replay(mut self, idempotent_replay: bool) -> Self {
+        self.config.idempotent_replay = idempotent_replay;
+        self
+    }
+
+    /// Build a processor keeping at most `capacity` accounts, evicting the least recently used ones to make room for new ones.
+    ///
+    /// Each eviction is logged, but evicted accounts are definitely lost and will not appear in the output,
+    /// should a further transaction occur for the same client, its account will start afresh.
+    /// Accounts already held by this processor are considered the least recently used ones.
+    ///
+    /// # Panics
+    /// This function will panic when called with a `capacity` equal to `0`.
+    pub fn with_account_lru(mut self, capacity: usize) -> Self {
+        assert!(capacity > 0);
+
+        self.config.account_lru_capacity = Some(capacity);
+
+        let untracked: Vec<_> = self
+            .accounts
+            .keys()
+            .filter(|client| !self.recency.stamps.contains_key(client))
+            .copied()
+            .collect();
+        for client in untracked {
+            self.recency.touch(client);
+        }
+        self
+    }
+
+    /// Build a processor with custom capacity limits of its transaction history, eg. for memory-constrained deployments.
+    ///
+    /// `default` transactions are reserved up front, ended disputes are rolled out from `rollout_threshold` transactions,
+    /// and the oldest transactions are rolled out so that at most `max` transactions are kept.
+    ///
+    /// # Panics
+    /// This function will panic when called with a `max` capacity equal to `0`.
+    pub fn with_capacity(mut self, default: usize, rollout_threshold: usize, max: usize) -> Self {
+        assert!(max > 0);
+
+        self.config.transaction_capacity = TransactionCapacity { rollout_threshold, max };
+
+        self.transactions.shrink_to(default);
+        self.transactions.reserve(default.saturating_sub(self.transactions.len()));
+        self
+    }
+
+    /// Build a processor invoking `hook` with the transaction and client IDs of each transaction dropped from its history.
+    ///
+    /// Once dropped, a transaction can no longer be disputed, any further reference to it resulting in an `Error::TransactionNotFound`,
+    /// so this hook allows recording such transactions to correlate these errors.
+    /// Only transactions dropped to honor the max. capacity are reported, not rolled out ended disputes which cannot be disputed anymore anyway.
+    #[inline]
+    pub fn with_on_transaction_dropped(mut self, hook: impl Fn(TransactionID, ClientID) + Send + Sync + 'static) -> Self {
+        self.config.on_transaction_dropped = Some(DroppedTransactionHook(Arc::new(hook)));
+        self
+    }
 
-                // processor.process_transaction(transaction)?;
-                if let Err(err) = processor.process_transaction(transaction) {
-                    tracing::error!("Transaction ignored: {err}.")
+    /// Process a stream of transactions on-the-fly with this processor.
+    #[inline]
+    pub fn process_stream(self, transactions: impl Stream<Item = Result<Transaction>>) -> impl Stream<Item = Result<Account>> {
+        self.handle_stream(transactions)
+    }
+
+    /// Process a stream of transactions on-the-fly with this processor, emitting accounts incrementally.
+    ///
+    /// Unlike `Self::process_stream()`, an account is emitted as soon as its client is closed by a `CloseClient` transaction,
+    /// and removed from this processor to free memory, while all remaining accounts are emitted at the end of the stream.
+    pub fn process_incremental(self, transactions: impl Stream<Item = Result<Transaction>>) -> impl Stream<Item = Result<Account>> {
+        stream::unfold(Some((self, Box::pin(transactions))), |state| async move {
+            let (mut processor, mut transactions) = state?;
+
+            match transactions.next().await {
+                Some(Ok(transaction)) => {
+                    processor.process_or_discard(transaction);
+
+                    let closed_accounts: Vec<_> = processor.drain_closed_accounts().map(Ok).collect();
+                    Some((closed_accounts, Some((processor, transactions))))
                 }
+                Some(Err(err)) => Some((vec![Err(err)], None)),
+                None => Some((processor.into_records().map(Ok).collect(), None)),
+            }
+        })
+        .flat_map(stream::iter)
+    }
 
-                Ok(processor)
-            })
-            .map_ok(|processor| stream::iter(processor.accounts).map(Into::into).map(Ok))
-            .try_flatten_stream()
+    /// Process a stream of transactions on-the-fly with this processor, yielding snapshots of all accounts periodically.
+    ///
+    /// A snapshot of current accounts is yielded every `interval` transactions, as well as a final one at the end of the stream,
+    /// accounts being sorted by client ID in every snapshot.
+    ///
+    /// # Panics
+    /// This function will panic when called with an `interval` equal to `0`.
+    pub fn process_snapshots(
+        self,
+        transactions: impl Stream<Item = Result<Transaction>>,
+        interval: usize,
+    ) -> impl Stream<Item = Result<Vec<Account>>> {
+        assert!(interval > 0);
+
+        stream::unfold(Some((self, Box::pin(transactions), 0)), move |state| async move {
+            let (mut processor, mut transactions, mut count) = state?;
+
+            loop {
+                match transactions.next().await {
+                    Some(Ok(transaction)) => {
+                        processor.process_or_discard(transaction);
+
+                        count += 1;
+                        if count % interval == 0 {
+                            let snapshot = processor.accounts_snapshot();
+                            return Some((Ok(snapshot), Some((processor, transactions, count))));
+                        }
+                    }
+                    Some(Err(err)) => return Some((Err(err), None)),
+                    None => return Some((Ok(processor.into_records().collect()), None)),
+                }
+            }
+        })
     }
 
-    /// Process a single transaction.
-    pub fn process_transaction(&mut self, transaction: Transaction) -> Result<(), Error> {
-        let account_status = self.accounts.entry(transaction.client).or_default();
+    /// Clear this processor state, to reuse it for another independent batch of transactions, eg. when processing many small files.
+    ///
+    /// All accounts and their balances are reset, as well as transaction history, metrics and logs, while the configuration is kept.
+    /// Allocated capacity is preserved, so that the transaction history reservation is not paid again.
+    pub fn clear(&mut self) {
+        self.accounts.clear();
+        self.transactions.clear();
+        self.dispute_timestamps.clear();
+        self.recency = AccountRecency::default();
+        self.frozen = false;
+        self.closed_accounts.clear();
+        self.metrics = Metrics::default();
+        self.held_high_water.clear();
+        self.outcomes.0.clear();
+        self.rejects.clear();
+        self.sync_ledger();
+    }
+
+    /// Take a snapshot of this processor state, to restore it later on with `Self::restore()`.
+    pub fn snapshot(&self) -> ProcessorSnapshot {
+        ProcessorSnapshot {
+            accounts: self.accounts.iter().map(|(client, status)| (*client, status.clone())).collect(),
+            transactions: self.transactions.iter().map(|(tx, status)| (*tx, status.clone())).collect(),
+            dispute_timestamps: self.dispute_timestamps.clone(),
+            frozen: self.frozen,
+            closed_accounts: self
+                .closed_accounts
+                .iter()
+                .map(|account| (account.client, account.status.clone()))
+                .collect(),
+        }
+    }
+
+    /// Insert an account with an arbitrary status, eg. to set up a test scenario.
+    ///
+    /// Any previous status of this client's account is replaced.
+    #[cfg(any(test, feature = "test-util"))]
+    pub fn insert_account(&mut self, client: ClientID, status: AccountStatus) {
+        self.accounts.insert(client, status);
+        self.touch_account(client);
+        self.evict_accounts();
+        self.sync_ledger();
+    }
+
+    /// Load pre-built accounts, eg. account records of a previous run, alongside the current ones.
+    ///
+    /// Should a client appear twice, either among loaded accounts or along with a current account, an `Error::DuplicateClient` is
+    /// returned rather than silently overwriting any account, while no account at all is loaded.
+    pub fn load_accounts(&mut self, accounts: impl IntoIterator<Item = Account>) -> Result<(), Error> {
+        let accounts: Vec<_> = accounts.into_iter().collect();
+
+        let mut clients = HashSet::with_capacity(accounts.len());
+        if let Some(account) = accounts
+            .iter()
+            .find(|account| self.accounts.contains_key(&account.client) || !clients.insert(account.client))
+        {
+            return Err(Error::DuplicateClient(account.client));
+        }
+
+        for account in accounts {
+            self.accounts.insert(account.client, account.status);
+            self.touch_account(account.client);
+        }
+        self.evict_accounts();
+        self.sync_ledger();
 
-        if account_status.locked {
-            return Err(Error::AccountLocked(transaction.tx, transaction.client));
+        Ok(())
+    }
+
+    /// Freeze all accounts, eg. during an incident, so that no further deposit or withdrawal is processed until thawed.
+    ///
+    /// Unlike a chargeback, no account gets locked, and disputes are still processed meanwhile.
+    #[inline]
+    pub fn freeze_all(&mut self) {
+        self.frozen = true;
+    }
+
+    /// Thaw all accounts previously frozen with `Self::freeze_all()`, resuming deposits and withdrawals.
+    #[inline]
+    pub fn thaw_all(&mut self) {
+        self.frozen = false;
+    }
+
+    /// Whether all accounts are currently frozen.
+    #[inline]
+    pub fn is_frozen(&self) -> bool {
+        self.frozen
+    }
+
+    /// Look up the current status of a client's account, if any.
+    #[inline]
+    pub fn account_status(&self, client: ClientID) -> Option<&AccountStatus> {
+        self.accounts.get(&client)
+    }
+
+    /// Get the headroom of a client's available funds, ie. the largest deposit that would not be rejected with an `Error::TooManyFunds`,
+    /// eg. to split a large deposit into acceptable chunks.
+    ///
+    /// A client without any account has the whole `Amount::MAX` headroom. Only funds are considered, not whether the account is locked.
+    pub fn available_headroom(&self, client: ClientID) -> Amount {
+        let available = self.accounts.get(&client).map_or(Balance::ZERO, |status| status.available);
+
+        Balance::MAX.saturating_sub(available).unsigned_abs() // never negative, clamped for negative available funds
+    }
+
+    /// Preview the effect of a transaction on its client's account, without applying it.
+    ///
+    /// The hypothetical status of this client's account is returned, or the error the transaction would be rejected with.
+    pub fn preview(&self, transaction: &Transaction) -> Result<AccountStatus, Error> {
+        Processor::check_amount(&self.config, transaction)?;
+
+        let mut account_status = self.accounts.get(&transaction.client).cloned().unwrap_or_default();
+
+        if transaction.r#type == TransactionType::CloseClient {
+            return Ok(account_status);
+        }
+
+        if self.frozen && matches!(transaction.r#type, TransactionType::Deposit | TransactionType::Withdrawal) {
+            return Err(Error::SystemFrozen(transaction.tx, transaction.client));
         }
 
+        Processor::check_locked(&self.config, transaction, &account_status)?;
+
+        // only the referred transaction is needed, while no dropped transaction should be reported
+        let config = Config {
+            on_transaction_dropped: None,
+            ..self.config.clone()
+        };
+        let mut transactions: Transactions = self
+            .transactions
+            .get_key_value(&transaction.tx)
+            .map(|(tx, transaction_status)| (*tx, transaction_status.clone()))
+            .into_iter()
+            .collect();
+
         match transaction.r#type {
             TransactionType::Deposit | TransactionType::Withdrawal => {
-                Self::register_transaction(&mut self.transactions, transaction, account_status)?;
+                Processor::register_transaction(&config, &mut transactions, transaction.clone(), &mut account_status)?;
+            }
+            _ => {
+                Processor::dispute_transaction(&config, &mut transactions, transaction, &mut account_status)?;
             }
-            t => Self::dispute_transaction(&mut self.transactions, transaction.tx, t, account_status)?,
         }
 
-        Ok(())
+        Ok(account_status)
     }
 
-    /// Manage a new transaction.
-    fn register_transaction(
-        transactions: &mut Transactions,
-        transaction: Transaction,
-        account_status: &mut AccountStatus,
-    ) -> Result<(), Error> {
-        if transactions.contains_key(&transaction.tx) {
-            return Err(Error::TransactionAlreadyExists(transaction.tx));
+    /// Get the max. held funds a client's account ever reached, if tracked with `Self::with_held_high_water()`.
+    ///
+    /// Unlike current held funds, this high-water mark never decreases, eg. once a dispute is resolved.
+    #[inline]
+    pub fn held_high_water(&self, client: ClientID) -> Option<Amount> {
+        self.held_high_water.get(&client).copied()
+    }
+
+    /// List all current accounts, eg. to inspect them mid-stream.
+    pub fn accounts(&self) -> impl Iterator<Item = Account> + '_ {
+        self.accounts
+            .iter()
+            .map(|(client, status)| Account::from((*client, status.clone())))
+    }
+
+    /// List all currently locked accounts.
+    pub fn locked_accounts(&self) -> impl Iterator<Item = Account> + '_ {
+        self.accounts().filter(|account| account.status.locked)
+    }
+
+    /// Sum up the total funds of all accounts, ie. their available and held funds.
+    ///
+    /// The sum saturates at the bounds of a balance, should it not be represented. Closed accounts are not accounted for.
+    pub fn audit_total(&self) -> Balance {
+        let total = self.wide_total();
+
+        total.to_signed_decimal().unwrap_or(match total < BalanceTotal::ZERO {
+            true => Balance::MIN,
+            false => Balance::MAX,
+        })
+    }
+
+    /// Sum up the total funds of all accounts, without any overflow.
+    fn wide_total(&self) -> BalanceTotal {
+        let mut total = BalanceTotal::ZERO;
+        for status in self.accounts.values() {
+            total += status.total();
         }
 
-        let transaction_status = match transaction.r#type {
-            t @ TransactionType::Deposit => {
-                let amount = transaction.amount.ok_or(Error::MissingAmount(transaction.tx))?;
-                if Amount::MAX - account_status.available < amount {
-                    return Err(Error::TooManyFunds(transaction.tx, transaction.client));
-                }
+        total
+    }
 
-                account_status.available += amount;
+    /// Take a compact audit snapshot of this processor, capturing only open disputes and locked accounts.
+    pub fn audit_snapshot(&self) -> AuditSnapshot {
+        let mut disputes: Vec<_> = self
+            .transactions
+            .iter()
+            .filter(|(_, TransactionStatus(state, ..))| *state == TransactionType::Dispute)
+            .map(|(tx, TransactionStatus(state, client, amount))| DisputeRecord {
+                tx: *tx,
+                client: *client,
+                amount: *amount,
+                state: *state,
+            })
+            .collect();
+        disputes.sort_unstable_by_key(|dispute| dispute.tx);
 
-                TransactionStatus(t, amount)
-            }
-            t @ TransactionType::Withdrawal => {
-                let amount = transaction.amount.ok_or(Error::MissingAmount(transaction.tx))?;
-                if account_status.available < amount {
-                    return Err(Error::NotEnoughFunds(transaction.tx, transaction.client));
-                }
+        let mut locked_accounts: Vec<_> = self.locked_accounts().collect();
+        locked_accounts.sort_unstable_by_key(|account| account.client);
 
-                account_status.available -= amount;
+        AuditSnapshot { disputes, locked_accounts }
+    }
+
+    /// Release held funds no longer backed by any open dispute, eg. after disputed transactions have been rolled out of history.
+    ///
+    /// This is a one-time cleanup aid: held funds of each unlocked account exceeding the total of its open disputes are considered
+    /// orphaned, and released back to available funds. Each adjustment is logged and reported as `(client, released funds)`.
+    pub fn reconcile_held(&mut self) -> Vec<(ClientID, Balance)> {
+        let disputed = self.disputed_funds();
+
+        let mut adjustments = Vec::new();
+        for (client, status) in self.accounts.iter_mut().filter(|(_, status)| !status.locked) {
+            let orphaned = status.held.checked_sub(disputed.get(client).copied().unwrap_or_default());
+
+            if let Some(orphaned) = orphaned.filter(|orphaned| *orphaned > Balance::ZERO) {
+                if let Err(err) = status.checked_release(orphaned) {
+                    tracing::warn!("Held funds not released: '{orphaned}' for client '{client}' ({err}).");
+                    continue;
+                }
+                tracing::warn!("Held funds released: '{orphaned}' for client '{client}'.");
 
-                TransactionStatus(t, amount)
+                adjustments.push((*client, orphaned));
             }
-            t => return Err(Error::OperationNotSupported(transaction.tx, None, t)),
-        };
+        }
+        adjustments.sort_unstable_by_key(|(client, _)| *client);
+
+        adjustments
+    }
 
-        Self::rollout_transactions(transactions, ROLLOUT_TRANSACTION_THRESHOLD, MAX_TRANSACTION_CAPACITY);
+    /// Settle held funds of all accounts, ie. recompute them from open disputes, eg. after a batch of resolves.
+    ///
+    /// Unlike `Self::reconcile_held()`, any drift is corrected: held funds of each unlocked account are set to the total of its open
+    /// disputes, the difference being moved from/to available funds so that total funds are unchanged. Each adjustment is logged
+    /// and reported as `(client, released funds)`, released funds being negative should held funds have been missing.
+    pub fn settle(&mut self) -> Vec<(ClientID, Balance)> {
+        let disputed = self.disputed_funds();
+
+        let mut adjustments = Vec::new();
+        for (client, status) in self.accounts.iter_mut().filter(|(_, status)| !status.locked) {
+            let Some(released) = status.held.checked_sub(disputed.get(client).copied().unwrap_or_default()) else {
+                tracing::warn!("Held funds not settled for client '{client}' (disputed funds overflow).");
+                continue;
+            };
+
+            if released != Balance::ZERO {
+                if let Err(err) = status.checked_release(released) {
+                    tracing::warn!("Held funds not settled: '{released}' for client '{client}' ({err}).");
+                    continue;
+                }
+                tracing::warn!("Held funds settled: '{released}' released for client '{client}'.");
 
-        transactions.insert(transaction.tx, transaction_status);
+                adjustments.push((*client, released));
+            }
+        }
+        adjustments.sort_unstable_by_key(|(client, _)| *client);
+
+        adjustments
+    }
+
+    /// Expire open disputes older than a cutoff, ie. resolve any dispute opened strictly before `before`, releasing its held funds.
+    ///
+    /// Only disputes opened by a timestamped transaction could expire, while disputes of locked accounts are left open, as with
+    /// `Self::settle()`. Each expired dispute is logged, counted as a resolve in metrics and outcomes, and reported as
+    /// `(client, transaction)`.
+    pub fn expire_disputes(&mut self, before: Timestamp) -> Vec<(ClientID, TransactionID)> {
+        // timestamps of disputes no longer open, eg. rolled out of history, are pruned along the way
+        let transactions = &self.transactions;
+        self.dispute_timestamps
+            .retain(|tx, _| matches!(transactions.get(tx), Some(TransactionStatus(TransactionType::Dispute, ..))));
+
+        let mut expired = Vec::new();
+        for (tx, _) in self.dispute_timestamps.iter().filter(|(_, timestamp)| **timestamp < before) {
+            let Some(TransactionStatus(state, client, amount)) = self.transactions.get_mut(tx) else {
+                continue;
+            };
+            let Some(status) = self.accounts.get_mut(client).filter(|status| !status.locked) else {
+                continue;
+            };
+            if let Err(err) = status.release(*amount) {
+                tracing::warn!("Dispute not expired: '{tx}' for client '{client}' ({err}).");
+                continue;
+            }
+            tracing::info!("Dispute expired: '{tx}' resolved for client '{client}'.");
+
+            *state = TransactionType::Resolve;
+            expired.push((*client, *tx));
+        }
+        expired.sort_unstable();
+
+        for (_, tx) in &expired {
+            self.dispute_timestamps.remove(tx);
+            self.metrics.record(TransactionType::Resolve, &Ok(()));
+            if self.config.record_outcomes {
+                self.outcomes.record(*tx, &Ok(()));
+            }
+        }
+        self.sync_ledger();
+
+        expired
+    }
+
+    /// Compute the total funds of open disputes per client, saturating at the bounds of a balance.
+    fn disputed_funds(&self) -> HashMap<ClientID, Balance> {
+        let mut disputed: HashMap<ClientID, Balance> = HashMap::new();
+        for TransactionStatus(state, client, amount) in self.transactions.values() {
+            if *state == TransactionType::Dispute {
+                let funds = disputed.entry(*client).or_default();
+                *funds = funds.saturating_add((*amount).into());
+            }
+        }
+
+        disputed
+    }
+
+    /// Merge another processor into this one, eg. when processing transactions in several shards.
+    ///
+    /// Accounts of the same client held by both processors are resolved according to the given `policy`,
+    /// while transaction history is kept from this processor should the same transaction exist in both.
+    /// Merged accounts are considered the most recently used ones, should accounts be evicted with `Self::with_account_lru()`.
+    ///
+    /// Should summed balances of a client not be represented, an `Error::MergeOverflow` is returned while nothing at all is merged.
+    /// On error, the other processor is given back untouched along with the error, eg. to merge it again with another `policy`.
+    pub fn merge(&mut self, other: Self, policy: MergePolicy) -> Result<(), (Error, Box<Self>)> {
+        // merged accounts are resolved upfront, so that both processors are left untouched on error
+        let accounts = match self.merged_accounts(&other, policy) {
+            Ok(accounts) => accounts,
+            Err(err) => return Err((err, Box::new(other))),
+        };
+
+        // untracked accounts first, then tracked ones in their order of use
+        let mut clients: Vec<_> = accounts.iter().map(|(client, _)| *client).collect();
+        clients.sort_by_key(|client| other.recency.stamps.get(client).copied());
+
+        self.accounts.extend(accounts);
+        for client in clients {
+            self.touch_account(client);
+        }
+        self.evict_accounts();
+
+        for (tx, transaction_status) in other.transactions {
+            if let Entry::Vacant(entry) = self.transactions.entry(tx) {
+                entry.insert(transaction_status);
+
+                if let Some(timestamp) = other.dispute_timestamps.get(&tx) {
+                    self.dispute_timestamps.insert(tx, *timestamp);
+                }
+            }
+        }
+
+        self.closed_accounts.extend(other.closed_accounts);
+        self.metrics.merge(other.metrics);
+
+        for (client, other_high_water) in other.held_high_water {
+            let high_water = self.held_high_water.entry(client).or_default();
+            *high_water = other_high_water.max(*high_water);
+        }
+
+        self.outcomes.0.extend(other.outcomes.0);
+        self.rejects.extend(other.rejects);
+        self.sync_ledger();
+
+        Ok(())
+    }
+
+    /// Resolve the accounts of another processor to be merged into this one, see `Self::merge()`.
+    fn merged_accounts(&self, other: &Self, policy: MergePolicy) -> Result<Vec<(ClientID, AccountStatus)>, Error> {
+        let mut accounts = Vec::with_capacity(other.accounts.len());
+        for (&client, other_status) in &other.accounts {
+            let account_status = match self.accounts.get(&client) {
+                None => other_status.clone(),
+                Some(_) if policy == MergePolicy::Reject => return Err(Error::AccountConflict(client)),
+                Some(account_status) if policy == MergePolicy::LockedWins && account_status.locked != other_status.locked => {
+                    match other_status.locked {
+                        true => other_status.clone(),
+                        false => account_status.clone(),
+                    }
+                }
+                Some(account_status) => AccountStatus {
+                    available: (account_status.available)
+                        .checked_add(other_status.available)
+                        .ok_or(Error::MergeOverflow(client))?,
+                    held: (account_status.held)
+                        .checked_add(other_status.held)
+                        .ok_or(Error::MergeOverflow(client))?,
+                    locked: account_status.locked || other_status.locked,
+                },
+            };
+            accounts.push((client, account_status));
+        }
+
+        Ok(accounts)
+    }
+
+    /// Process a single transaction.
+    ///
+    /// A dispute always holds the whole disputed amount, even if already withdrawn, in which case available funds become negative
+    /// and any further withdrawal is rejected with an `Error::NotEnoughFunds` until they are restored, eg. by a resolve or a deposit.
+    ///
+    /// Once closed by a `CloseClient` transaction, a client's account is set aside until emitted, should a further transaction occur
+    /// for the same client, its account will start afresh.
+    pub fn process_transaction(&mut self, transaction: Transaction) -> Result<(), Error> {
+        let (r#type, client, tx) = (transaction.r#type, transaction.client, transaction.tx);
+        let created = !self.accounts.contains_key(&client);
+        let rejected = self.config.record_rejects.then(|| transaction.clone());
+        #[cfg(feature = "audit")]
+        let ledger_change = self.ledger_change(&transaction);
+        let result = self.apply_transaction(transaction);
+        self.metrics.record(r#type, &result);
+
+        #[cfg(feature = "audit")]
+        if result.is_ok() {
+            let total = self.checked_total();
+
+            // an unrepresentable ledger cannot be checked, only resynchronized
+            if let Some(ledger) = self
+                .ledger
+                .zip(ledger_change)
+                .and_then(|(ledger, change)| ledger.checked_add(change))
+            {
+                assert_eq!(total, Some(ledger), "accounts total diverging from ledger after transaction '{tx}'");
+            }
+            self.ledger = total;
+        }
+
+        // accounts are only evicted once a transaction succeeded, so that a rejected one never drops any account
+        if self.config.account_lru_capacity.is_some() {
+            match &result {
+                _ if r#type == TransactionType::CloseClient => self.recency.remove(client),
+                Err(_) if created => {
+                    self.accounts.remove(&client); // no room taken by a rejected transaction, its account being empty
+                }
+                _ => {
+                    self.touch_account(client);
+                    self.evict_accounts();
+                }
+            }
+        }
+
+        if self.config.record_outcomes {
+            self.outcomes.record(tx, &result);
+        }
+        if let (Some(transaction), Err(err)) = (rejected, &result) {
+            self.rejects.push((transaction, err.clone()));
+        }
+
+        result
+    }
+
+    /// Get the metrics of this processor, ie. counts of transactions processed so far.
+    #[inline]
+    pub fn metrics(&self) -> &Metrics {
+        &self.metrics
+    }
+
+    /// Get the outcome log of this processor, if enabled with `Self::with_outcome_log()`.
+    ///
+    /// Merged processors get their logs appended to each other.
+    #[inline]
+    pub fn outcomes(&self) -> &OutcomeLog {
+        &self.outcomes
+    }
+
+    /// Take the transactions rejected so far along with their error, in processing order, if enabled with `Self::with_reject_log()`.
+    #[inline]
+    pub fn take_rejects(&mut self) -> Vec<(Transaction, Error)> {
+        std::mem::take(&mut self.rejects)
+    }
+
+    /// Apply a single transaction to this processor.
+    fn apply_transaction(&mut self, transaction: Transaction) -> Result<(), Error> {
+        Processor::check_amount(&self.config, &transaction)?;
+
+        if transaction.r#type == TransactionType::CloseClient {
+            if let Some(status) = self.accounts.remove(&transaction.client) {
+                self.closed_accounts.push(Account::from((transaction.client, status)));
+            }
+
+            return Ok(());
+        }
+
+        if self.frozen && matches!(transaction.r#type, TransactionType::Deposit | TransactionType::Withdrawal) {
+            return Err(Error::SystemFrozen(transaction.tx, transaction.client));
+        }
+
+        let account_status = self.accounts.entry(transaction.client).or_default();
+
+        Processor::check_locked(&self.config, &transaction, account_status)?;
+
+        match transaction.r#type {
+            TransactionType::Deposit | TransactionType::Withdrawal => {
+                Processor::register_transaction(&self.config, &mut self.transactions, transaction, account_status)?;
+            }
+            t => {
+                if Processor::dispute_transaction(&self.config, &mut self.transactions, &transaction, account_status)? {
+                    match transaction.timestamp.filter(|_| t == TransactionType::Dispute) {
+                        Some(timestamp) => self.dispute_timestamps.insert(transaction.tx, timestamp),
+                        None => self.dispute_timestamps.remove(&transaction.tx),
+                    };
+                }
+
+                if self.config.track_held_high_water {
+                    if let Ok(held) = Amount::try_from(account_status.held) {
+                        let high_water = self.held_high_water.entry(transaction.client).or_default();
+                        *high_water = held.max(*high_water);
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Compute how much the total funds of all accounts should change, should a transaction succeed.
+    ///
+    /// Returns `None` should the change not be represented, eg. when closing an account whose total funds overflow a balance.
+    #[cfg(feature = "audit")]
+    fn ledger_change(&self, transaction: &Transaction) -> Option<Balance> {
+        let amount = transaction.amount.unwrap_or_default();
+
+        let change = match transaction.r#type {
+            // skipped replays or rejected duplicates
+            TransactionType::Deposit | TransactionType::Withdrawal if self.transactions.contains_key(&transaction.tx) => Balance::ZERO,
+            TransactionType::Deposit => amount.into(),
+            TransactionType::Withdrawal => -amount,
+            TransactionType::Chargeback => match self.transactions.get(&transaction.tx) {
+                Some(TransactionStatus(TransactionType::Dispute, _, amount)) => -*amount,
+                _ => Balance::ZERO, // skipped out-of-order chargeback
+            },
+            TransactionType::CloseClient => match self.accounts.get(&transaction.client) {
+                Some(status) => -status.total().to_signed_decimal()?,
+                None => Balance::ZERO,
+            },
+            TransactionType::Dispute | TransactionType::Resolve => Balance::ZERO,
+        };
+
+        Some(change)
+    }
+
+    /// Sum up the total funds of all accounts, returning `None` should the sum not be represented.
+    #[cfg(feature = "audit")]
+    fn checked_total(&self) -> Option<Balance> {
+        self.wide_total().to_signed_decimal()
+    }
+
+    /// Resynchronize the running ledger with accounts, after funds have been moved outside of transactions.
+    #[inline]
+    fn sync_ledger(&mut self) {
+        #[cfg(feature = "audit")]
+        {
+            self.ledger = self.checked_total();
+        }
+    }
+
+    /// Mark a client's account as the most recently used one, if accounts are evicted with `Self::with_account_lru()`.
+    #[inline]
+    fn touch_account(&mut self, client: ClientID) {
+        if self.config.account_lru_capacity.is_some() {
+            self.recency.touch(client);
+        }
+    }
+
+    /// Evict the least recently used accounts beyond the capacity configured with `Self::with_account_lru()`, if any.
+    fn evict_accounts(&mut self) {
+        let Some(capacity) = self.config.account_lru_capacity else {
+            return;
+        };
+
+        while self.accounts.len() > capacity {
+            let Some(coldest) = self.recency.pop_coldest() else {
+                break; // untracked accounts
+            };
+
+            if let Some(account_status) = self.accounts.remove(&coldest) {
+                tracing::warn!("Account dropped: '{coldest}' ({account_status:?}).");
+            }
+        }
+        self.sync_ledger(); // dropped accounts take their funds away
+    }
+
+    /// Process a single transaction, just logging then discarding it on error.
+    fn process_or_discard(&mut self, transaction: Transaction) {
+        tracing::debug!("{transaction:?}");
+
+        let summary = TransactionSummary::from(&transaction);
+        if let Err(err) = self.process_transaction(transaction) {
+            log_ignored(summary, &err);
+        }
+    }
+
+    /// Take a snapshot of current accounts, filtered as records and sorted by client ID as by `Self::into_records()`.
+    fn accounts_snapshot(&self) -> Vec<Account> {
+        let suppress_empty = self.config.suppress_empty;
+
+        let mut accounts: Vec<_> = self
+            .accounts()
+            .filter(|account| !suppress_empty || !account.status.is_empty())
+            .collect();
+        accounts.sort_unstable_by_key(|account| account.client);
+
+        accounts
+    }
+
+    /// Drain accounts closed so far, filtered as records.
+    fn drain_closed_accounts(&mut self) -> impl Iterator<Item = Account> + '_ {
+        let suppress_empty = self.config.suppress_empty;
+
+        self.closed_accounts
+            .drain(..)
+            .filter(move |account| !suppress_empty || !account.status.is_empty())
+    }
+}
+
+impl Processor {
+    /// Process a stream of transactions on-the-fly.
+    #[inline]
+    pub fn process(transactions: impl Stream<Item = Result<Transaction>>) -> impl Stream<Item = Result<Account>> {
+        Self::default().process_stream(transactions)
+    }
+
+    /// Process a stream of transactions on-the-fly, aborting on the first transaction error.
+    #[inline]
+    pub fn process_strict(transactions: impl Stream<Item = Result<Transaction>>) -> impl Stream<Item = Result<Account>> {
+        Self::default().handle_stream_strict(transactions)
+    }
+
+    /// Restore a processor from a snapshot of its state, taken with `Self::snapshot()`.
+    ///
+    /// The restored processor gets a default configuration, which could be adapted by chaining builders as usual.
+    pub fn restore(snapshot: ProcessorSnapshot) -> Self {
+        let mut processor = Self {
+            accounts: snapshot.accounts,
+            dispute_timestamps: snapshot.dispute_timestamps,
+            frozen: snapshot.frozen,
+            closed_accounts: snapshot.closed_accounts.into_iter().map(Account::from).collect(),
+            ..Default::default()
+        };
+        processor.transactions.extend(snapshot.transactions);
+        processor.sync_ledger();
+
+        processor
+    }
+
+    /// Check whether a transaction is allowed to operate on an account, wrt. its locked state.
+    fn check_locked(config: &Config, transaction: &Transaction, account_status: &AccountStatus) -> Result<(), Error> {
+        let allowed = match config.lock_policy {
+            LockPolicy::StrictAll => false,
+            LockPolicy::AllowDisputes => matches!(
+                transaction.r#type,
+                TransactionType::Dispute | TransactionType::Resolve | TransactionType::Chargeback
+            ),
+        };
+
+        match account_status.locked && !allowed {
+            true => Err(Error::AccountLocked(transaction.tx, transaction.client)),
+            false => Ok(()),
+        }
+    }
+
+    /// Check the amount of a transaction upfront, before any account is involved.
+    ///
+    /// A deposit or withdrawal must carry a non-zero amount, while any other transaction should carry none if configured so.
+    fn check_amount(config: &Config, transaction: &Transaction) -> Result<(), Error> {
+        match (transaction.r#type, transaction.amount) {
+            (TransactionType::Deposit | TransactionType::Withdrawal, None) => Err(Error::MissingAmount(transaction.tx)),
+            (TransactionType::Deposit | TransactionType::Withdrawal, Some(amount)) if !amount.is_positive() => {
+                Err(Error::ZeroAmount(transaction.tx))
+            }
+            (TransactionType::Deposit | TransactionType::Withdrawal, _) => Ok(()),
+            (_, Some(_)) if config.reject_unexpected_amount => Err(Error::UnexpectedAmount(transaction.tx)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Manage a new transaction.
+    fn register_transaction<H: BuildHasher>(
+        config: &Config,
+        transactions: &mut Transactions<H>,
+        transaction: Transaction,
+        account_status: &mut AccountStatus,
+    ) -> Result<(), Error> {
+        if let Some(transaction_status) = transactions.get(&transaction.tx) {
+            if config.idempotent_replay && transaction_status.is_replayed_by(&transaction) {
+                tracing::debug!("Transaction replayed: '{}' ({transaction_status:?}).", transaction.tx);
+                return Ok(());
+            }
+
+            return Err(Error::TransactionAlreadyExists(transaction.tx));
+        }
+
+        let transaction_status = match transaction.r#type {
+            t @ TransactionType::Deposit => {
+                let amount = Self::transaction_amount(config, &transaction)?;
+
+                account_status.available = account_status
+                    .available
+                    .checked_add(amount.into())
+                    .ok_or(Error::TooManyFunds(transaction.tx, transaction.client))?;
+
+                TransactionStatus(t, transaction.client, amount)
+            }
+            t @ TransactionType::Withdrawal => {
+                let amount = Self::transaction_amount(config, &transaction)?;
+
+                let allowance = account_status
+                    .available
+                    .checked_add(config.overdraft_limit.into())
+                    .unwrap_or(Balance::MAX);
+
+                if allowance < Balance::from(amount) {
+                    return Err(Error::NotEnoughFunds(transaction.tx, transaction.client));
+                }
+
+                account_status.available -= amount;
+
+                TransactionStatus(t, transaction.client, amount)
+            }
+            t => return Err(Error::OperationNotSupported(transaction.tx, None, t)),
+        };
+
+        let capacity = config.transaction_capacity;
+        Self::rollout_transactions(
+            transactions,
+            capacity.rollout_threshold,
+            capacity.max,
+            |tx, TransactionStatus(_, client, ..)| {
+                if let Some(DroppedTransactionHook(hook)) = &config.on_transaction_dropped {
+                    hook(tx, *client);
+                }
+            },
+        );
+
+        transactions.insert(transaction.tx, transaction_status);
+
+        Ok(())
+    }
+
+    /// Get the amount of a new transaction, checking it against configured limits.
+    fn transaction_amount(config: &Config, transaction: &Transaction) -> Result<Amount, Error> {
+        let amount = transaction.amount.ok_or(Error::MissingAmount(transaction.tx))?;
+
+        match config.max_transaction_amount {
+            Some(limit) if amount > limit => Err(Error::AmountExceedsLimit {
+                tx: transaction.tx,
+                amount,
+                limit,
+            }),
+            _ => Ok(amount),
+        }
+    }
+
+    /// Manage a transaction dispute.
+    ///
+    /// Returns whether the disputed transaction moved to another state, ie. `false` should a transition be leniently skipped.
+    fn dispute_transaction<H: BuildHasher>(
+        config: &Config,
+        transactions: &mut Transactions<H>,
+        transaction: &Transaction,
+        account_status: &mut AccountStatus,
+    ) -> Result<bool, Error> {
+        let (transaction_id, client, transaction_type) = (transaction.tx, transaction.client, transaction.r#type);
+        let transaction_status = transactions
+            .get_mut(&transaction_id)
+            .ok_or(Error::TransactionNotFound(transaction_id))?;
+
+        if transaction_status.1 != client {
+            return Err(Error::ClientMismatch(transaction_id, transaction_status.1, client));
+        }
+
+        let (t, amount) = transaction_status.as_mut();
+
+        // an amount-less transaction cannot be disputed, eg. as restored from an edited snapshot, rather than holding a zero amount
+        if transaction_type == TransactionType::Dispute && !amount.is_positive() {
+            return Err(Error::NotDisputable(transaction_id));
+        }
+
+        // locked accounts are only ever reached as allowed by `LockPolicy::AllowDisputes`, see `Self::check_locked()`
+        let funds = match transaction_type {
+            TransactionType::Dispute if matches!(t, TransactionType::Deposit) => account_status.force_hold(amount),
+            TransactionType::Resolve if matches!(t, TransactionType::Dispute) => account_status.force_release(amount),
+            TransactionType::Chargeback if matches!(t, TransactionType::Dispute) => account_status.force_lock(amount),
+            TransactionType::Dispute if config.lenient_transitions && matches!(t, TransactionType::Dispute) => {
+                tracing::warn!("Transaction skipped: '{transaction_id}' already under dispute ({transaction_type:?}).");
+                return Ok(false);
+            }
+            TransactionType::Resolve | TransactionType::Chargeback
+                if config.lenient_transitions && !matches!(t, TransactionType::Dispute) =>
+            {
+                tracing::warn!("Transaction skipped: '{transaction_id}' not under dispute ({transaction_type:?}).");
+                return Ok(false);
+            }
+            _ => return Err(Error::OperationNotSupported(transaction_id, Some(*t), transaction_type)),
+        };
+        funds.map_err(|_| Error::TooManyFunds(transaction_id, client))?;
+
+        *t = transaction_type;
+
+        Ok(true)
+    }
+
+    /// Make room for incoming transactions, rolling out old transactions.
+    ///
+    /// It is guaranteed that room has been made for at least one future transaction wrt. expected `max_capacity`,
+    /// `on_dropped` being called for each transaction dropped to do so.
+    ///
+    /// # Panics
+    /// This function will panic when called with a `max_capacity` equal to `0`.
+    fn rollout_transactions(
+        transactions: &mut Transactions<impl BuildHasher>,
+        rollout_threshold: usize,
+        max_capacity: usize,
+        mut on_dropped: impl FnMut(TransactionID, &TransactionStatus),
+    ) {
+        assert!(max_capacity > 0);
+
+        if transactions.len() >= rollout_threshold {
+            // ideal case: roll out all ended disputes
+            transactions
+                .retain(|_, TransactionStatus(status, ..)| !matches!(status, TransactionType::Resolve | TransactionType::Chargeback));
+        }
+        while transactions.len() >= max_capacity {
+            // worst case: got no ended dispute, make room for only one entry, presuming arbitrarily the min. transaction ID could be old enough
+            let tx = *transactions.keys().min().unwrap();
+            let transaction_status = transactions.remove(&tx).unwrap();
+
+            tracing::warn!("Transaction dropped: '{tx}' ({transaction_status:?}).");
+            on_dropped(tx, &transaction_status);
+        }
+    }
+}
+
+impl<S: BuildHasher> TransactionHandler for Processor<S> {
+    type Record = Account;
+
+    #[inline]
+    async fn handle(&mut self, transaction: Transaction) -> Result<(), Error> {
+        self.process_transaction(transaction)
+    }
+
+    /// Turn this processor into its accounts, sorted by client ID for a stable output whatever the hashing of accounts.
+    ///
+    /// Should a client be closed then reopened, its closed account comes first.
+    fn into_records(self) -> impl Iterator<Item = Self::Record> {
+        let suppress_empty = self.config.suppress_empty;
+
+        let mut accounts: Vec<_> = self
+            .closed_accounts
+            .into_iter()
+            .chain(self.accounts.into_iter().map(Into::into))
+            .filter(|account| !suppress_empty || !account.status.is_empty())
+            .collect();
+        accounts.sort_by_key(|account| account.client); // stable, closed accounts first
+
+        accounts.into_iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use assert_matches::assert_matches;
+
+    #[test]
+    #[ignore = "not an actual test"]
+    fn test_size_of() {
+        fn print_size_of<T>() {
+            println!("{}: {}", std::any::type_name::<T>(), size_of::<T>());
+        }
+
+        print_size_of::<Error>();
+        print_size_of::<AccountStatus>();
+        print_size_of::<TransactionStatus>();
+        print_size_of::<TransactionType>();
+
+        println!(
+            "Default reserved min. size: {} bytes",
+            DEFAULT_TRANSACTION_CAPACITY * (size_of::<TransactionID>() + size_of::<TransactionStatus>())
+        );
+
+        println!(
+            "Max. size: {} bytes",
+            MAX_TRANSACTION_CAPACITY * (size_of::<TransactionID>() + size_of::<TransactionStatus>())
+        );
+    }
+
+    const DEPOSIT: Amount = Amount::raw(50000);
+    const WITHDRAWAL: Amount = Amount::raw(20000);
+    const DISPUTED: Amount = Amount::raw(10000);
+    const AVAILABLE: Amount = Amount::raw(DEPOSIT.into_raw() - WITHDRAWAL.into_raw() + DISPUTED.into_raw());
+
+    #[test]
+    fn test_error_codes() {
+        let errors = [
+            (Error::MissingAmount(1), "missing-amount"),
+            (Error::ZeroAmount(1), "zero-amount"),
+            (Error::UnexpectedAmount(1), "unexpected-amount"),
+            (Error::TransactionAlreadyExists(1), "transaction-already-exists"),
+            (Error::TransactionNotFound(1), "transaction-not-found"),
+            (
+                Error::OperationNotSupported(1, None, TransactionType::Resolve),
+                "operation-not-supported",
+            ),
+            (Error::NotDisputable(1), "not-disputable"),
+            (Error::TooManyFunds(1, 2), "too-many-funds"),
+            (Error::NotEnoughFunds(1, 2), "not-enough-funds"),
+            (Error::AccountLocked(1, 2), "account-locked"),
+            (Error::SystemFrozen(1, 2), "system-frozen"),
+            (Error::ClientMismatch(1, 3, 2), "client-mismatch"),
+            (Error::AccountConflict(2), "account-conflict"),
+            (Error::DuplicateClient(2), "duplicate-client"),
+            (Error::MergeOverflow(2), "merge-overflow"),
+            (
+                Error::AmountExceedsLimit {
+                    tx: 1,
+                    amount: DEPOSIT,
+                    limit: DISPUTED,
+                },
+                "amount-exceeds-limit",
+            ),
+        ];
+
+        for (err, code) in &errors {
+            assert_eq!(err.code(), *code);
+            assert_eq!(
+                err.tx(),
+                (!matches!(err, Error::AccountConflict(_) | Error::DuplicateClient(_) | Error::MergeOverflow(_))).then_some(1)
+            );
+        }
+        assert_eq!(Error::TransactionNotFound(1).client(), None);
+        assert_eq!(Error::ClientMismatch(1, 3, 2).client(), Some(2));
+        assert_eq!(Error::AccountConflict(2).client(), Some(2));
+        assert_eq!(Error::DuplicateClient(2).client(), Some(2));
+        assert_eq!(Error::MergeOverflow(2).client(), Some(2));
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn test_error_serialize() {
+        assert_eq!(
+            serde_json::to_string(&Error::NotEnoughFunds(1, 2)).unwrap(),
+            r#"{"code":"not-enough-funds","tx":1,"client":2,"message":"not enough funds to operate transaction '1' for client '2'"}"#
+        );
+        assert_eq!(
+            serde_json::to_string(&Error::AccountConflict(2)).unwrap(),
+            r#"{"code":"account-conflict","tx":null,"client":2,"message":"conflicting accounts for client '2' in merged processors"}"#
+        );
+    }
+
+    #[test]
+    fn test_rollout_transactions() {
+        let mut transactions: Transactions = Transactions::from_iter([
+            (1, TransactionStatus(TransactionType::Deposit, 0, Amount::MIN)),
+            (2, TransactionStatus(TransactionType::Withdrawal, 0, Amount::MIN)),
+            (3, TransactionStatus(TransactionType::Dispute, 0, Amount::MIN)),
+            (4, TransactionStatus(TransactionType::Resolve, 0, Amount::MIN)),
+            (5, TransactionStatus(TransactionType::Chargeback, 0, Amount::MIN)),
+        ]);
+
+        Processor::rollout_transactions(&mut transactions, 6, 6, |_, _| ());
+        assert!(transactions.len() == 5);
+
+        Processor::rollout_transactions(&mut transactions, 5, 6, |_, _| ());
+        assert!(transactions.len() == 3 && [1, 2, 3].iter().all(|id| transactions.contains_key(id)));
+
+        Processor::rollout_transactions(&mut transactions, 0, 6, |_, _| ());
+        assert!(transactions.len() == 3);
+
+        let mut dropped = vec![];
+        Processor::rollout_transactions(&mut transactions, 0, 3, |tx, _| dropped.push(tx));
+        assert!(transactions.len() == 2 && !transactions.contains_key(&1));
+        assert_eq!(dropped, [1]);
+
+        Processor::rollout_transactions(&mut transactions, 0, 1, |tx, _| dropped.push(tx));
+        assert!(transactions.is_empty());
+        assert_eq!(dropped, [1, 2, 3]);
+    }
+
+    #[test]
+    fn test_rollout_transactions_with_capacity() {
+        let deposits = |processor: &mut Processor, txs: std::ops::RangeInclusive<TransactionID>| {
+            for tx in txs {
+                processor
+                    .process_transaction(Transaction::deposit(tx, DEPOSIT).with_client(1))
+                    .unwrap();
+            }
+        };
+
+        let mut processor = Processor::default();
+        deposits(&mut processor, 1..=4);
+        assert_eq!(processor.transactions.len(), 4);
+
+        // Test: roll out oldest transactions earlier
+        let mut processor = Processor::default().with_capacity(1, 2, 3);
+        deposits(&mut processor, 1..=4);
+        assert_eq!(processor.transactions.len(), 3);
+        assert!(!processor.transactions.contains_key(&1));
+
+        // Test: roll out ended disputes earlier
+        let mut processor = Processor::default().with_capacity(1, 2, 3);
+        deposits(&mut processor, 1..=1);
+        processor.process_transaction(Transaction::dispute(1).with_client(1)).unwrap();
+        processor.process_transaction(Transaction::resolve(1).with_client(1)).unwrap();
+        deposits(&mut processor, 2..=3);
+        assert_eq!(processor.transactions.len(), 2);
+        assert!(!processor.transactions.contains_key(&1));
+    }
+
+    #[test]
+    fn test_on_transaction_dropped() {
+        let dropped = Arc::new(std::sync::Mutex::new(vec![]));
+
+        let mut processor = Processor::default().with_capacity(1, 1, 2).with_on_transaction_dropped({
+            let dropped = dropped.clone();
+            move |tx, client| dropped.lock().unwrap().push((tx, client))
+        });
+
+        for (tx, client) in [(1, 1), (2, 2), (3, 1), (4, 3)] {
+            processor
+                .process_transaction(Transaction::deposit(tx, DEPOSIT).with_client(client))
+                .unwrap();
+        }
+        assert_eq!(*dropped.lock().unwrap(), [(1, 1), (2, 2)]);
+
+        assert_matches!(
+            processor.process_transaction(Transaction::dispute(1).with_client(1)),
+            Err(Error::TransactionNotFound(1))
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "assertion failed: max > 0")]
+    fn test_with_capacity_zero() {
+        let _ = Processor::default().with_capacity(1, 1, 0);
+    }
+
+    #[test]
+    fn test_rollout_accounts() {
+        let mut processor = Processor::default().with_account_lru(2);
+
+        processor
+            .process_transaction(Transaction::deposit(1, DEPOSIT).with_client(1))
+            .unwrap();
+        processor
+            .process_transaction(Transaction::deposit(2, DEPOSIT).with_client(2))
+            .unwrap();
+        processor
+            .process_transaction(Transaction::withdrawal(3, WITHDRAWAL).with_client(1))
+            .unwrap();
+        assert_eq!(processor.accounts.len(), 2);
+
+        // Test: evict coldest account
+        processor
+            .process_transaction(Transaction::deposit(4, DEPOSIT).with_client(3))
+            .unwrap();
+        assert_eq!(processor.accounts.len(), 2);
+        assert_eq!(processor.accounts[&1], AccountStatus::from(DEPOSIT - WITHDRAWAL));
+        assert_eq!(processor.accounts[&3], AccountStatus::from(DEPOSIT));
+
+        // Test: touch account on failure as well
+        assert_matches!(
+            processor.process_transaction(Transaction::dispute(42).with_client(1)),
+            Err(Error::TransactionNotFound(42))
+        );
+        processor
+            .process_transaction(Transaction::deposit(5, DISPUTED).with_client(2))
+            .unwrap();
+        assert_eq!(processor.accounts.len(), 2);
+        assert_eq!(processor.accounts[&1], AccountStatus::from(DEPOSIT - WITHDRAWAL));
+        assert_eq!(processor.accounts[&2], AccountStatus::from(DISPUTED));
+
+        // Test: no account evicted nor created by a rejected transaction of an unknown client
+        assert_matches!(
+            processor.process_transaction(Transaction::withdrawal(6, WITHDRAWAL).with_client(4)),
+            Err(Error::NotEnoughFunds(6, 4))
+        );
+        assert_eq!(processor.accounts.len(), 2);
+        assert!(processor.accounts.contains_key(&1) && processor.accounts.contains_key(&2));
+
+        // Test: closed accounts no longer tracked
+        processor.process_transaction(Transaction::close_client(7).with_client(1)).unwrap();
+        assert_eq!(processor.recency.stamps.len(), 1);
+
+        // Test: evict beyond capacity when inserting or loading accounts
+        processor.insert_account(5, AccountStatus::from(DEPOSIT));
+        assert_eq!(processor.accounts.len(), 2);
+        processor.load_accounts([Account::from((6, AccountStatus::from(DEPOSIT)))]).unwrap();
+        assert_eq!(processor.accounts.len(), 2);
+        assert!(processor.accounts.contains_key(&5) && processor.accounts.contains_key(&6));
+
+        // Test: restored accounts tracked once evicting
+        let mut processor = Processor::restore(processor.snapshot()).with_account_lru(1);
+        processor
+            .process_transaction(Transaction::deposit(8, DEPOSIT).with_client(7))
+            .unwrap();
+        assert_eq!(processor.accounts.len(), 1);
+        assert!(processor.accounts.contains_key(&7));
+    }
+
+    #[test]
+    fn test_register_transaction() {
+        let config = Config::default();
+        let mut transactions: Transactions = Transactions::default();
+        let mut account_status = AccountStatus::default();
+
+        let transaction = Transaction::deposit(1, DEPOSIT);
+        Processor::register_transaction(&config, &mut transactions, transaction, &mut account_status).unwrap();
+        assert_eq!(account_status, AccountStatus::from(DEPOSIT));
+
+        let transaction = Transaction::withdrawal(2, WITHDRAWAL);
+        Processor::register_transaction(&config, &mut transactions, transaction, &mut account_status).unwrap();
+        assert_eq!(account_status, AccountStatus::from(DEPOSIT - WITHDRAWAL));
+
+        let ref_account_status = account_status.clone();
+
+        // Test: existing transaction
+        let transaction = Transaction::deposit(2, Default::default());
+        let err = Processor::register_transaction(&config, &mut transactions, transaction, &mut account_status).unwrap_err();
+        assert_matches!(err, Error::TransactionAlreadyExists(2));
+        assert_eq!(account_status, ref_account_status);
+
+        // Test: too many or not enough funds
+        let transaction = Transaction::deposit(3, Amount::MAX);
+        let err = Processor::register_transaction(&config, &mut transactions, transaction, &mut account_status).unwrap_err();
+        assert_matches!(err, Error::TooManyFunds(3, 0));
+        assert_eq!(account_status, ref_account_status);
+
+        let transaction = Transaction::withdrawal(3, DEPOSIT);
+        let err = Processor::register_transaction(&config, &mut transactions, transaction, &mut account_status).unwrap_err();
+        assert_matches!(err, Error::NotEnoughFunds(3, 0));
+        assert_eq!(account_status, ref_account_status);
+
+        // Test: register anything else than `Deposit` or `Withdrawal`
+        for transaction_type in [TransactionType::Dispute, TransactionType::Resolve, TransactionType::Chargeback] {
+            let transaction = Transaction::new(transaction_type, 3, Default::default());
+            let err = Processor::register_transaction(&config, &mut transactions, transaction, &mut account_status).unwrap_err();
+            assert_matches!(err, Error::OperationNotSupported(3, None, t) if t == transaction_type);
+            assert_eq!(account_status, ref_account_status);
+        }
+    }
+
+    #[test]
+    fn test_register_transaction_max_amount() {
+        let config = Config {
+            max_transaction_amount: Some(DEPOSIT),
+            ..Default::default()
+        };
+        let mut transactions: Transactions = Transactions::default();
+        let mut account_status = AccountStatus::default();
+
+        // Test: amount at the limit
+        let transaction = Transaction::deposit(1, DEPOSIT);
+        Processor::register_transaction(&config, &mut transactions, transaction, &mut account_status).unwrap();
+        assert_eq!(account_status, AccountStatus::from(DEPOSIT));
+
+        // Test: amount just under the limit
+        let transaction = Transaction::withdrawal(2, DEPOSIT - Amount::raw(1));
+        Processor::register_transaction(&config, &mut transactions, transaction, &mut account_status).unwrap();
+        assert_eq!(account_status, AccountStatus::from(Amount::raw(1)));
+
+        // Test: amount over the limit
+        for transaction in [
+            Transaction::deposit(3, DEPOSIT + Amount::raw(1)),
+            Transaction::withdrawal(3, DEPOSIT + Amount::raw(1)),
+        ] {
+            let err = Processor::register_transaction(&config, &mut transactions, transaction, &mut account_status).unwrap_err();
+            assert_matches!(err, Error::AmountExceedsLimit { tx: 3, amount, limit } if amount == DEPOSIT + Amount::raw(1) && limit == DEPOSIT);
+            assert_eq!(account_status, AccountStatus::from(Amount::raw(1)));
+            assert!(!transactions.contains_key(&3));
+        }
+    }
+
+    fn assert_dispute_not_supported(
+        transaction_id: TransactionID,
+        transaction_types: &[TransactionType],
+        transactions: &mut Transactions,
+        account_status: &mut AccountStatus,
+    ) {
+        let config = Config::default();
+        let not_supported = [TransactionType::Deposit, TransactionType::Withdrawal];
+
+        let ref_account_status = account_status.clone();
+
+        for transaction_type in not_supported.iter().chain(transaction_types) {
+            let err = Processor::dispute_transaction(
+                &config,
+                transactions,
+                &Transaction::new(*transaction_type, transaction_id, None),
+                account_status,
+            )
+            .unwrap_err();
+            assert_matches!(err, Error::OperationNotSupported(id, Some(_), t) if id == transaction_id && t == *transaction_type);
+            assert_eq!(*account_status, ref_account_status);
+        }
+    }
+
+    #[test]
+    fn test_dispute_transaction_failure() {
+        let config = Config::default();
+        let mut transactions: Transactions = Transactions::from_iter([
+            (1, TransactionStatus(TransactionType::Deposit, 0, DEPOSIT)),
+            (2, TransactionStatus(TransactionType::Withdrawal, 0, WITHDRAWAL)),
+            (3, TransactionStatus(TransactionType::Dispute, 0, DISPUTED)),
+        ]);
+        let mut account_status = AccountStatus::from(DEPOSIT - WITHDRAWAL);
+
+        // Test: dispute a `Deposit`
+        assert_dispute_not_supported(
+            1,
+            &[TransactionType::Resolve, TransactionType::Chargeback],
+            &mut transactions,
+            &mut account_status,
+        );
+
+        // Test: dispute a `Withdrawal`
+        assert_dispute_not_supported(
+            2,
+            &[TransactionType::Dispute, TransactionType::Resolve, TransactionType::Chargeback],
+            &mut transactions,
+            &mut account_status,
+        );
+
+        // Test: dispute a `Dispute`
+        assert_dispute_not_supported(3, &[TransactionType::Dispute], &mut transactions, &mut account_status);
+
+        // Test: not existing transaction
+        let err = Processor::dispute_transaction(
+            &config,
+            &mut transactions,
+            &Transaction::new(TransactionType::Deposit, 42, None),
+            &mut account_status,
+        )
+        .unwrap_err();
+        assert_matches!(err, Error::TransactionNotFound(42));
+        assert_eq!(account_status, AccountStatus::from(DEPOSIT - WITHDRAWAL));
+    }
+
+    #[test]
+    fn test_dispute_transaction_resolve() {
+        let config = Config::default();
+        let mut transactions: Transactions = Transactions::from_iter([
+            (1, TransactionStatus(TransactionType::Deposit, 0, DEPOSIT)),
+            (2, TransactionStatus(TransactionType::Deposit, 0, DISPUTED)),
+        ]);
+        let mut account_status = AccountStatus::from(DEPOSIT + DISPUTED);
+
+        Processor::dispute_transaction(&config, &mut transactions, &Transaction::dispute(2), &mut account_status).unwrap();
+        assert_eq!(account_status, AccountStatus::from(DEPOSIT).held(DISPUTED));
+
+        Processor::dispute_transaction(&config, &mut transactions, &Transaction::resolve(2), &mut account_status).unwrap();
+        assert_eq!(account_status, AccountStatus::from(DEPOSIT + DISPUTED));
+
+        assert_dispute_not_supported(
+            2,
+            &[TransactionType::Dispute, TransactionType::Chargeback],
+            &mut transactions,
+            &mut account_status,
+        );
+    }
+
+    #[test]
+    fn test_dispute_transaction_chargeback() {
+        let config = Config::default();
+        let mut transactions: Transactions = Transactions::from_iter([
+            (1, TransactionStatus(TransactionType::Deposit, 0, DEPOSIT)),
+            (2, TransactionStatus(TransactionType::Deposit, 0, DISPUTED)),
+        ]);
+        let mut account_status = AccountStatus::from(DEPOSIT + DISPUTED);
+
+        Processor::dispute_transaction(&config, &mut transactions, &Transaction::dispute(2), &mut account_status).unwrap();
+        assert_eq!(account_status, AccountStatus::from(DEPOSIT).held(DISPUTED));
+
+        Processor::dispute_transaction(&config, &mut transactions, &Transaction::chargeback(2), &mut account_status).unwrap();
+        assert_eq!(account_status, AccountStatus::from(DEPOSIT).locked());
+
+        assert_dispute_not_supported(
+            2,
+            &[TransactionType::Dispute, TransactionType::Resolve, TransactionType::Chargeback],
+            &mut transactions,
+            &mut account_status,
+        );
+    }
+
+    #[test]
+    fn test_dispute_transaction_withdrawn_funds() {
+        let config = Config::default();
+        let new_transactions =
+            || -> Transactions { Transactions::from_iter([(1, TransactionStatus(TransactionType::Deposit, 0, DISPUTED))]) };
+        let disputed = AccountStatus::from_parts(Balance::ZERO - DISPUTED, DISPUTED, false);
+
+        // Test: dispute then resolve a fully withdrawn `Deposit`
+        let (mut transactions, mut account_status) = (new_transactions(), AccountStatus::default());
+
+        Processor::dispute_transaction(&config, &mut transactions, &Transaction::dispute(1), &mut account_status).unwrap();
+        assert_eq!(account_status, disputed);
+        assert_eq!(account_status.total(), BalanceTotal::ZERO);
+
+        Processor::dispute_transaction(&config, &mut transactions, &Transaction::resolve(1), &mut account_status).unwrap();
+        assert_eq!(account_status, AccountStatus::default());
+
+        // Test: dispute then charge back a fully withdrawn `Deposit`
+        let (mut transactions, mut account_status) = (new_transactions(), AccountStatus::default());
+
+        Processor::dispute_transaction(&config, &mut transactions, &Transaction::dispute(1), &mut account_status).unwrap();
+        assert_eq!(account_status, disputed);
+
+        Processor::dispute_transaction(&config, &mut transactions, &Transaction::chargeback(1), &mut account_status).unwrap();
+        assert_eq!(
+            account_status,
+            AccountStatus::from_parts(Balance::ZERO - DISPUTED, Amount::MIN, true)
+        );
+        assert_eq!(account_status.total(), BalanceTotal::from(Balance::ZERO - DISPUTED));
+    }
+
+    #[test]
+    fn test_dispute_transaction_lenient() {
+        let strict = Config::default();
+        let lenient = Config {
+            lenient_transitions: true,
+            ..Default::default()
+        };
+
+        let mut transactions: Transactions = Transactions::from_iter([
+            (1, TransactionStatus(TransactionType::Deposit, 0, DEPOSIT)),
+            (2, TransactionStatus(TransactionType::Deposit, 0, DISPUTED)),
+        ]);
+        let mut account_status = AccountStatus::from(DEPOSIT + DISPUTED);
+
+        // Test: resolve without dispute
+        let err = Processor::dispute_transaction(&strict, &mut transactions, &Transaction::resolve(2), &mut account_status).unwrap_err();
+        assert_matches!(
+            err,
+            Error::OperationNotSupported(2, Some(TransactionType::Deposit), TransactionType::Resolve)
+        );
+
+        Processor::dispute_transaction(&lenient, &mut transactions, &Transaction::resolve(2), &mut account_status).unwrap();
+        Processor::dispute_transaction(&lenient, &mut transactions, &Transaction::chargeback(2), &mut account_status).unwrap();
+        assert_eq!(account_status, AccountStatus::from(DEPOSIT + DISPUTED));
+
+        // Test: dispute twice
+        Processor::dispute_transaction(&lenient, &mut transactions, &Transaction::dispute(2), &mut account_status).unwrap();
+        assert_eq!(account_status, AccountStatus::from(DEPOSIT).held(DISPUTED));
+
+        let err = Processor::dispute_transaction(&strict, &mut transactions, &Transaction::dispute(2), &mut account_status).unwrap_err();
+        assert_matches!(
+            err,
+            Error::OperationNotSupported(2, Some(TransactionType::Dispute), TransactionType::Dispute)
+        );
+
+        Processor::dispute_transaction(&lenient, &mut transactions, &Transaction::dispute(2), &mut account_status).unwrap();
+        assert_eq!(account_status, AccountStatus::from(DEPOSIT).held(DISPUTED));
+
+        // Test: still resolve disputed transaction
+        Processor::dispute_transaction(&lenient, &mut transactions, &Transaction::resolve(2), &mut account_status).unwrap();
+        assert_eq!(account_status, AccountStatus::from(DEPOSIT + DISPUTED));
+    }
+
+    #[test]
+    fn test_process_transaction_ok() {
+        let mut processor = Processor::default();
+
+        processor.process_transaction(Transaction::deposit(1, DEPOSIT)).unwrap();
+        assert_eq!(processor.accounts[&0], AccountStatus::from(DEPOSIT));
+
+        assert_matches!(
+            processor.process_transaction(Transaction::deposit(1, DEPOSIT)),
+            Err(Error::TransactionAlreadyExists(1))
+        );
+
+        assert_matches!(
+            processor.process_transaction(Transaction::dispute(42)),
+            Err(Error::TransactionNotFound(42))
+        );
+
+        processor.process_transaction(Transaction::deposit(2, DISPUTED)).unwrap();
+        assert_eq!(processor.accounts[&0], AccountStatus::from(DEPOSIT + DISPUTED));
+
+        processor.process_transaction(Transaction::withdrawal(5, WITHDRAWAL)).unwrap();
+        assert_eq!(processor.accounts[&0], AccountStatus::from(AVAILABLE));
+
+        processor.process_transaction(Transaction::dispute(2)).unwrap();
+        assert_eq!(processor.accounts[&0], AccountStatus::from(DEPOSIT - WITHDRAWAL).held(DISPUTED));
+
+        processor.process_transaction(Transaction::resolve(2)).unwrap();
+        assert_eq!(processor.accounts[&0], AccountStatus::from(AVAILABLE));
+
+        processor.process_transaction(Transaction::deposit(3, DISPUTED)).unwrap();
+        assert_eq!(processor.accounts[&0], AccountStatus::from(AVAILABLE + DISPUTED));
+
+        processor.process_transaction(Transaction::dispute(3)).unwrap();
+        assert_eq!(processor.accounts[&0], AccountStatus::from(AVAILABLE).held(DISPUTED));
+
+        processor.process_transaction(Transaction::chargeback(3)).unwrap();
+        assert_eq!(processor.accounts[&0], AccountStatus::from(AVAILABLE).locked());
+
+        for t in [
+            TransactionType::Deposit,
+            TransactionType::Withdrawal,
+            TransactionType::Dispute,
+            TransactionType::Resolve,
+            TransactionType::Chargeback,
+        ] {
+            let amount = matches!(t, TransactionType::Deposit | TransactionType::Withdrawal).then_some(DEPOSIT);
+            assert_matches!(
+                processor.process_transaction(Transaction::new(t, 4, amount)),
+                Err(Error::AccountLocked(4, 0))
+            );
+        }
+    }
+
+    #[test]
+    fn test_account_status() {
+        let mut processor = Processor::default();
+
+        processor
+            .process_transaction(Transaction::deposit(1, DEPOSIT).with_client(1))
+            .unwrap();
+        processor
+            .process_transaction(Transaction::deposit(2, DISPUTED).with_client(2))
+            .unwrap();
+
+        assert_eq!(processor.account_status(1), Some(&AccountStatus::from(DEPOSIT)));
+        assert_eq!(processor.account_status(2), Some(&AccountStatus::from(DISPUTED)));
+        assert_eq!(processor.account_status(3), None);
+
+        let mut accounts: Vec<_> = processor.accounts().map(|account| (account.client, account.status)).collect();
+        accounts.sort_unstable_by_key(|(client, _)| *client);
+        assert_eq!(accounts, [(1, AccountStatus::from(DEPOSIT)), (2, AccountStatus::from(DISPUTED))]);
+    }
+
+    #[test]
+    fn test_load_accounts() {
+        let account = |client, amount| Account::from((client, AccountStatus::from(amount)));
+
+        let mut processor = Processor::default();
+        processor
+            .process_transaction(Transaction::deposit(1, DEPOSIT).with_client(1))
+            .unwrap();
+
+        processor.load_accounts([account(2, DEPOSIT), account(3, DISPUTED)]).unwrap();
+        assert_eq!(processor.account_status(2), Some(&AccountStatus::from(DEPOSIT)));
+        assert_eq!(processor.account_status(3), Some(&AccountStatus::from(DISPUTED)));
+
+        // Test: duplicate client among loaded accounts
+        let err = processor
+            .load_accounts([account(4, DEPOSIT), account(5, DEPOSIT), account(4, DISPUTED)])
+            .unwrap_err();
+        assert_matches!(err, Error::DuplicateClient(4));
+        assert_eq!(processor.account_status(4), None);
+        assert_eq!(processor.account_status(5), None);
+
+        // Test: duplicate client along with a current account
+        let err = processor.load_accounts([account(4, DEPOSIT), account(1, DISPUTED)]).unwrap_err();
+        assert_matches!(err, Error::DuplicateClient(1));
+        assert_eq!(processor.account_status(1), Some(&AccountStatus::from(DEPOSIT)));
+        assert_eq!(processor.account_status(4), None);
+    }
+
+    #[test]
+    fn test_available_headroom() {
+        let mut processor = Processor::default();
+        assert_eq!(processor.available_headroom(1), Amount::MAX);
+
+        processor
+            .process_transaction(Transaction::deposit(1, Amount::MAX - DEPOSIT).with_client(1))
+            .unwrap();
+
+        // Test: headroom reported near `Amount::MAX`
+        let err = processor
+            .process_transaction(Transaction::deposit(2, DEPOSIT + DISPUTED).with_client(1))
+            .unwrap_err();
+        assert_matches!(err, Error::TooManyFunds(2, 1));
+        assert_eq!(processor.available_headroom(1), Amount::MAX - (Amount::MAX - DEPOSIT));
+
+        // Test: deposit split into acceptable chunks
+        processor
+            .process_transaction(Transaction::deposit(3, DEPOSIT).with_client(1))
+            .unwrap();
+        assert_eq!(processor.available_headroom(1), Amount::MIN);
+        assert_matches!(
+            processor.process_transaction(Transaction::deposit(4, DISPUTED).with_client(1)),
+            Err(Error::TooManyFunds(4, 1))
+        );
+
+        // Test: negative available funds
+        processor.insert_account(2, AccountStatus::from_parts(Balance::ZERO - DISPUTED, DISPUTED, false));
+        assert_eq!(processor.available_headroom(2), Amount::MAX);
+    }
+
+    #[test]
+    fn test_preview() {
+        let mut processor = Processor::default();
+
+        processor
+            .process_transaction(Transaction::deposit(1, DEPOSIT).with_client(1))
+            .unwrap();
+
+        // Test: preview a deposit
+        assert_eq!(
+            processor.preview(&Transaction::deposit(2, DISPUTED).with_client(1)).unwrap(),
+            AccountStatus::from(DEPOSIT + DISPUTED)
+        );
+        assert_eq!(
+            processor.preview(&Transaction::deposit(2, DISPUTED).with_client(2)).unwrap(),
+            AccountStatus::from(DISPUTED)
+        );
+
+        // Test: preview a withdrawal overdrawing funds
+        assert_matches!(
+            processor.preview(&Transaction::withdrawal(2, DEPOSIT + DISPUTED).with_client(1)),
+            Err(Error::NotEnoughFunds(2, 1))
+        );
+
+        // Test: preview a dispute
+        assert_eq!(
+            processor.preview(&Transaction::dispute(1).with_client(1)).unwrap(),
+            AccountStatus::from(Amount::MIN).held(DEPOSIT)
+        );
+        assert_matches!(
+            processor.preview(&Transaction::dispute(2).with_client(1)),
+            Err(Error::TransactionNotFound(2))
+        );
+
+        // Test: nothing applied
+        assert_eq!(processor.accounts.len(), 1);
+        assert_eq!(processor.accounts[&1], AccountStatus::from(DEPOSIT));
+        assert_eq!(processor.transactions.len(), 1);
+        assert_matches!(processor.transactions[&1], TransactionStatus(TransactionType::Deposit, ..));
+    }
+
+    #[test]
+    fn test_locked_accounts() {
+        let mut processor = Processor::default();
+
+        for client in 1..=4 {
+            let tx = TransactionID::from(client);
+            processor
+                .process_transaction(Transaction::deposit(tx, DEPOSIT).with_client(client))
+                .unwrap();
+        }
+        assert_eq!(processor.locked_accounts().count(), 0);
+
+        for client in [2, 4] {
+            let tx = TransactionID::from(client);
+            processor.process_transaction(Transaction::dispute(tx).with_client(client)).unwrap();
+            processor
+                .process_transaction(Transaction::chargeback(tx).with_client(client))
+                .unwrap();
+        }
+
+        let mut locked: Vec<_> = processor.locked_accounts().map(|account| account.client).collect();
+        locked.sort_unstable();
+        assert_eq!(locked, [2, 4]);
+    }
+
+    #[test]
+    fn test_insert_account() {
+        let mut processor = Processor::default();
+
+        processor.insert_account(1, AccountStatus::from_parts(AVAILABLE, DISPUTED, false));
+        processor.insert_account(2, AccountStatus::from_parts(Amount::MAX - DEPOSIT, Amount::MIN, false));
+        processor.insert_account(3, AccountStatus::from_parts(DEPOSIT, Amount::MIN, true));
+
+        processor
+            .process_transaction(Transaction::withdrawal(1, AVAILABLE).with_client(1))
+            .unwrap();
+        assert_eq!(processor.accounts[&1], AccountStatus::from(Amount::MIN).held(DISPUTED));
+
+        assert_matches!(
+            processor.process_transaction(Transaction::deposit(2, DEPOSIT + DEPOSIT).with_client(2)),
+            Err(Error::TooManyFunds(2, 2))
+        );
+        processor
+            .process_transaction(Transaction::deposit(2, DEPOSIT).with_client(2))
+            .unwrap();
+        assert_eq!(processor.accounts[&2], AccountStatus::from(Amount::MAX));
+
+        assert_matches!(
+            processor.process_transaction(Transaction::deposit(3, DEPOSIT).with_client(3)),
+            Err(Error::AccountLocked(3, 3))
+        );
+    }
+
+    #[test]
+    fn test_audit_snapshot() {
+        let mut processor = Processor::default();
+
+        assert!(processor.audit_snapshot().disputes.is_empty());
+        assert!(processor.audit_snapshot().locked_accounts.is_empty());
+
+        let transactions = [
+            Transaction::deposit(1, DEPOSIT).with_client(1),
+            Transaction::deposit(2, DISPUTED).with_client(1),
+            Transaction::deposit(3, DEPOSIT).with_client(2),
+            Transaction::deposit(4, DISPUTED).with_client(2),
+            Transaction::deposit(5, DISPUTED).with_client(3),
+            Transaction::withdrawal(6, WITHDRAWAL).with_client(1),
+            Transaction::dispute(4).with_client(2),
+            Transaction::dispute(2).with_client(1),
+            Transaction::dispute(3).with_client(2),
+            Transaction::resolve(3).with_client(2),
+            Transaction::dispute(5).with_client(3),
+            Transaction::chargeback(5).with_client(3),
+        ];
+        for transaction in transactions {
+            processor.process_transaction(transaction).unwrap();
+        }
+
+        let snapshot = processor.audit_snapshot();
+        assert_eq!(
+            snapshot.disputes,
+            [
+                DisputeRecord {
+                    tx: 2,
+                    client: 1,
+                    amount: DISPUTED,
+                    state: TransactionType::Dispute
+                },
+                DisputeRecord {
+                    tx: 4,
+                    client: 2,
+                    amount: DISPUTED,
+                    state: TransactionType::Dispute
+                },
+            ]
+        );
+        assert_eq!(snapshot.locked_accounts.len(), 1);
+        assert_eq!(
+            (snapshot.locked_accounts[0].client, &snapshot.locked_accounts[0].status),
+            (3, &AccountStatus::default().locked())
+        );
+    }
+
+    #[test]
+    fn test_reconcile_held() {
+        let mut processor = Processor::default();
+
+        processor
+            .process_transaction(Transaction::deposit(1, DEPOSIT).with_client(1))
+            .unwrap();
+        processor
+            .process_transaction(Transaction::deposit(2, DISPUTED).with_client(1))
+            .unwrap();
+        processor.process_transaction(Transaction::dispute(2).with_client(1)).unwrap();
+
+        // orphaned held funds, ie. with no corresponding open dispute
+        processor.insert_account(2, AccountStatus::from_parts(AVAILABLE, DISPUTED, false));
+        processor.insert_account(3, AccountStatus::from_parts(Amount::MIN, DISPUTED, true));
+
+        // partially orphaned held funds
+        processor.accounts.get_mut(&1).unwrap().held += DEPOSIT;
+
+        assert_eq!(
+            processor.reconcile_held(),
+            [(1, Balance::from(DEPOSIT)), (2, Balance::from(DISPUTED))]
+        );
+        assert_eq!(processor.accounts[&1], AccountStatus::from(DEPOSIT + DEPOSIT).held(DISPUTED));
+        assert_eq!(processor.accounts[&2], AccountStatus::from(AVAILABLE + DISPUTED));
+        assert_eq!(processor.accounts[&3], AccountStatus::from_parts(Amount::MIN, DISPUTED, true));
+
+        // Test: nothing left to reconcile
+        assert!(processor.reconcile_held().is_empty());
+
+        // Test: held funds not released should available funds overflow
+        processor.insert_account(4, AccountStatus::from(Amount::MAX).held(DISPUTED));
+        assert!(processor.reconcile_held().is_empty());
+        assert_eq!(processor.accounts[&4], AccountStatus::from(Amount::MAX).held(DISPUTED));
+    }
+
+    #[test]
+    fn test_expire_disputes() {
+        let mut processor = Processor::default().with_outcome_log(true);
+
+        for (tx, timestamp) in [(1, 10), (2, 20), (3, 30)] {
+            processor
+                .process_transaction(Transaction::deposit(tx, DISPUTED).with_client(1))
+                .unwrap();
+            processor
+                .process_transaction(Transaction::dispute(tx).with_client(1).with_timestamp(timestamp))
+                .unwrap();
+        }
+        // untimestamped dispute
+        processor
+            .process_transaction(Transaction::deposit(4, DISPUTED).with_client(1))
+            .unwrap();
+        processor.process_transaction(Transaction::dispute(4).with_client(1)).unwrap();
+        // locked account
+        processor
+            .process_transaction(Transaction::deposit(5, DISPUTED).with_client(2))
+            .unwrap();
+        processor
+            .process_transaction(Transaction::dispute(5).with_client(2).with_timestamp(10))
+            .unwrap();
+        processor.accounts.get_mut(&2).unwrap().locked = true;
+
+        // Test: nothing older than the first dispute
+        assert!(processor.expire_disputes(10).is_empty());
+
+        // Test: disputes strictly older than the cutoff
+        assert_eq!(processor.expire_disputes(30), [(1, 1), (1, 2)]);
+        assert_eq!(
+            processor.accounts[&1],
+            AccountStatus::from(DISPUTED + DISPUTED).held(DISPUTED + DISPUTED)
+        );
+        assert_matches!(processor.transactions[&1], TransactionStatus(TransactionType::Resolve, _, _));
+        assert_eq!(processor.accounts[&2], AccountStatus::from_parts(Amount::MIN, DISPUTED, true));
+
+        // Test: expired disputes counted as resolves
+        assert_eq!(processor.metrics().resolves, 2);
+        assert_eq!(processor.outcomes.0[10..], [(1, Ok(())), (2, Ok(()))]);
+
+        // Test: dispute timestamps persisted along with snapshots
+        assert_eq!(Processor::restore(processor.snapshot()).expire_disputes(Timestamp::MAX), [(1, 3)]);
+
+        // Test: expired disputes are resolved for good
+        assert_matches!(
+            processor.process_transaction(Transaction::dispute(1).with_client(1).with_timestamp(40)),
+            Err(Error::OperationNotSupported(
+                1,
+                Some(TransactionType::Resolve),
+                TransactionType::Dispute
+            ))
+        );
+        assert_eq!(processor.expire_disputes(Timestamp::MAX), [(1, 3)]);
+        assert_eq!(processor.accounts[&1], AccountStatus::from(DISPUTED * 3).held(DISPUTED));
+    }
+
+    #[test]
+    fn test_settle() {
+        let mut processor = Processor::default();
+
+        for tx in 1..=3 {
+            processor
+                .process_transaction(Transaction::deposit(tx, DISPUTED).with_client(1))
+                .unwrap();
+            processor.process_transaction(Transaction::dispute(tx).with_client(1)).unwrap();
+        }
+        processor.process_transaction(Transaction::resolve(2).with_client(1)).unwrap();
+        processor
+            .process_transaction(Transaction::deposit(4, DEPOSIT).with_client(2))
+            .unwrap();
+
+        // Test: nothing to settle without drift
+        assert!(processor.settle().is_empty());
+        assert_eq!(processor.accounts[&1], AccountStatus::from(DISPUTED).held(DISPUTED + DISPUTED));
+
+        // stale held funds
+        processor.accounts.get_mut(&1).unwrap().held += DEPOSIT;
+        processor.sync_ledger();
+        // missing held funds
+        processor.process_transaction(Transaction::dispute(4).with_client(2)).unwrap();
+        processor.accounts.get_mut(&2).unwrap().held -= DISPUTED;
+        // locked account
+        processor.insert_account(3, AccountStatus::from_parts(Amount::MIN, DISPUTED, true));
+
+        let totals: Vec<_> = (1..=3).map(|client| processor.accounts[&client].total()).collect();
+
+        assert_eq!(processor.settle(), [(1, Balance::from(DEPOSIT)), (2, Balance::ZERO - DISPUTED)]);
+        assert_eq!(
+            processor.accounts[&1],
+            AccountStatus::from(DISPUTED + DEPOSIT).held(DISPUTED + DISPUTED)
+        );
+        assert_eq!(
+            processor.accounts[&2],
+            AccountStatus::from_parts(Balance::ZERO - DISPUTED, DEPOSIT, false)
+        );
+        assert_eq!(processor.accounts[&3], AccountStatus::from_parts(Amount::MIN, DISPUTED, true));
+        assert_eq!(
+            (1..=3).map(|client| processor.accounts[&client].total()).collect::<Vec<_>>(),
+            totals
+        );
+
+        // Test: nothing left to settle
+        assert!(processor.settle().is_empty());
+
+        // Test: held funds not settled should available funds overflow
+        processor.insert_account(4, AccountStatus::from(Amount::MAX).held(DEPOSIT));
+        assert!(processor.settle().is_empty());
+        assert_eq!(processor.accounts[&4], AccountStatus::from(Amount::MAX).held(DEPOSIT));
+    }
+
+    #[test]
+    fn test_merge() {
+        fn shards() -> (Processor, Processor) {
+            let mut shard1 = Processor::default();
+            shard1.process_transaction(Transaction::deposit(1, DEPOSIT).with_client(1)).unwrap();
+            shard1.process_transaction(Transaction::deposit(2, DEPOSIT).with_client(2)).unwrap();
+
+            let mut shard2 = Processor::default();
+            shard2
+                .process_transaction(Transaction::deposit(3, DISPUTED).with_client(2))
+                .unwrap();
+            shard2.process_transaction(Transaction::dispute(3).with_client(2)).unwrap();
+            shard2.process_transaction(Transaction::chargeback(3).with_client(2)).unwrap();
+            shard2
+                .process_transaction(Transaction::deposit(4, DISPUTED).with_client(3))
+                .unwrap();
+
+            (shard1, shard2)
+        }
+
+        // Test: sum balances, locked if any
+        let (mut processor, shard) = shards();
+        processor.merge(shard, MergePolicy::default()).unwrap();
+        assert_eq!(processor.accounts.len(), 3);
+        assert_eq!(processor.accounts[&1], AccountStatus::from(DEPOSIT));
+        assert_eq!(processor.accounts[&2], AccountStatus::from(DEPOSIT).locked());
+        assert_eq!(processor.accounts[&3], AccountStatus::from(DISPUTED));
+        assert_eq!(processor.transactions.len(), 4);
+
+        // Test: keep locked account
+        let (mut processor, shard) = shards();
+        processor.merge(shard, MergePolicy::LockedWins).unwrap();
+        assert_eq!(processor.accounts.len(), 3);
+        assert_eq!(processor.accounts[&2], AccountStatus::default().locked());
+
+        let (shard, mut processor) = shards();
+        processor.merge(shard, MergePolicy::LockedWins).unwrap();
+        assert_eq!(processor.accounts[&2], AccountStatus::default().locked());
+
+        // Test: reject conflicting accounts, the shard being given back to be merged with another policy
+        let (mut processor, shard) = shards();
+        let (err, shard) = processor.merge(shard, MergePolicy::Reject).unwrap_err();
+        assert_matches!(err, Error::AccountConflict(2));
+        assert_eq!(processor.accounts.len(), 2);
+        assert_eq!(processor.accounts[&2], AccountStatus::from(DEPOSIT));
+        assert_eq!((shard.accounts.len(), shard.transactions.len()), (2, 2));
+
+        processor.merge(*shard, MergePolicy::LockedWins).unwrap();
+        assert_eq!(processor.accounts.len(), 3);
+        assert_eq!(processor.accounts[&2], AccountStatus::default().locked());
+
+        // Test: reject summed balances that cannot be represented, nothing being merged
+        let (mut processor, mut shard) = shards();
+        shard.insert_account(1, AccountStatus::from(Amount::MAX));
+        let (err, shard) = processor.merge(shard, MergePolicy::default()).unwrap_err();
+        assert_matches!(err, Error::MergeOverflow(1));
+        assert_eq!(processor.accounts.len(), 2);
+        assert_eq!(processor.accounts[&2], AccountStatus::from(DEPOSIT));
+        assert_eq!(processor.transactions.len(), 2);
+        assert_eq!(shard.accounts[&1], AccountStatus::from(Amount::MAX));
+        assert_eq!(shard.transactions.len(), 2);
+
+        // Test: merged accounts evicted beyond capacity, as the most recently used ones
+        let (processor, shard) = shards();
+        let mut processor = processor.with_account_lru(2);
+        processor.merge(shard, MergePolicy::default()).unwrap();
+        assert_eq!(processor.accounts.len(), 2);
+        assert!(!processor.accounts.contains_key(&1));
+    }
+
+    #[test]
+    fn test_freeze_all() {
+        let mut processor = Processor::default();
+
+        processor.process_transaction(Transaction::deposit(1, DEPOSIT)).unwrap();
+
+        processor.freeze_all();
+        assert!(processor.is_frozen());
+
+        // Test: block deposits/withdrawals
+        assert_matches!(
+            processor.process_transaction(Transaction::deposit(2, DEPOSIT)),
+            Err(Error::SystemFrozen(2, 0))
+        );
+        assert_matches!(
+            processor.process_transaction(Transaction::withdrawal(3, WITHDRAWAL).with_client(1)),
+            Err(Error::SystemFrozen(3, 1))
+        );
+        assert_eq!(processor.accounts.len(), 1);
+        assert_eq!(processor.accounts[&0], AccountStatus::from(DEPOSIT));
+
+        // Test: still process disputes
+        processor.process_transaction(Transaction::dispute(1)).unwrap();
+        processor.process_transaction(Transaction::resolve(1)).unwrap();
+        assert_eq!(processor.accounts[&0], AccountStatus::from(DEPOSIT));
+
+        // Test: resume deposits/withdrawals
+        processor.thaw_all();
+        assert!(!processor.is_frozen());
+
+        processor.process_transaction(Transaction::deposit(2, DEPOSIT)).unwrap();
+        processor.process_transaction(Transaction::withdrawal(3, WITHDRAWAL)).unwrap();
+        assert_eq!(processor.accounts[&0], AccountStatus::from(DEPOSIT + DEPOSIT - WITHDRAWAL));
+        assert!(processor.locked_accounts().next().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_process_incremental() {
+        let transactions = [
+            Transaction::deposit(1, DEPOSIT).with_client(1),
+            Transaction::deposit(2, DEPOSIT).with_client(2),
+            Transaction::withdrawal(3, WITHDRAWAL).with_client(1),
+            Transaction::close_client(4).with_client(1),
+            Transaction::close_client(5).with_client(3),
+            Transaction::deposit(6, DISPUTED).with_client(1),
+        ];
+
+        let accounts: Vec<_> = Processor::default()
+            .process_incremental(stream::iter(transactions).map(Ok))
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(accounts.len(), 3);
+
+        // Test: closed account emitted first
+        assert_eq!(
+            (accounts[0].client, &accounts[0].status),
+            (1, &AccountStatus::from(DEPOSIT - WITHDRAWAL))
+        );
+
+        // Test: remaining accounts emitted at the end, client `1` starting afresh
+        let mut remaining: Vec<_> = accounts[1..].iter().map(|account| (account.client, &account.status)).collect();
+        remaining.sort_unstable_by_key(|(client, _)| *client);
+        assert_eq!(remaining, [(1, &AccountStatus::from(DISPUTED)), (2, &AccountStatus::from(DEPOSIT))]);
+    }
+
+    #[tokio::test]
+    async fn test_process_snapshots() {
+        let transactions = [
+            Transaction::deposit(1, DEPOSIT).with_client(1),
+            Transaction::deposit(2, DEPOSIT).with_client(2),
+            Transaction::withdrawal(3, WITHDRAWAL).with_client(1),
+            Transaction::withdrawal(4, WITHDRAWAL).with_client(1),
+            Transaction::deposit(5, DISPUTED).with_client(2),
+        ];
+
+        let snapshots: Vec<_> = Processor::default()
+            .process_snapshots(stream::iter(transactions).map(Ok), 2)
+            .map_ok(|accounts| {
+                accounts
+                    .into_iter()
+                    .map(|account| (account.client, account.status))
+                    .collect::<Vec<_>>()
+            })
+            .try_collect()
+            .await
+            .unwrap();
+
+        assert_eq!(
+            snapshots,
+            [
+                vec![(1, AccountStatus::from(DEPOSIT)), (2, AccountStatus::from(DEPOSIT))],
+                vec![
+                    (1, AccountStatus::from(DEPOSIT - WITHDRAWAL - WITHDRAWAL)),
+                    (2, AccountStatus::from(DEPOSIT))
+                ],
+                vec![
+                    (1, AccountStatus::from(DEPOSIT - WITHDRAWAL - WITHDRAWAL)),
+                    (2, AccountStatus::from(DEPOSIT + DISPUTED))
+                ],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_process_transaction_close_client() {
+        let mut processor = Processor::default();
+
+        processor.process_transaction(Transaction::deposit(1, DEPOSIT)).unwrap();
+        processor.process_transaction(Transaction::dispute(1)).unwrap();
+        processor.process_transaction(Transaction::chargeback(1)).unwrap();
+
+        // Test: close a locked account
+        processor.process_transaction(Transaction::close_client(2)).unwrap();
+        assert!(processor.accounts.is_empty());
+        assert_eq!(processor.closed_accounts.len(), 1);
+        assert_eq!(processor.closed_accounts[0].status, AccountStatus::default().locked());
+
+        // Test: close an unknown account
+        processor.process_transaction(Transaction::close_client(3)).unwrap();
+        assert_eq!(processor.closed_accounts.len(), 1);
+
+        // Test: closed accounts still emitted
+        assert_eq!(processor.into_records().count(), 1);
+    }
+
+    #[test]
+    fn test_process_transaction_dispute_zero_available() {
+        let mut processor = Processor::default();
+        let five = Amount::from(5);
+
+        processor.process_transaction(Transaction::deposit(1, five)).unwrap();
+        processor.process_transaction(Transaction::withdrawal(2, five)).unwrap();
+        assert_eq!(processor.accounts[&0], AccountStatus::from(Amount::MIN));
+
+        // Test: dispute a deposit once available funds are exactly zero
+        processor.process_transaction(Transaction::dispute(1)).unwrap();
+        let status = &processor.accounts[&0];
+        assert_eq!(
+            (status.available, status.held, status.total()),
+            (Balance::ZERO - five, five.into(), BalanceTotal::ZERO)
+        );
+
+        // Test: no withdrawal until available funds are restored
+        processor.process_transaction(Transaction::deposit(3, Amount::from(2))).unwrap();
+        assert_matches!(
+            processor.process_transaction(Transaction::withdrawal(4, Amount::new(0, 1))),
+            Err(Error::NotEnoughFunds(4, 0))
+        );
+
+        processor.process_transaction(Transaction::resolve(1)).unwrap();
+        let status = &processor.accounts[&0];
+        assert_eq!(
+            (status.available, status.held, status.total()),
+            (Amount::from(2).into(), Balance::ZERO, Amount::from(2).into())
+        );
+
+        processor
+            .process_transaction(Transaction::withdrawal(4, Amount::new(0, 1)))
+            .unwrap();
+    }
+
+    #[test]
+    fn test_process_transaction_resolve_deposit() {
+        let mut processor = Processor::default();
+        let ten = Amount::from(10);
+        let funds = |processor: &Processor| {
+            let status = &processor.accounts[&0];
+            (status.available, status.held, status.total())
+        };
+
+        processor.process_transaction(Transaction::deposit(1, ten)).unwrap();
+        assert_eq!(funds(&processor), (ten.into(), Balance::ZERO, ten.into()));
+
+        // Test: held funds move back to available funds, total funds being unchanged throughout
+        processor.process_transaction(Transaction::dispute(1)).unwrap();
+        assert_eq!(funds(&processor), (Balance::ZERO, ten.into(), ten.into()));
+
+        processor.process_transaction(Transaction::resolve(1)).unwrap();
+        assert_eq!(funds(&processor), (ten.into(), Balance::ZERO, ten.into()));
+
+        // Test: a withdrawal is not disputable, so could never be resolved nor double-counted
+        processor.process_transaction(Transaction::withdrawal(2, Amount::from(4))).unwrap();
+        let expected = (Amount::from(6).into(), Balance::ZERO, Amount::from(6).into());
+        assert_eq!(funds(&processor), expected);
+
+        assert_matches!(
+            processor.process_transaction(Transaction::dispute(2)),
+            Err(Error::OperationNotSupported(
+                2,
+                Some(TransactionType::Withdrawal),
+                TransactionType::Dispute
+            ))
+        );
+        assert_matches!(
+            processor.process_transaction(Transaction::resolve(2)),
+            Err(Error::OperationNotSupported(
+                2,
+                Some(TransactionType::Withdrawal),
+                TransactionType::Resolve
+            ))
+        );
+        assert_eq!(funds(&processor), expected);
+    }
+
+    #[test]
+    fn test_process_transaction_negative_funds() {
+        let mut processor = Processor::default();
+
+        processor.process_transaction(Transaction::deposit(2, DISPUTED)).unwrap();
+        assert_eq!(processor.accounts[&0], AccountStatus::from(DISPUTED));
+
+        assert_matches!(
+            processor.process_transaction(Transaction::withdrawal(5, WITHDRAWAL)),
+            Err(Error::NotEnoughFunds(5, 0))
+        );
+
+        processor.process_transaction(Transaction::withdrawal(5, DISPUTED)).unwrap();
+        assert_eq!(processor.accounts[&0], AccountStatus::from(Amount::MIN));
+
+        // Test: dispute already withdrawn funds
+        processor.process_transaction(Transaction::dispute(2)).unwrap();
+        assert_eq!(
+            processor.accounts[&0],
+            AccountStatus::from_parts(Balance::ZERO - DISPUTED, DISPUTED, false)
+        );
+        assert_eq!(processor.accounts[&0].total(), BalanceTotal::ZERO);
+
+        assert_matches!(
+            processor.process_transaction(Transaction::withdrawal(6, WITHDRAWAL)),
+            Err(Error::NotEnoughFunds(6, 0))
+        );
+
+        // Test: charge back already withdrawn funds
+        processor.process_transaction(Transaction::chargeback(2)).unwrap();
+        assert_eq!(
+            processor.accounts[&0],
+            AccountStatus::from_parts(Balance::ZERO - DISPUTED, Amount::MIN, true)
+        );
+        assert_eq!(processor.accounts[&0].total(), BalanceTotal::from(Balance::ZERO - DISPUTED));
+    }
+
+    #[test]
+    fn test_metrics() {
+        let mut processor = Processor::default();
+
+        let transactions = [
+            Transaction::deposit(1, DEPOSIT),
+            Transaction::deposit(2, DISPUTED),
+            Transaction::deposit(2, DISPUTED),
+            Transaction::withdrawal(3, WITHDRAWAL),
+            Transaction::withdrawal(4, DEPOSIT),
+            Transaction::dispute(1),
+            Transaction::resolve(1),
+            Transaction::dispute(2),
+            Transaction::chargeback(2),
+            Transaction::deposit(5, DEPOSIT),
+            Transaction::dispute(6),
+        ];
+        for transaction in transactions {
+            let _ = processor.process_transaction(transaction);
+        }
+
+        let metrics = processor.metrics();
+        assert_eq!(
+            (
+                metrics.deposits,
+                metrics.withdrawals,
+                metrics.disputes,
+                metrics.resolves,
+                metrics.chargebacks
+            ),
+            (2, 1, 2, 1, 1)
+        );
+        assert_eq!(
+            metrics.errors,
+            HashMap::from([("transaction-already-exists", 1), ("not-enough-funds", 1), ("account-locked", 2)])
+        );
+
+        // Test: merged metrics
+        let mut other = Processor::default();
+        other.process_transaction(Transaction::deposit(7, DEPOSIT).with_client(1)).unwrap();
+        let _ = other.process_transaction(Transaction::dispute(8).with_client(1));
+
+        processor.merge(other, MergePolicy::Sum).unwrap();
+        assert_eq!(processor.metrics().deposits, 3);
+        assert_eq!(processor.metrics().errors["transaction-not-found"], 1);
+    }
+
+    #[test]
+    fn test_process_transaction_idempotent_replay() {
+        let transactions = || {
+            [
+                Transaction::deposit(1, DEPOSIT).with_client(1),
+                Transaction::withdrawal(2, WITHDRAWAL).with_client(1),
+                Transaction::deposit(3, DISPUTED).with_client(1),
+                Transaction::dispute(3).with_client(1),
+            ]
+        };
+
+        let mut processor = Processor::default().with_idempotent_replay(true);
+        for transaction in transactions() {
+            processor.process_transaction(transaction).unwrap();
+        }
+        let status = processor.accounts[&1].clone();
+
+        // Test: exact replays are skipped, even once disputed
+        for transaction in transactions().into_iter().take(3) {
+            processor.process_transaction(transaction).unwrap();
+        }
+        assert_eq!(processor.accounts[&1], status);
+
+        // Test: conflicting replays are rejected
+        for transaction in [
+            Transaction::deposit(1, DISPUTED).with_client(1),
+            Transaction::deposit(1, DEPOSIT).with_client(2),
+            Transaction::deposit(2, WITHDRAWAL).with_client(1),
+            Transaction::withdrawal(3, DISPUTED).with_client(1),
+        ] {
+            let tx = transaction.tx;
+            assert_matches!(processor.process_transaction(transaction), Err(Error::TransactionAlreadyExists(id)) if id == tx);
+        }
+        assert_eq!(processor.accounts[&1], status);
+
+        // Test: exact replays are rejected by default
+        let mut processor = Processor::default();
+        processor.process_transaction(Transaction::deposit(1, DEPOSIT)).unwrap();
+        assert_matches!(
+            processor.process_transaction(Transaction::deposit(1, DEPOSIT)),
+            Err(Error::TransactionAlreadyExists(1))
+        );
+    }
+
+    /// A feed of transactions for snapshot tests, splittable at any point.
+    fn snapshot_transactions() -> Vec<Transaction> {
+        vec![
+            Transaction::deposit(1, DEPOSIT).with_client(1),
+            Transaction::deposit(2, DEPOSIT).with_client(2),
+            Transaction::withdrawal(3, WITHDRAWAL).with_client(1),
+            Transaction::dispute(2).with_client(2),
+            Transaction::close_client(4).with_client(3),
+            Transaction::deposit(5, DISPUTED).with_client(1),
+            Transaction::dispute(1).with_client(1),
+            Transaction::chargeback(2).with_client(2),
+            Transaction::resolve(1).with_client(1),
+            Transaction::close_client(6).with_client(2),
+        ]
+    }
+
+    fn process_all(mut processor: Processor, transactions: Vec<Transaction>) -> Processor {
+        for transaction in transactions {
+            let _ = processor.process_transaction(transaction);
+        }
+        processor
+    }
 
-        Ok(())
+    fn sorted_accounts(processor: Processor) -> Vec<(ClientID, AccountStatus)> {
+        let mut accounts: Vec<_> = processor.into_records().map(|account| (account.client, account.status)).collect();
+        accounts.sort_unstable_by_key(|(client, _)| *client);
+        accounts
     }
 
-    /// Manage a transaction dispute.
-    fn dispute_transaction(
-        transactions: &mut Transactions,
-        transaction_id: TransactionID,
-        transaction_type: TransactionType,
-        account_status: &mut AccountStatus,
-    ) -> Result<(), Error> {
-        let (t, amount) = match transactions.get_mut(&transaction_id) {
-            Some(transaction_status) => transaction_status.as_mut(),
-            None => return Err(Error::TransactionNotFound(transaction_id)),
-        };
+    #[test]
+    fn test_snapshot_restore() {
+        let expected = sorted_accounts(process_all(Processor::default(), snapshot_transactions()));
+        assert_eq!(expected.len(), 2);
 
-        match transaction_type {
-            TransactionType::Dispute if matches!(t, TransactionType::Deposit) => account_status.hold(amount),
-            TransactionType::Resolve if matches!(t, TransactionType::Dispute) => account_status.release(amount),
-            TransactionType::Chargeback if matches!(t, TransactionType::Dispute) => account_status.lock(amount),
-            _ => return Err(Error::OperationNotSupported(transaction_id, Some(*t), transaction_type)),
+        for half in [0, 3, 5, 8, 10] {
+            let mut first = snapshot_transactions();
+            let second = first.split_off(half);
+
+            let processor = process_all(Processor::default(), first);
+            let restored = process_all(Processor::restore(processor.snapshot()), second);
+            assert_eq!(sorted_accounts(restored), expected);
         }
+    }
 
-        *t = transaction_type;
+    #[test]
+    fn test_restore_amountless() {
+        // as read from an edited or corrupted snapshot
+        let snapshot = ProcessorSnapshot {
+            accounts: Accounts::from_iter([(1, AccountStatus::from(DEPOSIT))]),
+            transactions: Transactions::from_iter([(1, TransactionStatus(TransactionType::Deposit, 1, Amount::MIN))]),
+            ..Default::default()
+        };
+        let mut processor = Processor::restore(snapshot);
 
-        Ok(())
+        let err = processor.process_transaction(Transaction::dispute(1).with_client(1)).unwrap_err();
+        assert_matches!(err, Error::NotDisputable(1));
+        assert_eq!(processor.accounts[&1], AccountStatus::from(DEPOSIT));
+        assert_matches!(processor.transactions[&1], TransactionStatus(TransactionType::Deposit, ..));
     }
 
-    /// Make room for incoming transactions, rolling out old transactions.
-    ///
-    /// It is guaranteed that room has been made for at least one future transaction wrt. expected `max_capacity`.
-    ///
-    /// # Panics
-    /// This function will panic when called with a `max_capacity` equal to `0`.
-    fn rollout_transactions(transactions: &mut Transactions, rollout_threshold: usize, max_capacity: usize) {
-        assert!(max_capacity > 0);
+    #[tokio::test]
+    async fn test_handle_all() {
+        let expected = sorted_accounts(process_all(Processor::default(), snapshot_transactions()));
+
+        let mut first = snapshot_transactions();
+        let second = first.split_off(5);
+
+        // Test: resume from the state of a handled stream
+        let processor = Processor::default().handle_all(stream::iter(first).map(Ok)).await.unwrap();
+        let restored = Processor::restore(processor.snapshot())
+            .handle_all(stream::iter(second).map(Ok))
+            .await
+            .unwrap();
+        assert_eq!(sorted_accounts(restored), expected);
+
+        // Test: aborted on the first transaction error
+        let transactions = [
+            Transaction::deposit(1, DEPOSIT),
+            Transaction::dispute(2),
+            Transaction::deposit(3, DEPOSIT),
+        ];
+        let result = Processor::default().handle_all_strict(stream::iter(transactions).map(Ok)).await;
+        assert_matches!(result, Err(crate::Error::Process(Error::TransactionNotFound(2))));
+    }
 
-        if transactions.len() >= rollout_threshold {
-            // ideal case: roll out all ended disputes
-            transactions
-                .retain(|_, TransactionStatus(status, _)| !matches!(status, TransactionType::Resolve | TransactionType::Chargeback));
+    #[cfg(feature = "json")]
+    #[test]
+    fn test_snapshot_serde() {
+        let expected = sorted_accounts(process_all(Processor::default(), snapshot_transactions()));
+
+        let mut first = snapshot_transactions();
+        let second = first.split_off(5);
+        let processor = process_all(Processor::default(), first);
+
+        // Test: persist then restore from disk
+        let path = std::env::temp_dir().join(format!("processor-snapshot-{}.json", std::process::id()));
+        std::fs::write(&path, serde_json::to_vec(&processor.snapshot()).unwrap()).unwrap();
+        let snapshot: ProcessorSnapshot = serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let restored = process_all(Processor::restore(snapshot), second);
+        assert_eq!(sorted_accounts(restored), expected);
+
+        // Test: round trip
+        let snapshot = processor.snapshot();
+        let round_trip: ProcessorSnapshot = serde_json::from_str(&serde_json::to_string(&snapshot).unwrap()).unwrap();
+        assert_eq!(round_trip.accounts, snapshot.accounts);
+        assert_eq!(round_trip.transactions.len(), snapshot.transactions.len());
+        assert!(round_trip.transactions.iter().all(|(tx, TransactionStatus(t, client, amount))| {
+            let TransactionStatus(expected_t, expected_client, expected_amount) = &snapshot.transactions[tx];
+            (t, client, amount) == (expected_t, expected_client, expected_amount)
+        }));
+        assert_eq!(round_trip.frozen, snapshot.frozen);
+        assert_eq!(round_trip.closed_accounts, snapshot.closed_accounts);
+
+        // Test: restore a snapshot of the first format, ie. accounts and transaction history only
+        #[derive(Serialize)]
+        struct FirstFormat<'a> {
+            accounts: &'a Accounts,
+            transactions: &'a Transactions,
         }
-        while transactions.len() >= max_capacity {
-            // worst case: got no ended dispute, make room for only one entry, presuming arbitrarily the min. transaction ID could be old enough
-            let tx = *transactions.keys().min().unwrap();
-            let transaction_status = transactions.remove(&tx).unwrap();
 
-            tracing::warn!("Transaction dropped: '{tx}' ({transaction_status:?}).");
-        }
+        let json = serde_json::to_string(&FirstFormat {
+            accounts: &snapshot.accounts,
+            transactions: &snapshot.transactions,
+        })
+        .unwrap();
+        let first_format: ProcessorSnapshot = serde_json::from_str(&json).unwrap();
+        assert_eq!(first_format.accounts, snapshot.accounts);
+        assert!(first_format.dispute_timestamps.is_empty() && !first_format.frozen && first_format.closed_accounts.is_empty());
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[test]
+    fn test_process_transaction_overdraft() {
+        let mut processor = Processor::default().with_overdraft_limit(WITHDRAWAL);
 
-    use assert_matches::assert_matches;
+        processor.process_transaction(Transaction::deposit(1, DISPUTED)).unwrap();
 
-    #[test]
-    #[ignore = "not an actual test"]
-    fn test_size_of() {
-        fn print_size_of<T>() {
-            println!("{}: {}", std::any::type_name::<T>(), size_of::<T>());
-        }
+        // Test: withdraw within overdraft limit
+        processor
+            .process_transaction(Transaction::withdrawal(2, DISPUTED + DISPUTED))
+            .unwrap();
+        assert_eq!(processor.accounts[&0].available, Balance::ZERO - DISPUTED);
 
-        print_size_of::<Error>();
-        print_size_of::<AccountStatus>();
-        print_size_of::<TransactionStatus>();
-        print_size_of::<TransactionType>();
+        processor
+            .process_transaction(Transaction::withdrawal(3, WITHDRAWAL - DISPUTED))
+            .unwrap();
+        assert_eq!(processor.accounts[&0].available, Balance::ZERO - WITHDRAWAL);
 
-        println!(
-            "Default reserved min. size: {} bytes",
-            DEFAULT_TRANSACTION_CAPACITY * (size_of::<TransactionID>() + size_of::<TransactionStatus>())
+        // Test: withdraw beyond overdraft limit
+        assert_matches!(
+            processor.process_transaction(Transaction::withdrawal(4, Amount::raw(1))),
+            Err(Error::NotEnoughFunds(4, 0))
         );
+        assert_eq!(processor.accounts[&0].available, Balance::ZERO - WITHDRAWAL);
 
-        println!(
-            "Max. size: {} bytes",
-            MAX_TRANSACTION_CAPACITY * (size_of::<TransactionID>() + size_of::<TransactionStatus>())
+        // Test: no overdraft by default
+        let mut processor = Processor::default();
+        processor.process_transaction(Transaction::deposit(1, DISPUTED)).unwrap();
+        assert_matches!(
+            processor.process_transaction(Transaction::withdrawal(2, DISPUTED + Amount::raw(1))),
+            Err(Error::NotEnoughFunds(2, 0))
         );
     }
 
-    const DEPOSIT: Amount = Amount::raw(50000);
-    const WITHDRAWAL: Amount = Amount::raw(20000);
-    const DISPUTED: Amount = Amount::raw(10000);
-    const AVAILABLE: Amount = Amount::raw(DEPOSIT.into_raw() - WITHDRAWAL.into_raw() + DISPUTED.into_raw());
-
     #[test]
-    fn test_rollout_transactions() {
-        let mut transactions = Transactions::from_iter([
-            (1, TransactionStatus(TransactionType::Deposit, Amount::MIN)),
-            (2, TransactionStatus(TransactionType::Withdrawal, Amount::MIN)),
-            (3, TransactionStatus(TransactionType::Dispute, Amount::MIN)),
-            (4, TransactionStatus(TransactionType::Resolve, Amount::MIN)),
-            (5, TransactionStatus(TransactionType::Chargeback, Amount::MIN)),
-        ]);
-
-        Processor::rollout_transactions(&mut transactions, 6, 6);
-        assert!(transactions.len() == 5);
+    fn test_held_high_water() {
+        let mut processor = Processor::default().with_held_high_water(true);
+
+        for transaction in [
+            Transaction::deposit(1, DEPOSIT).with_client(1),
+            Transaction::deposit(2, DISPUTED).with_client(1),
+            Transaction::deposit(3, DEPOSIT).with_client(2),
+            Transaction::dispute(1).with_client(1),
+            Transaction::dispute(2).with_client(1),
+            Transaction::resolve(1).with_client(1),
+        ] {
+            processor.process_transaction(transaction).unwrap();
+        }
 
-        Processor::rollout_transactions(&mut transactions, 5, 6);
-        assert!(transactions.len() == 3 && [1, 2, 3].iter().all(|id| transactions.contains_key(id)));
+        // Test: peak held funds, not current ones
+        assert_eq!(processor.accounts[&1].held, DISPUTED.into());
+        assert_eq!(processor.held_high_water(1), Some(DEPOSIT + DISPUTED));
 
-        Processor::rollout_transactions(&mut transactions, 0, 6);
-        assert!(transactions.len() == 3);
+        processor.process_transaction(Transaction::resolve(2).with_client(1)).unwrap();
+        assert_eq!(processor.accounts[&1].held, Balance::ZERO);
+        assert_eq!(processor.held_high_water(1), Some(DEPOSIT + DISPUTED));
 
-        Processor::rollout_transactions(&mut transactions, 0, 3);
-        assert!(transactions.len() == 2 && !transactions.contains_key(&1));
+        // Test: client never holding funds
+        assert_eq!(processor.held_high_water(2), None);
 
-        Processor::rollout_transactions(&mut transactions, 0, 1);
-        assert!(transactions.is_empty());
+        // Test: not tracked by default
+        let mut processor = Processor::default();
+        processor
+            .process_transaction(Transaction::deposit(1, DEPOSIT).with_client(1))
+            .unwrap();
+        processor.process_transaction(Transaction::dispute(1).with_client(1)).unwrap();
+        assert_eq!(processor.held_high_water(1), None);
     }
 
     #[test]
-    fn test_register_transaction() {
-        let mut transactions = Transactions::default();
-        let mut account_status = AccountStatus::default();
-
-        let transaction = Transaction::deposit(1, DEPOSIT);
-        Processor::register_transaction(&mut transactions, transaction, &mut account_status).unwrap();
-        assert_eq!(account_status, AccountStatus::from(DEPOSIT));
+    fn test_process_transaction_unexpected_amount() {
+        let with_amount = |transaction: Transaction| Transaction {
+            amount: Some(DISPUTED),
+            ..transaction
+        };
 
-        let transaction = Transaction::withdrawal(2, WITHDRAWAL);
-        Processor::register_transaction(&mut transactions, transaction, &mut account_status).unwrap();
-        assert_eq!(account_status, AccountStatus::from(DEPOSIT - WITHDRAWAL));
+        let mut processor = Processor::default().with_reject_unexpected_amount(true);
+        processor.process_transaction(Transaction::deposit(1, DEPOSIT)).unwrap();
 
-        let ref_account_status = account_status.clone();
+        // Test: reject a dispute with an amount
+        assert_matches!(
+            processor.process_transaction(with_amount(Transaction::dispute(1))),
+            Err(Error::UnexpectedAmount(1))
+        );
+        assert_matches!(
+            processor.preview(&with_amount(Transaction::dispute(1))),
+            Err(Error::UnexpectedAmount(1))
+        );
+        assert_eq!(processor.accounts[&0], AccountStatus::from(DEPOSIT));
 
-        // Test: existing transaction
-        let transaction = Transaction::deposit(2, Default::default());
-        let err = Processor::register_transaction(&mut transactions, transaction, &mut account_status).unwrap_err();
-        assert_matches!(err, Error::TransactionAlreadyExists(2));
-        assert_eq!(account_status, ref_account_status);
+        // Test: accept a dispute without amount
+        processor.process_transaction(Transaction::dispute(1)).unwrap();
+        assert_eq!(processor.accounts[&0], AccountStatus::from(Amount::MIN).held(DEPOSIT));
 
-        // Test: register anything else than `Deposit` or `Withdrawal`
-        for transaction_type in [TransactionType::Dispute, TransactionType::Resolve, TransactionType::Chargeback] {
-            let transaction = Transaction::new(transaction_type, 3, Default::default());
-            let err = Processor::register_transaction(&mut transactions, transaction, &mut account_status).unwrap_err();
-            assert_matches!(err, Error::OperationNotSupported(3, None, t) if t == transaction_type);
-            assert_eq!(account_status, ref_account_status);
+        for transaction in [Transaction::resolve(1), Transaction::chargeback(1), Transaction::close_client(1)] {
+            assert_matches!(
+                processor.process_transaction(with_amount(transaction)),
+                Err(Error::UnexpectedAmount(1))
+            );
         }
-    }
-
-    fn assert_dispute_not_supported(
-        transaction_id: TransactionID,
-        transaction_types: &[TransactionType],
-        transactions: &mut Transactions,
-        account_status: &mut AccountStatus,
-    ) {
-        let not_supported = [TransactionType::Deposit, TransactionType::Withdrawal];
 
-        let ref_account_status = account_status.clone();
-
-        for transaction_type in not_supported.iter().chain(transaction_types) {
-            let err = Processor::dispute_transaction(transactions, transaction_id, *transaction_type, account_status).unwrap_err();
-            assert_matches!(err, Error::OperationNotSupported(id, Some(_), t) if id == transaction_id && t == *transaction_type);
-            assert_eq!(*account_status, ref_account_status);
-        }
+        // Test: amount ignored by default
+        let mut processor = Processor::default();
+        processor.process_transaction(Transaction::deposit(1, DEPOSIT)).unwrap();
+        processor.process_transaction(with_amount(Transaction::dispute(1))).unwrap();
+        assert_eq!(processor.accounts[&0], AccountStatus::from(Amount::MIN).held(DEPOSIT));
     }
 
     #[test]
-    fn test_dispute_transaction_failure() {
-        let mut transactions = Transactions::from_iter([
-            (1, TransactionStatus(TransactionType::Deposit, DEPOSIT)),
-            (2, TransactionStatus(TransactionType::Withdrawal, WITHDRAWAL)),
-            (3, TransactionStatus(TransactionType::Dispute, DISPUTED)),
-        ]);
-        let mut account_status = AccountStatus::from(DEPOSIT - WITHDRAWAL);
+    fn test_process_transaction_zero_amount() {
+        let mut processor = Processor::default();
 
-        // Test: dispute a `Deposit`
-        assert_dispute_not_supported(
-            1,
-            &[TransactionType::Resolve, TransactionType::Chargeback],
-            &mut transactions,
-            &mut account_status,
+        // Test: reject zero or missing amounts without any account side effect
+        assert_matches!(
+            processor.process_transaction(Transaction::deposit(1, Amount::MIN).with_client(1)),
+            Err(Error::ZeroAmount(1))
         );
-
-        // Test: dispute a `Withdrawal`
-        assert_dispute_not_supported(
-            2,
-            &[TransactionType::Dispute, TransactionType::Resolve, TransactionType::Chargeback],
-            &mut transactions,
-            &mut account_status,
+        assert_matches!(
+            processor.process_transaction(Transaction::withdrawal(2, Amount::MIN).with_client(1)),
+            Err(Error::ZeroAmount(2))
         );
+        assert_matches!(
+            processor.process_transaction(Transaction::new(TransactionType::Deposit, 3, None).with_client(1)),
+            Err(Error::MissingAmount(3))
+        );
+        assert!(processor.accounts.is_empty());
+        assert!(processor.transactions.is_empty());
 
-        // Test: dispute a `Dispute`
-        assert_dispute_not_supported(3, &[TransactionType::Dispute], &mut transactions, &mut account_status);
-
-        // Test: not existing transaction
-        let err = Processor::dispute_transaction(&mut transactions, 42, TransactionType::Deposit, &mut account_status).unwrap_err();
-        assert_matches!(err, Error::TransactionNotFound(42));
-        assert_eq!(account_status, AccountStatus::from(DEPOSIT - WITHDRAWAL));
+        // Test: missing amount detected before an existing transaction
+        processor
+            .process_transaction(Transaction::deposit(1, DEPOSIT).with_client(1))
+            .unwrap();
+        assert_matches!(
+            processor.process_transaction(Transaction::new(TransactionType::Withdrawal, 1, None).with_client(1)),
+            Err(Error::MissingAmount(1))
+        );
+        assert_matches!(
+            processor.process_transaction(Transaction::withdrawal(1, Amount::MIN).with_client(2)),
+            Err(Error::ZeroAmount(1))
+        );
+        assert_eq!(processor.accounts.len(), 1);
+        assert_eq!(processor.accounts[&1], AccountStatus::from(DEPOSIT));
     }
 
     #[test]
-    fn test_dispute_transaction_resolve() {
-        let mut transactions = Transactions::from_iter([
-            (1, TransactionStatus(TransactionType::Deposit, DEPOSIT)),
-            (2, TransactionStatus(TransactionType::Deposit, DISPUTED)),
-        ]);
-        let mut account_status = AccountStatus::from(DEPOSIT + DISPUTED);
+    fn test_process_transaction_client_mismatch() {
+        let mut processor = Processor::default();
 
-        Processor::dispute_transaction(&mut transactions, 2, TransactionType::Dispute, &mut account_status).unwrap();
-        assert_eq!(account_status, AccountStatus::from(DEPOSIT).held(DISPUTED));
+        processor
+            .process_transaction(Transaction::deposit(1, DEPOSIT).with_client(1))
+            .unwrap();
+        processor
+            .process_transaction(Transaction::deposit(2, DISPUTED).with_client(2))
+            .unwrap();
 
-        Processor::dispute_transaction(&mut transactions, 2, TransactionType::Resolve, &mut account_status).unwrap();
-        assert_eq!(account_status, AccountStatus::from(DEPOSIT + DISPUTED));
+        // Test: dispute another client's transaction
+        for transaction in [Transaction::dispute(1), Transaction::resolve(1), Transaction::chargeback(1)] {
+            assert_matches!(
+                processor.process_transaction(transaction.with_client(2)),
+                Err(Error::ClientMismatch(1, 1, 2))
+            );
+        }
+        assert_eq!(processor.accounts[&1], AccountStatus::from(DEPOSIT));
+        assert_eq!(processor.accounts[&2], AccountStatus::from(DISPUTED));
 
-        assert_dispute_not_supported(
-            2,
-            &[TransactionType::Dispute, TransactionType::Chargeback],
-            &mut transactions,
-            &mut account_status,
+        // Test: dispute its own transaction
+        processor.process_transaction(Transaction::dispute(1).with_client(1)).unwrap();
+        assert_eq!(processor.accounts[&1], AccountStatus::from(Amount::MIN).held(DEPOSIT));
+        assert_matches!(
+            processor.process_transaction(Transaction::chargeback(1).with_client(2)),
+            Err(Error::ClientMismatch(1, 1, 2))
         );
+        processor.process_transaction(Transaction::resolve(1).with_client(1)).unwrap();
+        assert_eq!(processor.accounts[&1], AccountStatus::from(DEPOSIT));
     }
 
     #[test]
-    fn test_dispute_transaction_chargeback() {
-        let mut transactions = Transactions::from_iter([
-            (1, TransactionStatus(TransactionType::Deposit, DEPOSIT)),
-            (2, TransactionStatus(TransactionType::Deposit, DISPUTED)),
-        ]);
-        let mut account_status = AccountStatus::from(DEPOSIT + DISPUTED);
+    fn test_with_hasher() {
+        use std::collections::hash_map::DefaultHasher;
+        use std::hash::BuildHasherDefault;
+
+        let processor = || {
+            let mut processor = Processor::with_hasher(BuildHasherDefault::<DefaultHasher>::default());
+            for client in 1..=100 {
+                processor
+                    .process_transaction(Transaction::deposit(client.into(), Amount::new(1, 0)).with_client(client))
+                    .unwrap();
+            }
+            processor
+        };
 
-        Processor::dispute_transaction(&mut transactions, 2, TransactionType::Dispute, &mut account_status).unwrap();
-        assert_eq!(account_status, AccountStatus::from(DEPOSIT).held(DISPUTED));
+        // Test: iteration order is stable across processors with a deterministic hasher
+        let clients = |processor: &Processor<_>| processor.accounts().map(|account| account.client).collect::<Vec<_>>();
+        assert_eq!(clients(&processor()), clients(&processor()));
 
-        Processor::dispute_transaction(&mut transactions, 2, TransactionType::Chargeback, &mut account_status).unwrap();
-        assert_eq!(account_status, AccountStatus::from(DEPOSIT).locked());
+        let records = |processor: Processor<_>| processor.into_records().map(|account| account.client).collect::<Vec<_>>();
+        assert_eq!(records(processor()), records(processor()));
+    }
 
-        assert_dispute_not_supported(
-            2,
-            &[TransactionType::Dispute, TransactionType::Resolve, TransactionType::Chargeback],
-            &mut transactions,
-            &mut account_status,
+    #[test]
+    fn test_outcome_log() {
+        let transactions = [
+            Transaction::deposit(1, DEPOSIT),
+            Transaction::withdrawal(2, DEPOSIT + DISPUTED),
+            Transaction::deposit(1, DISPUTED),
+            Transaction::dispute(1),
+            Transaction::resolve(3),
+            Transaction::chargeback(1),
+        ];
+
+        let processor = process_all(Processor::default(), transactions.to_vec());
+        assert!(processor.outcomes().iter().next().is_none());
+
+        let processor = process_all(Processor::default().with_outcome_log(true), transactions.to_vec());
+
+        let golden: OutcomeLog = [
+            (1, Ok(())),
+            (2, Err("not-enough-funds")),
+            (1, Err("transaction-already-exists")),
+            (1, Ok(())),
+            (3, Err("transaction-not-found")),
+            (1, Ok(())),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(golden.diff(processor.outcomes()), []);
+
+        // Test: diff against a changed behavior, ie. an overdraft and a missing transaction
+        let processor = process_all(
+            Processor::default().with_outcome_log(true).with_overdraft_limit(DISPUTED),
+            transactions[..5].to_vec(),
         );
+
+        assert_eq!(
+            golden.diff(processor.outcomes()),
+            [
+                OutcomeDiff {
+                    position: 1,
+                    left: Some((2, Err("not-enough-funds"))),
+                    right: Some((2, Ok(())))
+                },
+                OutcomeDiff {
+                    position: 5,
+                    left: Some((1, Ok(()))),
+                    right: None
+                },
+            ]
+        );
+        assert_eq!(processor.outcomes().diff(&golden)[1].right, Some((1, Ok(()))));
     }
 
     #[test]
-    fn test_process_transaction_ok() {
-        let mut processor = Processor::default();
+    fn test_reject_log() {
+        let transactions = vec![
+            Transaction::deposit(1, DEPOSIT),
+            Transaction::withdrawal(2, DEPOSIT + DISPUTED),
+            Transaction::dispute(3),
+            Transaction::dispute(1),
+        ];
+
+        let mut processor = process_all(Processor::default(), transactions.clone());
+        assert!(processor.take_rejects().is_empty());
+
+        let mut processor = process_all(Processor::default().with_reject_log(true), transactions);
+        let rejects = processor.take_rejects();
+        assert_eq!(rejects.iter().map(|(transaction, _)| transaction.tx).collect::<Vec<_>>(), [2, 3]);
+        assert_matches!(rejects[..], [(_, Error::NotEnoughFunds(2, 0)), (_, Error::TransactionNotFound(3))]);
+
+        // Test: rejects taken once
+        assert!(processor.take_rejects().is_empty());
+    }
 
-        processor.process_transaction(Transaction::deposit(1, DEPOSIT)).unwrap();
-        assert_eq!(processor.accounts[&0], AccountStatus::from(DEPOSIT));
+    #[test]
+    fn test_clear() {
+        let mut processor = Processor::default().with_suppress_empty(true);
 
+        for transaction in [
+            Transaction::deposit(1, DEPOSIT).with_client(1),
+            Transaction::deposit(2, DISPUTED).with_client(2),
+            Transaction::dispute(2).with_client(2),
+        ] {
+            processor.process_transaction(transaction).unwrap();
+        }
+        let capacity = processor.transactions.capacity();
+
+        // Test: nothing left from the first batch but the configuration & capacity
+        processor.clear();
+        assert!(processor.accounts.is_empty() && processor.transactions.is_empty());
+        assert_eq!(processor.metrics(), &Metrics::default());
+        assert_eq!(processor.transactions.capacity(), capacity);
+
+        for transaction in [
+            Transaction::deposit(4, WITHDRAWAL).with_client(2),
+            Transaction::deposit(5, DISPUTED).with_client(3),
+            Transaction::withdrawal(6, DISPUTED).with_client(3),
+        ] {
+            processor.process_transaction(transaction).unwrap();
+        }
         assert_matches!(
-            processor.process_transaction(Transaction::deposit(1, DEPOSIT)),
-            Err(Error::TransactionAlreadyExists(1))
+            processor.process_transaction(Transaction::resolve(2).with_client(2)),
+            Err(Error::TransactionNotFound(2))
         );
 
-        assert_matches!(
-            processor.process_transaction(Transaction::dispute(42)),
-            Err(Error::TransactionNotFound(42))
+        assert_eq!(sorted_accounts(processor), [(2, AccountStatus::from(WITHDRAWAL))]);
+    }
+
+    #[tokio::test]
+    #[tracing_test::traced_test]
+    async fn test_process_logs_ignored_transaction() {
+        let transactions = [
+            Transaction::deposit(1, WITHDRAWAL).with_client(3),
+            Transaction::withdrawal(2, DEPOSIT).with_client(3),
+        ];
+
+        let _: Vec<_> = Processor::process(stream::iter(transactions).map(Ok)).try_collect().await.unwrap();
+
+        // Test: the whole rejected transaction is attached to the event
+        assert!(logs_contain(
+            r#"Transaction ignored: not enough funds to operate transaction '2' for client '3'. code="not-enough-funds" type=Withdrawal client=3 tx=2 amount=5"#
+        ));
+    }
+
+    #[test]
+    fn test_validate() {
+        let mut processor = Processor::default();
+        processor
+            .process_transaction(Transaction::deposit(1, DEPOSIT).with_client(1))
+            .unwrap();
+
+        let validate = |transactions: Vec<Transaction>| -> Vec<_> {
+            futures::executor::block_on_stream(Box::pin(processor.validate(stream::iter(transactions).map(Ok))))
+                .map(|validity| validity.unwrap().map_err(|err| err.to_string()))
+                .collect()
+        };
+
+        // Test: all transactions valid
+        let validity = validate(vec![
+            Transaction::deposit(2, DISPUTED).with_client(2),
+            Transaction::dispute(2).with_client(2),
+            Transaction::withdrawal(3, WITHDRAWAL).with_client(1),
+        ]);
+        assert_eq!(validity, [Ok(()), Ok(()), Ok(())]);
+
+        // Test: every invalid transaction reported, without short-circuiting
+        let validity = validate(vec![
+            Transaction::dispute(2).with_client(2),
+            Transaction::deposit(2, DISPUTED).with_client(2),
+            Transaction::withdrawal(3, DEPOSIT + DEPOSIT).with_client(1),
+            Transaction::resolve(2).with_client(2),
+        ]);
+        assert_eq!(
+            validity,
+            [
+                Err(Error::TransactionNotFound(2).to_string()),
+                Ok(()),
+                Err(Error::NotEnoughFunds(3, 1).to_string()),
+                Err(Error::OperationNotSupported(2, Some(TransactionType::Deposit), TransactionType::Resolve).to_string()),
+            ]
         );
 
-        processor.process_transaction(Transaction::deposit(2, DISPUTED)).unwrap();
-        assert_eq!(processor.accounts[&0], AccountStatus::from(DEPOSIT + DISPUTED));
+        // Test: stream errors kept apart from invalid transactions
+        let stream = stream::iter([
+            Err(crate::Error::Io(std::io::ErrorKind::InvalidData.into())),
+            Ok(Transaction::dispute(2).with_client(2)),
+        ]);
+        let validity: Vec<_> = futures::executor::block_on_stream(Box::pin(processor.validate(stream))).collect();
+        assert_matches!(validity[..], [Err(crate::Error::Io(_)), Ok(Err(Error::TransactionNotFound(2)))]);
 
-        processor.process_transaction(Transaction::withdrawal(5, WITHDRAWAL)).unwrap();
-        assert_eq!(processor.accounts[&0], AccountStatus::from(AVAILABLE));
+        // Test: the processor itself left untouched
+        assert_eq!(sorted_accounts(processor), [(1, AccountStatus::from(DEPOSIT))]);
+    }
 
-        processor.process_transaction(Transaction::dispute(2)).unwrap();
-        assert_eq!(processor.accounts[&0], AccountStatus::from(DEPOSIT - WITHDRAWAL).held(DISPUTED));
+    #[test]
+    fn test_lock_policy() {
+        let processor = process_all(
+            Processor::default(),
+            vec![
+                Transaction::deposit(1, DEPOSIT).with_client(1),
+                Transaction::deposit(2, DISPUTED).with_client(1),
+                Transaction::dispute(1).with_client(1),
+                Transaction::dispute(2).with_client(1),
+                Transaction::chargeback(1).with_client(1),
+            ],
+        );
+        assert_eq!(processor.accounts[&1], AccountStatus::from(Amount::MIN).held(DISPUTED).locked());
 
-        processor.process_transaction(Transaction::resolve(2)).unwrap();
-        assert_eq!(processor.accounts[&0], AccountStatus::from(AVAILABLE));
+        // Test: strict by default
+        let mut strict = processor.clone();
+        assert_matches!(
+            strict.process_transaction(Transaction::resolve(2).with_client(1)),
+            Err(Error::AccountLocked(2, 1))
+        );
 
-        processor.process_transaction(Transaction::deposit(3, DISPUTED)).unwrap();
-        assert_eq!(processor.accounts[&0], AccountStatus::from(AVAILABLE + DISPUTED));
+        // Test: pending dispute resolved, held funds being released back to available funds
+        let status = processor.accounts[&1].clone();
+        let mut processor = processor.with_lock_policy(LockPolicy::AllowDisputes);
+        assert_matches!(
+            processor.process_transaction(Transaction::deposit(3, DEPOSIT).with_client(1)),
+            Err(Error::AccountLocked(3, 1))
+        );
+        assert_matches!(
+            processor.process_transaction(Transaction::withdrawal(4, DISPUTED).with_client(1)),
+            Err(Error::AccountLocked(4, 1))
+        );
 
-        processor.process_transaction(Transaction::dispute(3)).unwrap();
-        assert_eq!(processor.accounts[&0], AccountStatus::from(AVAILABLE).held(DISPUTED));
+        let mut charged_back = processor.clone();
+        processor.process_transaction(Transaction::resolve(2).with_client(1)).unwrap();
+        assert!(processor.audit_snapshot().disputes.is_empty());
+        assert!(processor.accounts[&1].held < status.held);
+        assert!(processor.accounts[&1].available > status.available);
+        assert_eq!(processor.accounts[&1], AccountStatus::from(DISPUTED).locked());
 
-        processor.process_transaction(Transaction::chargeback(3)).unwrap();
-        assert_eq!(processor.accounts[&0], AccountStatus::from(AVAILABLE).locked());
+        // Test: pending dispute charged back, held funds being removed while the account stays locked
+        charged_back.process_transaction(Transaction::chargeback(2).with_client(1)).unwrap();
+        assert_eq!(charged_back.accounts[&1], AccountStatus::from(Amount::MIN).locked());
 
-        for t in [
-            TransactionType::Deposit,
-            TransactionType::Withdrawal,
-            TransactionType::Dispute,
-            TransactionType::Resolve,
-            TransactionType::Chargeback,
-        ] {
-            assert_matches!(
-                processor.process_transaction(Transaction::new(t, 4, None)),
-                Err(Error::AccountLocked(4, 0))
-            );
-        }
+        // Test: disputes still moving through their states only
+        assert_matches!(
+            processor.process_transaction(Transaction::chargeback(2).with_client(1)),
+            Err(Error::OperationNotSupported(
+                2,
+                Some(TransactionType::Resolve),
+                TransactionType::Chargeback
+            ))
+        );
     }
 
     #[test]
-    #[should_panic(expected = "attempt to subtract with overflow")]
-    fn test_process_transaction_failure() {
+    fn test_audit_total() {
         let mut processor = Processor::default();
+        assert_eq!(processor.audit_total(), Balance::ZERO);
+
+        let funded = Balance::from(DEPOSIT + DISPUTED - WITHDRAWAL);
+        let charged_back = Balance::from(DISPUTED) - WITHDRAWAL;
+
+        for (transaction, total) in [
+            (Transaction::deposit(1, DEPOSIT).with_client(1), Balance::from(DEPOSIT)),
+            (Transaction::deposit(2, DISPUTED).with_client(2), Balance::from(DEPOSIT + DISPUTED)),
+            (Transaction::withdrawal(3, WITHDRAWAL).with_client(1), funded),
+            (Transaction::dispute(1).with_client(1), funded),
+            (Transaction::dispute(2).with_client(2), funded),
+            (Transaction::resolve(2).with_client(2), funded),
+            (Transaction::chargeback(1).with_client(1), charged_back),
+            // rejected transactions
+            (Transaction::deposit(4, DEPOSIT).with_client(1), charged_back),
+            (Transaction::withdrawal(5, DEPOSIT).with_client(2), charged_back),
+        ] {
+            let tx = transaction.tx;
+            let _ = processor.process_transaction(transaction);
+            assert_eq!(processor.audit_total(), total, "transaction {tx}");
+        }
 
-        processor.process_transaction(Transaction::deposit(2, DISPUTED)).unwrap();
-        assert_eq!(processor.accounts[&0], AccountStatus::from(DISPUTED));
+        // Test: saturated sum past the bounds of a balance
+        processor.insert_account(3, AccountStatus::from(Amount::MAX).held(Amount::MAX));
+        assert_eq!(processor.audit_total(), Balance::MAX);
+    }
 
-        assert_matches!(
-            processor.process_transaction(Transaction::withdrawal(5, WITHDRAWAL)),
-            Err(Error::NotEnoughFunds(5, 0))
+    #[test]
+    fn test_clone() {
+        let processor = process_all(
+            Processor::default(),
+            vec![
+                Transaction::deposit(1, DEPOSIT).with_client(1),
+                Transaction::deposit(2, DISPUTED).with_client(1),
+                Transaction::dispute(2).with_client(1),
+            ],
         );
 
-        processor.process_transaction(Transaction::withdrawal(5, DISPUTED)).unwrap();
-        assert_eq!(processor.accounts[&0], AccountStatus::from(Amount::MIN));
+        // Test: the clone diverges without touching the original
+        let branch = process_all(
+            processor.clone(),
+            vec![
+                Transaction::resolve(2).with_client(1),
+                Transaction::withdrawal(3, WITHDRAWAL).with_client(1),
+                Transaction::deposit(4, DEPOSIT).with_client(2),
+            ],
+        );
+        assert_eq!((branch.metrics().deposits, processor.metrics().deposits), (3, 2));
+        assert_eq!(
+            sorted_accounts(branch),
+            [
+                (1, AccountStatus::from(DEPOSIT + DISPUTED - WITHDRAWAL)),
+                (2, AccountStatus::from(DEPOSIT))
+            ]
+        );
 
-        processor.process_transaction(Transaction::dispute(2)).unwrap();
+        let mut status = AccountStatus::from(DEPOSIT + DISPUTED);
+        status.hold(Amount::from(DISPUTED)).unwrap();
+        assert_eq!(sorted_accounts(processor), [(1, status)]);
     }
 }